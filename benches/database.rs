@@ -0,0 +1,67 @@
+//! Per-operation database cost.
+//!
+//! Run with `cargo bench --bench database`.
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use forge::crdt::{Operation, OperationType, Position};
+use forge::storage::{Database, QueryFilter};
+use tempfile::TempDir;
+
+const HISTORY: usize = 5_000;
+
+fn insert(file: &str, offset: usize) -> Operation {
+    Operation::new(
+        file.to_string(),
+        OperationType::Insert {
+            position: Position::new(1, offset + 1, offset, "bench".into(), offset as u64),
+            content: "x".into(),
+            length: 1,
+        },
+        "bench".into(),
+    )
+}
+
+fn open() -> (TempDir, Database) {
+    let temp_dir = TempDir::new().unwrap();
+    let db = Database::new(temp_dir.path()).unwrap();
+    db.initialize().unwrap();
+    (temp_dir, db)
+}
+
+fn bench_store(c: &mut Criterion) {
+    let (_dir, db) = open();
+    let mut offset = 0;
+    c.bench_function("db/store_operation", |b| {
+        b.iter_batched(
+            || {
+                offset += 1;
+                insert("src/main.rs", offset)
+            },
+            |op| db.store_operation(&op).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn bench_queries(c: &mut Criterion) {
+    let (_dir, db) = open();
+    for offset in 0..HISTORY {
+        let file = if offset % 2 == 0 { "src/main.rs" } else { "src/lib.rs" };
+        db.store_operation(&insert(file, offset)).unwrap();
+    }
+
+    c.bench_function("db/get_operations", |b| {
+        b.iter(|| db.get_operations(None, 50).unwrap())
+    });
+    c.bench_function("db/query_operations_by_file", |b| {
+        let filter = QueryFilter {
+            file: Some("src/lib.rs".into()),
+            limit: Some(50),
+            ..Default::default()
+        };
+        b.iter(|| db.query_operations(&filter).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_store, bench_queries);
+criterion_main!(benches);
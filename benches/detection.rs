@@ -0,0 +1,82 @@
+//! Detection latency benchmarks.
+//!
+//! Run with `cargo bench --features bench`.
+
+use std::path::Path;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use forge::watcher::detector::bench;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+fn sample(size: usize, utf8: bool) -> String {
+    let line = if utf8 {
+        "let café = \"naïve 🚀\";\n"
+    } else {
+        "let cafe = \"naive go\";\n"
+    };
+    line.repeat(size / line.len() + 1)
+}
+
+fn edit_in_middle(content: &str) -> String {
+    let mut mid = content.len() / 2;
+    while !content.is_char_boundary(mid) {
+        mid += 1;
+    }
+    let mut edited = content.to_string();
+    edited.insert(mid, 'x');
+    edited
+}
+
+fn rewrite_lines(content: &str) -> String {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| if i % 7 == 0 { "// rewritten" } else { line })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn bench_detection(c: &mut Criterion) {
+    for (label, utf8) in [("ascii", false), ("utf8", true)] {
+        let mut group = c.benchmark_group(format!("detect/{label}"));
+        for size in SIZES {
+            let path = Path::new("/bench/detect.rs");
+            let old = sample(size, utf8);
+            let scenarios = [
+                ("append", format!("{old}fn added() {{}}\n")),
+                ("single_char", edit_in_middle(&old)),
+                ("full_diff", rewrite_lines(&old)),
+            ];
+            for (scenario, new) in scenarios {
+                group.bench_with_input(BenchmarkId::new(scenario, size), &new, |b, new| {
+                    b.iter_batched(
+                        || {
+                            bench::prime(path, &old);
+                            new.clone()
+                        },
+                        |new| bench::detect(path, "bench", new),
+                        BatchSize::SmallInput,
+                    )
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+fn bench_snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_snapshot_fast");
+    for (label, utf8) in [("ascii", false), ("utf8", true)] {
+        for size in SIZES {
+            let content = sample(size, utf8);
+            group.bench_with_input(BenchmarkId::new(label, size), &content, |b, content| {
+                b.iter(|| bench::snapshot(content))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_detection, bench_snapshot);
+criterion_main!(benches);
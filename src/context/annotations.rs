@@ -1,6 +1,6 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use rusqlite::params;
+use rusqlite::{Row, params};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 use uuid::Uuid;
@@ -63,50 +63,51 @@ pub fn store_annotation(db: &Database, annotation: &Annotation) -> Result<()> {
 
 pub fn get_annotations(db: &Database, file: &Path, line: Option<usize>) -> Result<Vec<Annotation>> {
     let conn = db.conn.lock();
+    let file_path = file.display().to_string();
 
-    let query = if let Some(l) = line {
-        format!(
+    let annotations = if let Some(l) = line {
+        let mut stmt = conn.prepare(
             "SELECT id, file_path, anchor_id, line, content, author, created_at, is_ai
              FROM annotations
-             WHERE file_path = '{}' AND line = {}
+             WHERE file_path = ?1 AND line = ?2
              ORDER BY created_at DESC",
-            file.display(),
-            l
-        )
+        )?;
+        stmt.query_map(params![file_path, l as i64], row_to_annotation)?
+            .collect::<Result<Vec<_>, _>>()?
     } else {
-        format!(
+        let mut stmt = conn.prepare(
             "SELECT id, file_path, anchor_id, line, content, author, created_at, is_ai
              FROM annotations
-             WHERE file_path = '{}'
+             WHERE file_path = ?1
              ORDER BY created_at DESC",
-            file.display()
-        )
+        )?;
+        stmt.query_map(params![file_path], row_to_annotation)?
+            .collect::<Result<Vec<_>, _>>()?
     };
 
-    let mut stmt = conn.prepare(&query)?;
-    let annotations = stmt.query_map([], |row| {
-        let id: String = row.get(0)?;
-        let file_path: String = row.get(1)?;
-        let anchor_id: Option<String> = row.get(2)?;
-        let line: i64 = row.get(3)?;
-        let content: String = row.get(4)?;
-        let author: String = row.get(5)?;
-        let created_at: String = row.get(6)?;
-        let is_ai: bool = row.get(7)?;
+    Ok(annotations)
+}
 
-        Ok(Annotation {
-            id: Uuid::parse_str(&id).unwrap(),
-            file_path,
-            anchor_id: anchor_id.as_ref().and_then(|s| Uuid::parse_str(s).ok()),
-            line: line as usize,
-            content,
-            author,
-            created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
-                .unwrap()
-                .into(),
-            is_ai,
-        })
-    })?;
+fn row_to_annotation(row: &Row<'_>) -> rusqlite::Result<Annotation> {
+    let id: String = row.get(0)?;
+    let file_path: String = row.get(1)?;
+    let anchor_id: Option<String> = row.get(2)?;
+    let line: i64 = row.get(3)?;
+    let content: String = row.get(4)?;
+    let author: String = row.get(5)?;
+    let created_at: String = row.get(6)?;
+    let is_ai: bool = row.get(7)?;
 
-    Ok(annotations.collect::<Result<Vec<_>, _>>()?)
+    Ok(Annotation {
+        id: Uuid::parse_str(&id).unwrap(),
+        file_path,
+        anchor_id: anchor_id.as_ref().and_then(|s| Uuid::parse_str(s).ok()),
+        line: line as usize,
+        content,
+        author,
+        created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+            .unwrap()
+            .into(),
+        is_ai,
+    })
 }
@@ -0,0 +1,287 @@
+use anyhow::{Result, bail};
+use uuid::Uuid;
+
+use crate::crdt::{Anchor, Operation, OperationType};
+use crate::storage::{Database, QueryFilter, reconstruct};
+
+/// Character range of the text an anchor points to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+/// Every operation that touched the line `anchor_id` points to, oldest
+/// first. The line is followed backwards through earlier edits to where it
+/// was written and forwards through later ones; if it was later deleted
+/// outright, the history ends with that delete.
+pub fn anchor_history(db: &Database, anchor_id: &Uuid) -> Result<Vec<Operation>> {
+    let Some(anchor) = db.anchor(anchor_id)? else {
+        bail!("no anchor {anchor_id}");
+    };
+    let Some(content) = reconstruct::reconstruct_at(db, &anchor.file_path, anchor.created_at)?
+    else {
+        return Ok(Vec::new());
+    };
+    let origin = line_span(&content, &anchor);
+
+    let filter = QueryFilter {
+        file: Some(anchor.file_path.clone().into()),
+        ..Default::default()
+    };
+    let (before, after): (Vec<_>, Vec<_>) = db
+        .query_operations(&filter)?
+        .into_iter()
+        .partition(|op| op.timestamp <= anchor.created_at);
+
+    let mut history = Vec::new();
+    let mut span = origin;
+    for op in before.into_iter().rev() {
+        match &op.op_type {
+            // The file's creation wrote the line, if nothing after it did
+            OperationType::FileCreate { .. } => {
+                history.push(op);
+                break;
+            }
+            OperationType::FileDelete => break,
+            _ => {}
+        }
+        let Some((at, removed, inserted)) = edit_range(&op) else {
+            continue;
+        };
+        // Undo the edit: what it inserted is removed and vice versa
+        let was_empty = span.start == span.end;
+        if splice(&mut span, at, inserted, removed) {
+            let written_here = !was_empty && span.start == span.end;
+            history.push(op);
+            if written_here {
+                break;
+            }
+        }
+    }
+    history.reverse();
+
+    let mut span = origin;
+    for op in after {
+        if matches!(op.op_type, OperationType::FileDelete) {
+            history.push(op);
+            break;
+        }
+        let Some((at, removed, inserted)) = edit_range(&op) else {
+            continue;
+        };
+        let was_empty = span.start == span.end;
+        if splice(&mut span, at, removed, inserted) {
+            let deleted = !was_empty && span.start == span.end;
+            history.push(op);
+            if deleted {
+                break;
+            }
+        }
+    }
+
+    Ok(history)
+}
+
+/// Characters of the anchor's line, including its newline. Anchors made
+/// from a line and column carry no offset, so the line is found from those.
+fn line_span(content: &str, anchor: &Anchor) -> Span {
+    let line = anchor.position.line.max(1);
+    let mut start = 0;
+    let mut current = 1;
+    let mut chars = content.chars().enumerate();
+    while current < line {
+        match chars.next() {
+            Some((idx, '\n')) => {
+                current += 1;
+                start = idx + 1;
+            }
+            Some(_) => {}
+            None => {
+                let len = content.chars().count();
+                return Span {
+                    start: len,
+                    end: len,
+                };
+            }
+        }
+    }
+    let end = content
+        .chars()
+        .enumerate()
+        .skip(start)
+        .find(|&(_, ch)| ch == '\n')
+        .map_or_else(|| content.chars().count(), |(idx, _)| idx + 1);
+    Span { start, end }
+}
+
+/// Offset, characters removed and characters inserted by a content edit.
+fn edit_range(op: &Operation) -> Option<(usize, usize, usize)> {
+    match &op.op_type {
+        OperationType::Insert {
+            position, content, ..
+        } => Some((position.offset, 0, content.chars().count())),
+        OperationType::Delete { position, length } => Some((position.offset, *length, 0)),
+        OperationType::Replace {
+            position,
+            old_content,
+            new_content,
+        } => Some((
+            position.offset,
+            old_content.chars().count(),
+            new_content.chars().count(),
+        )),
+        _ => None,
+    }
+}
+
+/// Move `span` through an edit that removes `removed` characters at `at` and
+/// inserts `inserted` there. Returns whether the edit touched the span;
+/// text inserted at either end of it counts as part of it.
+fn splice(span: &mut Span, at: usize, removed: usize, inserted: usize) -> bool {
+    let touched = if removed == 0 {
+        span.start <= at && at <= span.end
+    } else {
+        at < span.end && span.start < at + removed
+    };
+
+    let map = |offset: usize| {
+        if offset <= at {
+            offset
+        } else if offset >= at + removed {
+            offset - removed
+        } else {
+            at
+        }
+    };
+    span.start = map(span.start);
+    span.end = map(span.end);
+
+    if at < span.start || (at == span.start && !touched) {
+        span.start += inserted;
+        span.end += inserted;
+    } else if touched && at <= span.end {
+        span.end += inserted;
+    }
+    touched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::Position;
+    use crate::sync::GLOBAL_CLOCK;
+    use chrono::{Duration, Utc};
+    use tempfile::TempDir;
+
+    fn edit(db: &Database, op_type: OperationType, seconds_ago: i64) -> Operation {
+        let mut op = Operation::new("lib.rs".into(), op_type, "actor".into());
+        op.timestamp = Utc::now() - Duration::seconds(seconds_ago);
+        db.store_operation(&op).unwrap();
+        op
+    }
+
+    fn at(offset: usize) -> Position {
+        Position::new(1, 1, offset, "actor".into(), GLOBAL_CLOCK.tick())
+    }
+
+    #[test]
+    fn follows_a_line_back_to_its_creation_and_forward_to_its_delete() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        // "one\ntwo\n" -> "one\ntwo\nthree\n"; the anchor is on "two"
+        let create = edit(
+            &db,
+            OperationType::FileCreate {
+                content: "one\ntwo\n".into(),
+            },
+            60,
+        );
+        let unrelated = edit(
+            &db,
+            OperationType::Insert {
+                position: at(8),
+                content: "three\n".into(),
+                length: 6,
+            },
+            50,
+        );
+        let mut anchor = Anchor::new(
+            "lib.rs".into(),
+            Position::new(2, 1, 0, "actor".into(), 0),
+            None,
+        );
+        anchor.created_at = Utc::now() - Duration::seconds(40);
+        db.store_anchor(&anchor).unwrap();
+
+        // Editing line one shifts "two" without touching it
+        let shift = edit(
+            &db,
+            OperationType::Insert {
+                position: at(0),
+                content: "zero\n".into(),
+                length: 5,
+            },
+            30,
+        );
+        let rename = edit(
+            &db,
+            OperationType::Replace {
+                position: at(5),
+                old_content: "one".into(),
+                new_content: "uno".into(),
+            },
+            25,
+        );
+        let touch = edit(
+            &db,
+            OperationType::Insert {
+                position: at(12),
+                content: "2".into(),
+                length: 1,
+            },
+            20,
+        );
+        let remove = edit(
+            &db,
+            OperationType::Delete {
+                position: at(9),
+                length: 5,
+            },
+            10,
+        );
+        let _after_delete = edit(
+            &db,
+            OperationType::Insert {
+                position: at(9),
+                content: "new\n".into(),
+                length: 4,
+            },
+            5,
+        );
+
+        let history: Vec<Uuid> = anchor_history(&db, &anchor.id)
+            .unwrap()
+            .into_iter()
+            .map(|op| op.id)
+            .collect();
+        assert_eq!(history, vec![create.id, touch.id, remove.id]);
+        for skipped in [unrelated.id, shift.id, rename.id] {
+            assert!(!history.contains(&skipped));
+        }
+    }
+
+    #[test]
+    fn splice_shifts_grows_and_empties_spans() {
+        let mut span = Span { start: 4, end: 8 };
+        assert!(!splice(&mut span, 0, 0, 2));
+        assert_eq!(span, Span { start: 6, end: 10 });
+        assert!(splice(&mut span, 7, 0, 3));
+        assert_eq!(span, Span { start: 6, end: 13 });
+        assert!(!splice(&mut span, 13, 2, 0));
+        assert!(splice(&mut span, 5, 10, 0));
+        assert_eq!(span, Span { start: 5, end: 5 });
+    }
+}
@@ -1,14 +1,16 @@
 pub mod ai_context;
 pub mod annotations;
 pub mod discussions;
+pub mod history;
 
 use anyhow::Result;
 use std::path::Path;
 
 pub use annotations::Annotation;
+pub use history::anchor_history;
 
 use crate::crdt::{Anchor, Position};
-use crate::storage::Database;
+use crate::storage::{Database, ForgeConfig};
 
 pub async fn create_anchor(
     file: &Path,
@@ -19,9 +21,11 @@ pub async fn create_anchor(
     let db = Database::open(".dx/forge")?;
 
     // Load config to get actor_id
-    let config: serde_json::Value =
-        serde_json::from_str(&tokio::fs::read_to_string(".dx/forge/config.json").await?)?;
-    let actor_id = config["actor_id"].as_str().unwrap().to_string();
+    let config = ForgeConfig::load(Path::new(".dx/forge"))?;
+    if config.actor_id.is_empty() {
+        anyhow::bail!("config.json has no actor_id; run `forge init` to create one");
+    }
+    let actor_id = config.actor_id;
 
     let position = Position::new(line, column, 0, actor_id, 0);
     let anchor = Anchor::new(file.display().to_string(), position, message);
@@ -41,11 +45,15 @@ pub async fn annotate(file: &Path, line: usize, message: &str, is_ai: bool) -> R
     Ok(())
 }
 
-pub async fn show_context(file: &Path, line: Option<usize>) -> Result<()> {
+pub async fn show_context(file: &Path, line: Option<usize>, json: bool) -> Result<()> {
     use colored::*;
 
     let db = Database::open(".dx/forge")?;
     let annotations = annotations::get_annotations(&db, file, line)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&annotations)?);
+        return Ok(());
+    }
 
     println!(
         "{}",
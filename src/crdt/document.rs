@@ -2,8 +2,10 @@ use anyhow::Result;
 use automerge::{AutoCommit, ROOT, ReadDoc, transaction::Transactable};
 use parking_lot::RwLock;
 use ropey::Rope;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
+use uuid::Uuid;
 
 use super::operations::{Operation, OperationType, Position};
 
@@ -16,6 +18,16 @@ pub struct CrdtDocument {
     pub rope: Arc<RwLock<Rope>>,
     /// Lamport timestamp for ordering
     pub lamport: Arc<parking_lot::Mutex<u64>>,
+    /// Content the document was created with, the replay base for `apply`
+    base: Arc<str>,
+    /// Operations merged through `apply`, kept in `Operation::order_key` order
+    history: Arc<RwLock<History>>,
+}
+
+#[derive(Default)]
+struct History {
+    ops: Vec<Operation>,
+    ids: HashSet<Uuid>,
 }
 
 #[allow(dead_code)]
@@ -29,7 +41,41 @@ impl CrdtDocument {
             doc: Arc::new(RwLock::new(doc)),
             rope: Arc::new(RwLock::new(Rope::from_str(initial_content))),
             lamport: Arc::new(parking_lot::Mutex::new(0)),
+            base: Arc::from(initial_content),
+            history: Arc::new(RwLock::new(History::default())),
+        }
+    }
+
+    /// Merge an operation using offset-based positions. Operations are
+    /// applied in `Operation::order_key` order regardless of arrival order,
+    /// so peers that have seen the same set of operations converge on the
+    /// same content. Duplicates are ignored.
+    pub fn apply(&self, op: &Operation) -> Result<()> {
+        let mut history = self.history.write();
+        if !history.ids.insert(op.id) {
+            return Ok(());
         }
+
+        let idx = history
+            .ops
+            .partition_point(|existing| existing.order_key() < op.order_key());
+        history.ops.insert(idx, op.clone());
+
+        let mut rope = self.rope.write();
+        if idx + 1 == history.ops.len() {
+            apply_to_rope(&mut rope, op);
+        } else {
+            // Arrived out of order: replay everything from the base content
+            *rope = Rope::from_str(&self.base);
+            for existing in &history.ops {
+                apply_to_rope(&mut rope, existing);
+            }
+        }
+
+        let mut lamport = self.lamport.lock();
+        *lamport = (*lamport).max(op.lamport().unwrap_or(0)) + 1;
+
+        Ok(())
     }
 
     pub fn apply_operation(&self, op: &Operation) -> Result<()> {
@@ -119,3 +165,95 @@ impl CrdtDocument {
         None
     }
 }
+
+/// Apply a single operation to `rope` using its character offset, clamping
+/// offsets that fall outside the current content.
+pub fn apply_to_rope(rope: &mut Rope, op: &Operation) {
+    match &op.op_type {
+        OperationType::FileCreate { content: c } => {
+            *rope = Rope::from_str(c);
+        }
+        OperationType::Insert {
+            position, content, ..
+        } => {
+            let char_idx = clamp_offset(rope, position.offset);
+            rope.insert(char_idx, content);
+        }
+        OperationType::Delete { position, length } => {
+            let start = clamp_offset(rope, position.offset);
+            let end = clamp_offset(rope, start + *length);
+            if start < end {
+                rope.remove(start..end);
+            }
+        }
+        OperationType::Replace {
+            position,
+            old_content,
+            new_content,
+        } => {
+            let start = clamp_offset(rope, position.offset);
+            let end = clamp_offset(rope, start + old_content.chars().count());
+            if start < end {
+                rope.remove(start..end);
+            }
+            rope.insert(start, new_content);
+        }
+        OperationType::FileDelete => {
+            *rope = Rope::new();
+        }
+        OperationType::FileRename { .. } => {
+            // Rename events are handled by resolving the target path.
+        }
+        OperationType::DirCreate | OperationType::DirDelete | OperationType::DirRename { .. } => {
+            // Directories have no content.
+        }
+        OperationType::ChmodChange { .. } => {
+            // Permissions don't affect content.
+        }
+        OperationType::BinaryModify { .. } => {
+            // Binary content isn't text; the last text content stands.
+        }
+        OperationType::SymlinkCreate { .. } | OperationType::SymlinkChange { .. } => {
+            // A symlink has no content of its own, only a target.
+            *rope = Rope::new();
+        }
+    }
+}
+
+fn clamp_offset(rope: &Rope, offset: usize) -> usize {
+    offset.min(rope.len_chars())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_at(offset: usize, content: &str, actor: &str, lamport: u64) -> Operation {
+        Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, offset + 1, offset, actor.to_string(), lamport),
+                content: content.to_string(),
+                length: content.chars().count(),
+            },
+            actor.to_string(),
+        )
+    }
+
+    #[test]
+    fn concurrent_inserts_converge_regardless_of_arrival_order() {
+        let a = insert_at(2, "A", "alice", 7);
+        let b = insert_at(2, "B", "bob", 7);
+
+        let peer_one = CrdtDocument::new(PathBuf::from("a.txt"), "xyz");
+        peer_one.apply(&a).unwrap();
+        peer_one.apply(&b).unwrap();
+
+        let peer_two = CrdtDocument::new(PathBuf::from("a.txt"), "xyz");
+        peer_two.apply(&b).unwrap();
+        peer_two.apply(&a).unwrap();
+        peer_two.apply(&a).unwrap();
+
+        assert_eq!(peer_one.get_content(), peer_two.get_content());
+    }
+}
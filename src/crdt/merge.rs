@@ -0,0 +1,306 @@
+use ropey::Rope;
+use similar::{DiffTag, TextDiff};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::ops::Range;
+
+use uuid::Uuid;
+
+use super::Operation;
+use super::document::apply_to_rope;
+
+/// Outcome of a three-way merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeResult {
+    /// Every edit applied cleanly; the merged document.
+    Clean(String),
+    /// Both sides changed the same lines differently.
+    Conflicts(Vec<ConflictHunk>),
+}
+
+/// Lines both branches changed, with each side's version of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictHunk {
+    /// Lines of the base the hunk replaces, 1-based and end-exclusive. Empty
+    /// when both sides inserted at the same place.
+    pub lines: Range<usize>,
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+/// The latest operation both histories contain, to use as the base of a
+/// three-way merge. A history contains its operations and every parent they
+/// name, even if the parent itself isn't in the slice. Of the operations in
+/// both, those that are an ancestor of another one are skipped; if more than
+/// one is left (criss-cross histories), the last in replay order wins.
+/// Returns `None` when the histories share nothing.
+#[allow(dead_code)]
+pub fn merge_base(a: &[Operation], b: &[Operation]) -> Option<Uuid> {
+    let known = |history: &[Operation]| -> HashSet<Uuid> {
+        history
+            .iter()
+            .flat_map(|op| iter::once(op.id).chain(op.parent_ops.iter().copied()))
+            .collect()
+    };
+    let common: HashSet<Uuid> = known(a).intersection(&known(b)).copied().collect();
+
+    let by_id: HashMap<Uuid, &Operation> = a.iter().chain(b).map(|op| (op.id, op)).collect();
+    let parents = |id: &Uuid| {
+        by_id
+            .get(id)
+            .map(|op| op.parent_ops.clone())
+            .unwrap_or_default()
+    };
+
+    // Every ancestor of a common operation is older than it
+    let mut ancestors = HashSet::new();
+    for id in &common {
+        let mut stack = parents(id);
+        while let Some(parent) = stack.pop() {
+            if ancestors.insert(parent) {
+                stack.extend(parents(&parent));
+            }
+        }
+    }
+
+    common
+        .into_iter()
+        .filter(|id| !ancestors.contains(id))
+        .max_by_key(|id| (by_id.get(id).map(|op| op.order_key()), *id))
+}
+
+/// Merge two branches that diverged from `base`, the document's content at
+/// their merge base. `ours` and `theirs` are each branch's operations since
+/// then; each is replayed onto `base` in replay order and the results are
+/// merged line by line. Edits to different lines combine, identical edits on
+/// both sides are taken once, and anything else touching the same or
+/// adjacent lines is a conflict.
+#[allow(dead_code)]
+pub fn three_way_merge(base: &str, ours: &[Operation], theirs: &[Operation]) -> MergeResult {
+    let ours = replay(base, ours);
+    let theirs = replay(base, theirs);
+
+    let ours_diff = TextDiff::from_lines(base, ours.as_str());
+    let theirs_diff = TextDiff::from_lines(base, theirs.as_str());
+    let base_lines = ours_diff.old_slices();
+    let sides = [
+        (changes(&ours_diff), ours_diff.new_slices()),
+        (changes(&theirs_diff), theirs_diff.new_slices()),
+    ];
+
+    // Changes from both sides by where they start in the base
+    let mut all: Vec<(usize, &Change)> = sides
+        .iter()
+        .enumerate()
+        .flat_map(|(side, (changes, _))| changes.iter().map(move |change| (side, change)))
+        .collect();
+    all.sort_by_key(|(side, change)| (change.base.start, change.base.end, *side));
+
+    let mut merged = String::new();
+    let mut conflicts = Vec::new();
+    let mut pos = 0;
+    let mut idx = 0;
+    while idx < all.len() {
+        // Grow the region while the next change overlaps or touches it
+        let mut region = all[idx].1.base.clone();
+        let mut end = idx + 1;
+        while end < all.len() && all[end].1.base.start <= region.end {
+            region.end = region.end.max(all[end].1.base.end);
+            end += 1;
+        }
+        let group = &all[idx..end];
+
+        merged.extend(base_lines[pos..region.start].iter().copied());
+        let versions: Vec<String> = sides
+            .iter()
+            .enumerate()
+            .map(|(side, (_, lines))| {
+                let side_changes = group
+                    .iter()
+                    .filter(|(changed_by, _)| *changed_by == side)
+                    .map(|(_, change)| *change);
+                apply_changes(base_lines, lines, region.clone(), side_changes)
+            })
+            .collect();
+        let changed_by_both =
+            group.iter().any(|(side, _)| *side == 0) && group.iter().any(|(side, _)| *side == 1);
+        if !changed_by_both || versions[0] == versions[1] {
+            let taken = if group.iter().any(|(side, _)| *side == 0) {
+                0
+            } else {
+                1
+            };
+            merged.push_str(&versions[taken]);
+        } else {
+            conflicts.push(ConflictHunk {
+                lines: region.start + 1..region.end + 1,
+                base: base_lines[region.clone()].concat(),
+                ours: versions[0].clone(),
+                theirs: versions[1].clone(),
+            });
+        }
+
+        pos = region.end;
+        idx = end;
+    }
+    merged.extend(base_lines[pos..].iter().copied());
+
+    if conflicts.is_empty() {
+        MergeResult::Clean(merged)
+    } else {
+        MergeResult::Conflicts(conflicts)
+    }
+}
+
+/// A run of base lines one side replaced with lines of its own.
+struct Change {
+    base: Range<usize>,
+    side: Range<usize>,
+}
+
+fn replay(base: &str, operations: &[Operation]) -> String {
+    let mut operations: Vec<&Operation> = operations.iter().collect();
+    operations.sort_by(|a, b| a.order_key().cmp(&b.order_key()));
+    let mut rope = Rope::from_str(base);
+    for op in operations {
+        apply_to_rope(&mut rope, op);
+    }
+    rope.to_string()
+}
+
+fn changes(diff: &TextDiff<'_, '_, '_, str>) -> Vec<Change> {
+    let mut changes: Vec<Change> = Vec::new();
+    for op in diff.ops() {
+        let (tag, base, side) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            continue;
+        }
+        // A delete next to an insert is one replacement
+        match changes.last_mut() {
+            Some(last) if last.base.end == base.start && last.side.end == side.start => {
+                last.base.end = base.end;
+                last.side.end = side.end;
+            }
+            _ => changes.push(Change { base, side }),
+        }
+    }
+    changes
+}
+
+/// One side's version of the base lines in `region`.
+fn apply_changes<'a>(
+    base_lines: &[&str],
+    side_lines: &[&str],
+    region: Range<usize>,
+    changes: impl Iterator<Item = &'a Change>,
+) -> String {
+    let mut out = String::new();
+    let mut pos = region.start;
+    for change in changes {
+        out.extend(base_lines[pos..change.base.start].iter().copied());
+        out.extend(side_lines[change.side.clone()].iter().copied());
+        pos = change.base.end;
+    }
+    out.extend(base_lines[pos..region.end].iter().copied());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{OperationType, Position};
+    use crate::sync::GLOBAL_CLOCK;
+
+    fn op(parents: &[&Operation]) -> Operation {
+        Operation::new(
+            "a.txt".into(),
+            OperationType::FileCreate {
+                content: String::new(),
+            },
+            "actor".into(),
+        )
+        .with_parents(parents.iter().map(|parent| parent.id).collect())
+    }
+
+    #[test]
+    fn finds_where_a_forked_history_diverged() {
+        let root = op(&[]);
+        let shared = op(&[&root]);
+        let ours = op(&[&shared]);
+        let ours_next = op(&[&ours]);
+        let theirs = op(&[&shared]);
+
+        let a = [root.clone(), shared.clone(), ours, ours_next];
+        let b = [root.clone(), shared.clone(), theirs.clone()];
+        assert_eq!(merge_base(&a, &b), Some(shared.id));
+        assert_eq!(merge_base(&b, &a), Some(shared.id));
+
+        // Only the tips were exchanged; their parents still tie them together
+        assert_eq!(merge_base(&a[3..], &[theirs]), None);
+        assert_eq!(merge_base(&a[2..], &b[2..]), Some(shared.id));
+    }
+
+    fn replace(base: &str, old: &str, new: &str) -> Operation {
+        let offset = base[..base.find(old).unwrap()].chars().count();
+        Operation::new(
+            "a.txt".into(),
+            OperationType::Replace {
+                position: Position::new(1, 1, offset, "actor".into(), GLOBAL_CLOCK.tick()),
+                old_content: old.into(),
+                new_content: new.into(),
+            },
+            "actor".into(),
+        )
+    }
+
+    const BASE: &str = "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n";
+
+    #[test]
+    fn disjoint_edits_merge_cleanly() {
+        let ours = [replace(BASE, "a()", "alpha()")];
+        let theirs = [
+            replace(BASE, "d()", "delta()"),
+            replace(BASE, "fn c() {}\n", ""),
+        ];
+        assert_eq!(
+            three_way_merge(BASE, &ours, &theirs),
+            MergeResult::Clean("fn alpha() {}\nfn b() {}\nfn delta() {}\n".into())
+        );
+
+        // The same edit on both sides is taken once
+        let same = [replace(BASE, "b()", "beta()")];
+        assert_eq!(
+            three_way_merge(BASE, &same, &same.clone()),
+            MergeResult::Clean("fn a() {}\nfn beta() {}\nfn c() {}\nfn d() {}\n".into())
+        );
+    }
+
+    #[test]
+    fn overlapping_edits_conflict() {
+        let ours = [
+            replace(BASE, "b()", "ours()"),
+            replace(BASE, "d()", "delta()"),
+        ];
+        let theirs = [replace(BASE, "b()", "theirs()")];
+        let MergeResult::Conflicts(conflicts) = three_way_merge(BASE, &ours, &theirs) else {
+            panic!("expected a conflict");
+        };
+        assert_eq!(
+            conflicts,
+            vec![ConflictHunk {
+                lines: 2..3,
+                base: "fn b() {}\n".into(),
+                ours: "fn ours() {}\n".into(),
+                theirs: "fn theirs() {}\n".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn unrelated_histories_have_no_merge_base() {
+        let a = [op(&[])];
+        let b = [op(&[])];
+        assert_eq!(merge_base(&a, &b), None);
+    }
+}
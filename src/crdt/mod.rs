@@ -1,8 +1,11 @@
 pub mod anchor;
 pub mod document;
+pub mod merge;
 pub mod operations;
+pub mod stamp;
 
 pub use anchor::Anchor;
-#[allow(unused_imports)]
 pub use document::CrdtDocument;
+#[allow(unused_imports)]
+pub use merge::{ConflictHunk, MergeResult, merge_base, three_way_merge};
 pub use operations::{Operation, OperationType, Position};
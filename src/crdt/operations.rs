@@ -1,7 +1,10 @@
+use anyhow::{Result, bail};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use super::stamp::{self, Stamper};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Operation {
     pub id: Uuid,
@@ -10,6 +13,10 @@ pub struct Operation {
     pub file_path: String,
     pub op_type: OperationType,
     pub parent_ops: Vec<Uuid>, // For causality tracking
+    /// Hybrid clock stamp taken when the operation was created. Zero for
+    /// operations recorded before every operation carried one.
+    #[serde(default)]
+    pub lamport: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +43,39 @@ pub enum OperationType {
         old_path: String,
         new_path: String,
     },
+    /// Unix permission bits changed without a content change.
+    ChmodChange {
+        old_mode: u32,
+        new_mode: u32,
+    },
+    /// A symbolic link was created; only its target is recorded.
+    SymlinkCreate {
+        target: String,
+    },
+    /// An existing symbolic link was repointed.
+    SymlinkChange {
+        old_target: String,
+        new_target: String,
+    },
+    /// The file changed to content that isn't UTF-8. Only hashes and size
+    /// are recorded here; with binary tracking on, the content itself is
+    /// kept as a blob under `new_hash`. Text reconstruction keeps the last
+    /// text content.
+    BinaryModify {
+        old_hash: Option<String>,
+        new_hash: String,
+        size: u64,
+    },
+    /// A directory was created. Files inside it are recorded on their own;
+    /// this keeps directories that are empty in the tree.
+    DirCreate,
+    /// A directory was removed.
+    DirDelete,
+    /// A directory was moved. The files inside it get their own renames.
+    DirRename {
+        old_path: String,
+        new_path: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -71,14 +111,38 @@ impl Position {
 }
 
 impl Operation {
+    /// A new operation stamped by this thread's stamper (random id, current
+    /// time and a `GLOBAL_CLOCK` tick unless a test installed
+    /// `stamp::with_stamper`).
     pub fn new(file_path: String, op_type: OperationType, actor_id: String) -> Self {
+        let (id, timestamp, lamport) = stamp::stamp();
+        Self {
+            id,
+            timestamp,
+            actor_id,
+            file_path,
+            op_type,
+            parent_ops: Vec::new(),
+            lamport,
+        }
+    }
+
+    /// A new operation with its id and timestamp taken from `stamper`.
+    #[allow(dead_code)]
+    pub fn new_with(
+        stamper: &dyn Stamper,
+        file_path: String,
+        op_type: OperationType,
+        actor_id: String,
+    ) -> Self {
         Self {
-            id: Uuid::new_v4(),
-            timestamp: Utc::now(),
+            id: stamper.id(),
+            timestamp: stamper.now(),
             actor_id,
             file_path,
             op_type,
             parent_ops: Vec::new(),
+            lamport: stamper.lamport(),
         }
     }
 
@@ -87,7 +151,104 @@ impl Operation {
         self
     }
 
+    /// Total order used to replay operations identically on every peer: the
+    /// hybrid clock stamp (derived from the wall clock when the operation has
+    /// no position), then the recorded timestamp, actor id and operation id.
+    pub fn order_key(&self) -> (u64, DateTime<Utc>, &str, Uuid) {
+        let stamp = self.lamport().unwrap_or_else(|| {
+            crate::sync::clock::stamp_from_millis(self.timestamp.timestamp_millis().max(0) as u64)
+        });
+        (stamp, self.timestamp, &self.actor_id, self.id)
+    }
+
+    /// Check that the operation is well formed on its own. Operations from
+    /// peers are untrusted and must pass this before they are stored. Ranges
+    /// aren't checked against any particular document: an edit is made
+    /// against its author's copy, which concurrent edits elsewhere may have
+    /// grown or shrunk, and replay clamps each range to the content it
+    /// applies to.
+    pub fn validate(&self) -> Result<()> {
+        if self.file_path.is_empty() {
+            bail!("operation {} has an empty file path", self.id);
+        }
+
+        match &self.op_type {
+            OperationType::Insert {
+                content, length, ..
+            } => {
+                if content.is_empty() {
+                    bail!("insert {} has no content", self.id);
+                }
+                if *length != content.chars().count() {
+                    bail!(
+                        "insert {} length {} does not match content length {}",
+                        self.id,
+                        length,
+                        content.chars().count()
+                    );
+                }
+            }
+            OperationType::Delete { position, length } => {
+                if *length == 0 {
+                    bail!("delete {} removes nothing", self.id);
+                }
+                check_range(self.id, "delete", position.offset, *length)?;
+            }
+            OperationType::Replace {
+                position,
+                old_content,
+                new_content,
+            } => {
+                if old_content.is_empty() && new_content.is_empty() {
+                    bail!("replace {} has no content", self.id);
+                }
+                check_range(
+                    self.id,
+                    "replace",
+                    position.offset,
+                    old_content.chars().count(),
+                )?;
+            }
+            OperationType::FileRename { old_path, new_path }
+            | OperationType::DirRename { old_path, new_path } => {
+                if old_path.is_empty() || new_path.is_empty() {
+                    bail!("rename {} has an empty path", self.id);
+                }
+            }
+            OperationType::ChmodChange { new_mode, .. } => {
+                if *new_mode > 0o7777 {
+                    bail!("chmod {} sets invalid mode {:o}", self.id, new_mode);
+                }
+            }
+            OperationType::BinaryModify { new_hash, .. } => {
+                if new_hash.is_empty() {
+                    bail!("binary change {} has no hash", self.id);
+                }
+            }
+            OperationType::SymlinkCreate { target }
+            | OperationType::SymlinkChange {
+                new_target: target, ..
+            } => {
+                if target.is_empty() {
+                    bail!("symlink {} has an empty target", self.id);
+                }
+            }
+            OperationType::FileCreate { .. }
+            | OperationType::FileDelete
+            | OperationType::DirCreate
+            | OperationType::DirDelete => {}
+        }
+
+        Ok(())
+    }
+
+    /// Clock value used to order the operation: its own stamp, or for
+    /// operations recorded without one, the stamp of its position if it has
+    /// one.
     pub fn lamport(&self) -> Option<u64> {
+        if self.lamport > 0 {
+            return Some(self.lamport);
+        }
         match &self.op_type {
             OperationType::Insert { position, .. }
             | OperationType::Delete { position, .. }
@@ -96,3 +257,133 @@ impl Operation {
         }
     }
 }
+
+impl OperationType {
+    /// Variant name, as stored in the `op_type` column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperationType::Insert { .. } => "Insert",
+            OperationType::Delete { .. } => "Delete",
+            OperationType::Replace { .. } => "Replace",
+            OperationType::FileCreate { .. } => "FileCreate",
+            OperationType::FileDelete => "FileDelete",
+            OperationType::FileRename { .. } => "FileRename",
+            OperationType::ChmodChange { .. } => "ChmodChange",
+            OperationType::SymlinkCreate { .. } => "SymlinkCreate",
+            OperationType::SymlinkChange { .. } => "SymlinkChange",
+            OperationType::BinaryModify { .. } => "BinaryModify",
+            OperationType::DirCreate => "DirCreate",
+            OperationType::DirDelete => "DirDelete",
+            OperationType::DirRename { .. } => "DirRename",
+        }
+    }
+
+    /// Whether the operation is about a directory rather than a file.
+    pub fn is_dir_op(&self) -> bool {
+        matches!(
+            self,
+            OperationType::DirCreate | OperationType::DirDelete | OperationType::DirRename { .. }
+        )
+    }
+
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            OperationType::Insert { position, .. }
+            | OperationType::Delete { position, .. }
+            | OperationType::Replace { position, .. } => Some(position),
+            _ => None,
+        }
+    }
+}
+
+fn check_range(id: Uuid, kind: &str, offset: usize, length: usize) -> Result<()> {
+    match offset.checked_add(length) {
+        Some(_) => Ok(()),
+        None => bail!("{kind} {id} covers {offset}+{length}, which overflows"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(offset: usize) -> Position {
+        Position::new(0, offset, offset, "actor".into(), 1)
+    }
+
+    #[test]
+    fn validate_rejects_malformed_ranges() {
+        // Past the end of any document this repo has seen is still fine: the
+        // edit may have been made against a longer concurrent version
+        let delete = Operation::new(
+            "a.txt".into(),
+            OperationType::Delete {
+                position: position(3),
+                length: 5,
+            },
+            "actor".into(),
+        );
+        assert!(delete.validate().is_ok());
+
+        let overflow = Operation::new(
+            "a.txt".into(),
+            OperationType::Delete {
+                position: position(usize::MAX),
+                length: 1,
+            },
+            "actor".into(),
+        );
+        assert!(overflow.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_empty_or_mismatched_inserts() {
+        let insert = |content: &str, length| {
+            Operation::new(
+                "a.txt".into(),
+                OperationType::Insert {
+                    position: position(0),
+                    content: content.into(),
+                    length,
+                },
+                "actor".into(),
+            )
+        };
+        assert!(insert("héllo", 5).validate().is_ok());
+        assert!(insert("", 0).validate().is_err());
+        assert!(insert("héllo", 6).validate().is_err());
+    }
+
+    #[test]
+    fn every_operation_is_stamped_and_orders_before_later_edits() {
+        let create = Operation::new(
+            "a.txt".into(),
+            OperationType::FileCreate {
+                content: String::new(),
+            },
+            "actor".into(),
+        );
+        // An edit stamped with an old position clock still follows the create
+        let mut insert = Operation::new(
+            "a.txt".into(),
+            OperationType::Insert {
+                position: position(0),
+                content: "a".into(),
+                length: 1,
+            },
+            "actor".into(),
+        );
+        insert.timestamp = create.timestamp - chrono::Duration::seconds(5);
+
+        assert!(create.lamport().is_some());
+        assert!(create.order_key() < insert.order_key());
+
+        // Operations serialized before the field existed fall back to their
+        // position's clock
+        let mut legacy = serde_json::to_value(&insert).unwrap();
+        legacy.as_object_mut().unwrap().remove("lamport");
+        let legacy: Operation = serde_json::from_value(legacy).unwrap();
+        assert_eq!(legacy.lamport, 0);
+        assert_eq!(legacy.lamport(), Some(1));
+    }
+}
@@ -0,0 +1,137 @@
+use chrono::{DateTime, Duration, Utc};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use uuid::Uuid;
+
+/// Source of the id, timestamp and clock value given to each new operation.
+pub trait Stamper {
+    fn id(&self) -> Uuid;
+    fn now(&self) -> DateTime<Utc>;
+    fn lamport(&self) -> u64;
+}
+
+/// Random v4 ids, the wall clock and `GLOBAL_CLOCK`; what operations use by
+/// default.
+pub struct SystemStamper;
+
+impl Stamper for SystemStamper {
+    fn id(&self) -> Uuid {
+        Uuid::new_v4()
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn lamport(&self) -> u64 {
+        crate::sync::GLOBAL_CLOCK.tick()
+    }
+}
+
+/// Reproducible stamps for tests: ids and clock values count up from 1 and
+/// every timestamp is `step` after the previous one, starting at `start`.
+#[allow(dead_code)]
+pub struct SequentialStamper {
+    next: Cell<u128>,
+    start: DateTime<Utc>,
+    step: Duration,
+}
+
+#[allow(dead_code)]
+impl SequentialStamper {
+    pub fn new(start: DateTime<Utc>, step: Duration) -> Self {
+        Self {
+            next: Cell::new(0),
+            start,
+            step,
+        }
+    }
+
+    /// Starts at the Unix epoch with one millisecond between stamps.
+    pub fn from_epoch() -> Self {
+        Self::new(DateTime::UNIX_EPOCH, Duration::milliseconds(1))
+    }
+}
+
+impl Stamper for SequentialStamper {
+    fn id(&self) -> Uuid {
+        let next = self.next.get() + 1;
+        self.next.set(next);
+        Uuid::from_u128(next)
+    }
+
+    /// The time of the latest id handed out, so `Operation::new` pairs the
+    /// nth id with the nth timestamp.
+    fn now(&self) -> DateTime<Utc> {
+        let steps = self.next.get().saturating_sub(1) as i32;
+        self.start + self.step * steps
+    }
+
+    /// Like `now`, the clock value of the latest id handed out.
+    fn lamport(&self) -> u64 {
+        self.next.get() as u64
+    }
+}
+
+thread_local! {
+    static OVERRIDE: RefCell<Option<Rc<dyn Stamper>>> = const { RefCell::new(None) };
+}
+
+/// Stamp operations created on this thread with `stamper` while `f` runs.
+/// Operations created on other threads, including by tasks a multi-threaded
+/// runtime moves elsewhere, keep the system stamper.
+#[allow(dead_code)]
+pub fn with_stamper<R>(stamper: impl Stamper + 'static, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Rc<dyn Stamper>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            OVERRIDE.with(|current| *current.borrow_mut() = self.0.take());
+        }
+    }
+
+    let previous = OVERRIDE.with(|current| current.borrow_mut().replace(Rc::new(stamper)));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// An id, timestamp and clock value from this thread's stamper.
+pub(crate) fn stamp() -> (Uuid, DateTime<Utc>, u64) {
+    OVERRIDE.with(|current| match current.borrow().as_deref() {
+        Some(stamper) => (stamper.id(), stamper.now(), stamper.lamport()),
+        None => (
+            SystemStamper.id(),
+            SystemStamper.now(),
+            SystemStamper.lamport(),
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Operation, OperationType};
+
+    #[test]
+    fn sequential_stamper_makes_operations_reproducible() {
+        let make = || {
+            with_stamper(SequentialStamper::from_epoch(), || {
+                (0..3)
+                    .map(|_| {
+                        Operation::new("a.txt".into(), OperationType::FileDelete, "actor".into())
+                    })
+                    .map(|op| (op.id, op.timestamp, op.lamport))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let first = make();
+        assert_eq!(first, make());
+        assert_eq!(first[0].0, Uuid::from_u128(1));
+        assert_eq!(first[2].1, DateTime::UNIX_EPOCH + Duration::milliseconds(2));
+        assert_eq!(first[2].2, 3);
+
+        // Outside the closure operations are stamped normally again
+        let op = Operation::new("a.txt".into(), OperationType::FileDelete, "actor".into());
+        assert_ne!(op.id, Uuid::from_u128(4));
+    }
+}
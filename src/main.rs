@@ -1,5 +1,6 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
 use colored::*;
 use std::path::PathBuf;
 
@@ -30,13 +31,13 @@ mod watcher;
 All Git commands are supported without the 'git' prefix. Use 'forge <git-command>' instead of 'git <git-command>'.
 
 Main Porcelain Commands:
-   add, am, archive, backfill, bisect, branch, bundle, checkout, cherry-pick, citool, clean, clone, commit, describe, diff, fetch, format-patch, gc, gitk, grep, gui, init, log, maintenance, merge, mv, notes, pull, push, range-diff, rebase, reset, restore, revert, rm, scalar, shortlog, show, sparse-checkout, stash, status, submodule, survey, switch, tag, worktree
+   add, am, archive, backfill, bisect, branch, bundle, checkout, cherry-pick, citool, clean, clone, commit, describe, diff, fetch, format-patch, gitk, grep, gui, init, log, maintenance, merge, mv, notes, pull, push, range-diff, rebase, reset, restore, revert, rm, scalar, shortlog, show, sparse-checkout, stash, submodule, survey, switch, tag, worktree
 
 Ancillary Commands / Manipulators:
-   config, fast-export, fast-import, filter-branch, mergetool, pack-refs, prune, reflog, refs, remote, repack, replace
+   fast-export, fast-import, filter-branch, mergetool, pack-refs, prune, reflog, refs, remote, repack, replace
 
 Ancillary Commands / Interrogators:
-   annotate, blame, bugreport, count-objects, diagnose, difftool, fsck, gitweb, help, instaweb, merge-tree, rerere, show-branch, verify-commit, verify-tag, version, whatchanged
+   annotate, blame, bugreport, count-objects, diagnose, difftool, gitweb, help, instaweb, merge-tree, rerere, show-branch, verify-commit, verify-tag, version, whatchanged
 
 Interacting with Others:
    archimport, cvsexportcommit, cvsimport, cvsserver, imap-send, p4, quiltimport, request-pull, send-email, svn
@@ -80,6 +81,48 @@ enum Commands {
         /// WebSocket peer(s) to connect, e.g. ws://localhost:3000/ws
         #[arg(long, value_name = "URL")]
         peer: Vec<String>,
+
+        /// Discover and connect to LAN servers for the same repository
+        #[arg(long)]
+        discover: bool,
+
+        /// Only track paths matching this glob, e.g. 'src/**' (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Skip paths matching this glob (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Detect and print operations without recording or syncing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also print operations received from peers
+        #[arg(long)]
+        show_remote: bool,
+
+        /// Print recording throughput every 100 operations
+        #[arg(long)]
+        stats: bool,
+    },
+
+    /// Show or change settings in .dx/forge/config.json
+    Config {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+
+        /// Display name shown in the oplog and to sync peers. Not combined
+        /// with a subcommand
+        #[arg(long, value_name = "NAME")]
+        actor_name: Option<String>,
+
+        /// Email shown next to the actor name. Not combined with a subcommand
+        #[arg(long, value_name = "EMAIL")]
+        actor_email: Option<String>,
     },
 
     /// Query the operation log
@@ -89,8 +132,106 @@ enum Commands {
 
         #[arg(short, long)]
         limit: Option<usize>,
+
+        /// Print operations as a JSON array instead of formatted text
+        #[arg(long)]
+        json: bool,
     },
 
+    /// Summarize tracked files, the watcher and pending sync
+    Status {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+    },
+
+    /// Prune deleted files' history and orphaned context, then vacuum the database
+    Gc {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Also replace each file's fine-grained history with a snapshot
+        #[arg(long)]
+        aggressive: bool,
+    },
+
+    /// Print everything stored for one operation
+    ShowOp { id: String },
+
+    /// Print a stored anchor
+    ShowAnchor {
+        id: String,
+
+        /// Also list every operation that touched the anchored line
+        #[arg(long)]
+        history: bool,
+    },
+
+    /// Rebuild checkpoints, the search index and file heads from the log
+    Reindex {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+    },
+
+    /// Check the operation log's causal links, ranges and context rows
+    Fsck {
+        #[arg(short, long, default_value = ".")]
+        path: PathBuf,
+
+        /// Unlink missing parents, quarantine malformed operations and
+        /// delete orphaned anchors and annotations
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Search inserted and replaced content across the operation log
+    Search {
+        query: String,
+
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
+
+    /// Export operations as JSON Lines
+    Export {
+        out: PathBuf,
+
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Only export operations at or after this RFC3339 timestamp
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only export operations at or before this RFC3339 timestamp
+        #[arg(long)]
+        until: Option<String>,
+    },
+
+    /// Print a content-addressed manifest of the tracked tree as JSON
+    Tree {
+        /// Build the tree as of this RFC3339 timestamp instead of now
+        #[arg(long)]
+        at: Option<String>,
+
+        /// Write the manifest to a file instead of stdout
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Write every tracked file as of a point in time into a new directory
+    ExportTree {
+        /// Reconstruct the tree as of this RFC3339 timestamp instead of now
+        #[arg(long)]
+        at: Option<String>,
+
+        /// Directory to write to; must be empty or not exist yet
+        #[arg(long)]
+        out: PathBuf,
+    },
+
+    /// Import operations from a JSON Lines export
+    Import { input: PathBuf },
+
     /// Create a character-level anchor/permalink
     Anchor {
         file: PathBuf,
@@ -119,6 +260,10 @@ enum Commands {
 
         #[arg(short, long)]
         line: Option<usize>,
+
+        /// Print annotations as a JSON array instead of formatted text
+        #[arg(long)]
+        json: bool,
     },
 
     /// Sync Forge repository
@@ -138,6 +283,14 @@ enum Commands {
 
         #[arg(short, long, default_value = ".")]
         path: PathBuf,
+
+        /// Advertise this server to `forge watch --discover` on the LAN
+        #[arg(long)]
+        advertise: bool,
+
+        /// Serve history to clients but reject the operations they send
+        #[arg(long)]
+        read_only: bool,
     },
 
     /// Show time-travel view of a file
@@ -146,7 +299,50 @@ enum Commands {
 
         #[arg(short, long)]
         timestamp: Option<String>,
+
+        /// Diff the recorded state against the file on disk
+        #[arg(long)]
+        diff: bool,
+    },
+
+    /// Find the operation that introduced a condition in a file
+    Bisect {
+        file: PathBuf,
+
+        /// Shell command run with each version on stdin; exit 0 means the condition holds
+        #[arg(long)]
+        cmd: String,
     },
+
+    /// Merge rapid runs of edits to a file into single operations
+    Squash {
+        file: PathBuf,
+
+        /// Longest gap between edits merged together, in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        window_ms: u64,
+
+        /// Squash this actor's edits instead of your own
+        #[arg(long, conflicts_with = "all_actors")]
+        actor: Option<String>,
+
+        /// Squash every actor's edits
+        #[arg(long)]
+        all_actors: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print one setting
+    Get { key: String },
+
+    /// Change one setting; lists are comma-separated and an empty value
+    /// clears optional settings
+    Set { key: String, value: String },
+
+    /// Print every setting
+    List,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 10)]
@@ -159,6 +355,12 @@ async fn main() -> Result<()> {
             path: ".".into(),
             sync: false,
             peer: vec![],
+            discover: false,
+            include: vec![],
+            exclude: vec![],
+            dry_run: false,
+            show_remote: false,
+            stats: false,
         },
     };
 
@@ -182,16 +384,172 @@ async fn main() -> Result<()> {
             );
         }
 
-        Commands::Watch { path, sync, peer } => {
+        Commands::Watch {
+            path,
+            sync,
+            peer,
+            discover,
+            include,
+            exclude,
+            dry_run,
+            show_remote,
+            stats,
+        } => {
             println!(
                 "{}",
                 "✔ Starting operation-level tracking...".cyan().bold()
             );
-            watcher::watch(path, sync, peer).await?;
+            let options = watcher::WatchOptions {
+                sync,
+                peers: peer,
+                discover,
+                include,
+                exclude,
+                dry_run,
+                show_remote,
+                stats,
+            };
+            watcher::watch_with_options(path, options).await?;
+        }
+
+        Commands::Config {
+            path,
+            action,
+            actor_name,
+            actor_email,
+        } => match action {
+            // Clap can't tie an argument's conflicts to a subcommand without
+            // also rejecting `--path` ahead of one, so this is checked here
+            Some(_) if actor_name.is_some() || actor_email.is_some() => {
+                let mut cli = Cli::command();
+                let config = cli.find_subcommand_mut("config").expect("config is a subcommand");
+                config.set_bin_name("forge config");
+                config
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "--actor-name and --actor-email can't be used with a subcommand",
+                    )
+                    .exit()
+            }
+            Some(ConfigAction::Get { key }) => storage::config_get(&path, &key).await?,
+            Some(ConfigAction::Set { key, value }) => {
+                storage::config_set(&path, &key, &value).await?
+            }
+            Some(ConfigAction::List) => storage::config_list(&path).await?,
+            None => storage::configure(&path, actor_name, actor_email).await?,
+        },
+
+        Commands::OpLog { file, limit, json } => {
+            storage::show_log(file, limit.unwrap_or(50), json).await?;
+        }
+
+        Commands::Status { path } => {
+            storage::status(&path).await?;
+        }
+
+        Commands::Gc { path, aggressive } => {
+            storage::gc(&path, aggressive).await?;
+        }
+
+        Commands::ShowOp { id } => {
+            storage::show_op(&id).await?;
+        }
+
+        Commands::ShowAnchor { id, history } => {
+            storage::show_anchor(&id, history).await?;
+        }
+
+        Commands::Reindex { path } => {
+            storage::reindex(&path).await?;
+        }
+
+        Commands::Fsck { path, repair } => {
+            storage::fsck(&path, repair).await?;
+        }
+
+        Commands::Search { query, limit } => {
+            storage::search(&query, limit.unwrap_or(50)).await?;
+        }
+
+        Commands::Export {
+            out,
+            file,
+            since,
+            until,
+        } => {
+            let parse_ts = |ts: String| -> Result<chrono::DateTime<chrono::Utc>> {
+                Ok(chrono::DateTime::parse_from_rfc3339(&ts)?.with_timezone(&chrono::Utc))
+            };
+            let filter = storage::QueryFilter {
+                file,
+                since: since.map(parse_ts).transpose()?,
+                until: until.map(parse_ts).transpose()?,
+                ..Default::default()
+            };
+            let count = storage::export_ops(&out, filter).await?;
+            println!(
+                "{} Exported {} operations to {}",
+                "✓".green(),
+                count,
+                out.display().to_string().bright_white()
+            );
+        }
+
+        Commands::Tree { at, out } => {
+            let at = at
+                .map(|ts| chrono::DateTime::parse_from_rfc3339(&ts))
+                .transpose()?
+                .map(|ts| ts.with_timezone(&chrono::Utc));
+            let manifest = storage::build_tree(at).await?;
+            let json = manifest.to_json()?;
+            match out {
+                Some(out) => {
+                    tokio::fs::write(&out, json).await?;
+                    println!(
+                        "{} Wrote tree {} ({} files) to {}",
+                        "✓".green(),
+                        manifest.hash()?.bright_yellow(),
+                        manifest.entries.len(),
+                        out.display().to_string().bright_white()
+                    );
+                }
+                None => println!("{}", json),
+            }
+        }
+
+        Commands::ExportTree { at, out } => {
+            let at = at
+                .map(|ts| chrono::DateTime::parse_from_rfc3339(&ts))
+                .transpose()?
+                .map(|ts| ts.with_timezone(&chrono::Utc));
+            let summary = storage::export_tree(at, &out).await?;
+            for (path, written) in &summary.collisions {
+                println!(
+                    "{} {} was taken; wrote it as {}",
+                    "⚠️".yellow(),
+                    path.bright_white(),
+                    written.display()
+                );
+            }
+            for path in &summary.missing_blobs {
+                println!(
+                    "{} Skipped {}: its binary content wasn't stored (see track_binary)",
+                    "⚠️".yellow(),
+                    path.bright_white()
+                );
+            }
+            println!(
+                "{} Exported {} files and {} directories to {}",
+                "✓".green(),
+                summary.files,
+                summary.dirs,
+                out.display().to_string().bright_white()
+            );
         }
 
-        Commands::OpLog { file, limit } => {
-            storage::show_log(file, limit.unwrap_or(50)).await?;
+        Commands::Import { input } => {
+            let count = storage::import_ops(&input).await?;
+            println!("{} Imported {} new operations", "✓".green(), count);
         }
 
         Commands::Anchor {
@@ -219,8 +577,8 @@ async fn main() -> Result<()> {
             println!("{} Annotation added", "✓".green());
         }
 
-        Commands::Context { file, line } => {
-            context::show_context(&file, line).await?;
+        Commands::Context { file, line, json } => {
+            context::show_context(&file, line, json).await?;
         }
 
         Commands::ForgeSync { path } => {
@@ -239,18 +597,44 @@ async fn main() -> Result<()> {
             }
         }
 
-        Commands::Serve { port, path } => {
+        Commands::Serve {
+            port,
+            path,
+            advertise,
+            read_only,
+        } => {
             println!(
                 "{}",
                 format!("🌐 Starting server on port {}...", port)
                     .cyan()
                     .bold()
             );
-            server::start(port, path).await?;
+            let options = server::ServeOptions {
+                advertise,
+                read_only,
+            };
+            server::start_with_options(port, path, options).await?;
+        }
+
+        Commands::TimeTravel {
+            file,
+            timestamp,
+            diff,
+        } => {
+            storage::time_travel(&file, timestamp, diff).await?;
         }
 
-        Commands::TimeTravel { file, timestamp } => {
-            storage::time_travel(&file, timestamp).await?;
+        Commands::Bisect { file, cmd } => {
+            storage::run_bisect(&file, &cmd).await?;
+        }
+
+        Commands::Squash {
+            file,
+            window_ms,
+            actor,
+            all_actors,
+        } => {
+            storage::run_squash(&file, window_ms, actor, all_actors).await?;
         }
     }
 
@@ -1,23 +1,35 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::Result;
 use axum::{
     Json, Router,
     extract::Query,
     extract::State,
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    middleware,
+    response::sse::{Event, KeepAlive, Sse},
     routing::get,
 };
 use colored::*;
 use futures::{SinkExt, StreamExt};
 
+use super::ServeOptions;
+use super::auth;
+use super::metrics::Metrics;
+use super::rate_limit::{DEFAULT_WS_OPS_PER_SEC, TokenBucket};
 use crate::crdt::Operation;
-use crate::storage::{Database, OperationLog};
-use crate::sync::{GLOBAL_CLOCK, SyncManager, SyncMessage};
+use crate::storage::{ActorIdentity, Database, ForgeConfig, OperationLog, hash, reconstruct};
+use crate::sync::messages::{Frame, MAX_MESSAGE_BYTES};
+use crate::sync::protocol::{CATCH_UP_BATCH, DEFAULT_SYNC_CHANNEL_CAPACITY};
+use crate::sync::{GLOBAL_CLOCK, SyncManager, SyncMessage, discovery};
 use dashmap::DashSet;
 use serde::Deserialize;
-use sha2::{Digest, Sha256};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite;
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -28,60 +40,116 @@ pub struct AppState {
     pub actor_id: String,
     pub repo_id: String,
     pub seen: Arc<DashSet<Uuid>>,
+    pub metrics: Arc<Metrics>,
+    pub api_token: Option<Arc<str>>,
+    pub ws_ops_per_sec: u32,
+    pub repo_root: PathBuf,
+    /// Refuse every operation clients send (`forge serve --read-only`).
+    pub read_only: bool,
 }
 
-pub async fn serve(port: u16, path: PathBuf) -> Result<()> {
+pub async fn serve(port: u16, path: PathBuf, options: ServeOptions) -> Result<()> {
     // Initialize DB/oplog
     let forge_path = path.join(".dx/forge");
     let db = Arc::new(Database::new(&forge_path)?);
     db.initialize()?;
     let oplog = Arc::new(OperationLog::new(db.clone()));
 
+    // Serving a directory without a Forge config falls back to defaults
+    let cfg = ForgeConfig::load(&forge_path).ok();
+    if let Some(cfg) = &cfg {
+        hash::set_active(cfg.hash_algorithm);
+    }
+
     // Load actor/repo identifiers
-    let config_path = forge_path.join("config.json");
     let default_repo_id = {
-        let mut hasher = Sha256::new();
         let path_string = forge_path.to_string_lossy().into_owned();
-        hasher.update(path_string.as_bytes());
-        format!("repo-{:x}", hasher.finalize())
+        format!("repo-{}", hash::digest(path_string.as_bytes())?)
     };
 
-    let (actor_id, repo_id) = if let Ok(bytes) = tokio::fs::read(&config_path).await {
-        if let Ok(cfg) = serde_json::from_slice::<serde_json::Value>(&bytes) {
-            let actor = cfg
-                .get("actor_id")
-                .and_then(|s| s.as_str())
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| whoami::username());
-            let repo = cfg
-                .get("repo_id")
-                .and_then(|s| s.as_str())
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| default_repo_id.clone());
-            (actor, repo)
-        } else {
-            (whoami::username(), default_repo_id.clone())
-        }
-    } else {
-        (whoami::username(), default_repo_id)
-    };
+    let actor_id = cfg
+        .as_ref()
+        .map(|cfg| cfg.actor_id.clone())
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(whoami::username);
+    if let Some(cfg) = &cfg {
+        db.record_actor(&ActorIdentity {
+            actor_id: actor_id.clone(),
+            ..cfg.identity()
+        })?;
+    }
+    let repo_id = cfg
+        .as_ref()
+        .and_then(|cfg| cfg.repo_id.clone())
+        .unwrap_or(default_repo_id);
+    let ws_ops_per_sec = cfg
+        .as_ref()
+        .map_or(DEFAULT_WS_OPS_PER_SEC, |cfg| cfg.ws_ops_per_sec);
+    let sync_channel_capacity = cfg.as_ref().map_or(DEFAULT_SYNC_CHANNEL_CAPACITY, |cfg| {
+        cfg.sync_channel_capacity
+    });
 
+    let advertised_repo_id = repo_id.clone();
     let state = AppState {
         oplog,
         db,
-        sync: SyncManager::new(),
+        sync: SyncManager::with_capacity(sync_channel_capacity),
         actor_id,
         repo_id,
         seen: Arc::new(DashSet::new()),
+        metrics: Arc::new(Metrics::new()),
+        api_token: auth::token_from_env().map(Arc::from),
+        ws_ops_per_sec,
+        repo_root: path.canonicalize().unwrap_or_else(|_| path.clone()),
+        read_only: options.read_only,
     };
 
-    let app = Router::new()
-        .route("/", get(|| async { "Forge DeltaDB Server" }))
-        .route("/health", get(|| async { Json("OK") }))
+    if state.api_token.is_none() {
+        println!(
+            "{} {} not set; sync endpoints are unauthenticated",
+            "⚠️".bright_yellow(),
+            auth::API_TOKEN_ENV
+        );
+    }
+
+    // Sync endpoints require the bearer token; health and metrics stay public
+    let protected = Router::new()
         .route("/ops", get(get_ops))
+        .route("/api/v1/files/{*path}", get(get_file))
+        .route("/api/v1/stream", get(stream_ops))
         .route("/ws", get(ws_handler))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_token,
+        ));
+
+    let app = Router::new()
+        .route("/", get(|| async { "Forge DeltaDB Server" }))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics_handler))
+        .merge(protected)
         .with_state(state);
 
+    // Held for as long as the server runs, so it keeps answering queries
+    let _advertiser = if options.advertise {
+        let daemon = discovery::advertise(advertised_repo_id, port)?;
+        println!(
+            "{} Advertising on LAN (mDNS {})",
+            "→".bright_blue(),
+            discovery::SERVICE_TYPE
+        );
+        Some(daemon)
+    } else {
+        None
+    };
+
+    if options.read_only {
+        println!(
+            "{} Read-only: operations from clients are rejected",
+            "→".bright_blue()
+        );
+    }
+
     let addr = format!("0.0.0.0:{}", port);
     println!(
         "{} Server running at {}",
@@ -99,27 +167,89 @@ async fn ws_handler(
     State(state): State<AppState>,
     ws: WebSocketUpgrade,
 ) -> impl axum::response::IntoResponse {
-    ws.on_upgrade(move |socket| handle_ws(state, socket))
+    ws.max_message_size(MAX_MESSAGE_BYTES)
+        .max_frame_size(MAX_MESSAGE_BYTES)
+        .on_upgrade(move |socket| handle_ws(state, socket))
+}
+
+#[derive(serde::Serialize)]
+struct Health {
+    status: &'static str,
+    read_only: bool,
+}
+
+async fn health(State(state): State<AppState>) -> Json<Health> {
+    Json(Health {
+        status: "OK",
+        read_only: state.read_only,
+    })
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> impl axum::response::IntoResponse {
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4",
+        )],
+        state.metrics.render(),
+    )
 }
 
 async fn handle_ws(state: AppState, socket: WebSocket) {
     let (mut sender, mut receiver) = socket.split();
+    state.metrics.connection_opened();
 
     // Send handshake immediately with server metadata
-    let handshake = SyncMessage::handshake(state.actor_id.clone(), state.repo_id.clone());
+    let identity = state
+        .db
+        .actor(&state.actor_id)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| ActorIdentity::anonymous(state.actor_id.clone()));
+    let handshake = SyncMessage::handshake(identity, state.repo_id.clone());
     if let Ok(text) = serde_json::to_string(&handshake) {
         let _ = sender.send(Message::Text(text.into())).await;
     }
 
-    // Subscribe to local operations and forward to this client
+    // Set once the client's handshake says it decodes compressed frames
+    let compress = Arc::new(AtomicBool::new(false));
+
+    // Subscribe to local operations and forward to this client, along with
+    // any replies (such as rejections) addressed to it directly
     let mut rx = state.sync.subscribe();
-    let send_task = tokio::spawn(async move {
-        while let Ok(op_arc) = rx.recv().await {
-            // Forward as JSON text
-            if let Ok(text) = serde_json::to_string(&SyncMessage::operation((*op_arc).clone())) {
-                if sender.send(Message::Text(text.into())).await.is_err() {
+    let (reply_tx, mut reply_rx) = mpsc::channel::<SyncMessage>(REPLY_QUEUE_LIMIT);
+    let (close_tx, mut close_rx) = oneshot::channel::<CloseFrame>();
+    let compress_replies = compress.clone();
+    let oplog = state.oplog.clone();
+    let mut send_task = tokio::spawn(async move {
+        'forward: loop {
+            let msgs = tokio::select! {
+                recv = rx.recv_batch() => match recv {
+                    Ok(batch) => vec![SyncMessage::for_ops(&batch)],
+                    // A slow client is sent what it missed from the log
+                    // instead of being dropped
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let missed = rx.catch_up(&oplog).await.unwrap_or_default();
+                        missed.chunks(CATCH_UP_BATCH).map(SyncMessage::for_ops).collect()
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                Some(reply) = reply_rx.recv() => vec![reply],
+                Ok(frame) = &mut close_rx => {
+                    let _ = sender.send(Message::Close(Some(frame))).await;
                     break;
                 }
+            };
+            for msg in msgs {
+                // Forward as JSON text, or compressed if the client accepts it
+                let frame = match msg.encode(compress_replies.load(Ordering::Relaxed)) {
+                    Ok(Frame::Text(text)) => Message::Text(text.into()),
+                    Ok(Frame::Binary(bytes)) => Message::Binary(bytes.into()),
+                    Err(_) => continue,
+                };
+                if sender.send(frame).await.is_err() {
+                    break 'forward;
+                }
             }
         }
     });
@@ -127,59 +257,209 @@ async fn handle_ws(state: AppState, socket: WebSocket) {
     // Receive from client and publish
     let state_recv = state.clone();
     let recv_task = tokio::spawn(async move {
-        let oplog = state_recv.oplog.clone();
+        let mut bucket = TokenBucket::per_second(state_recv.ws_ops_per_sec);
+        let mut dropped_in_a_row = 0u32;
         while let Some(msg) = receiver.next().await {
-            match msg {
+            let msg = match msg {
                 Ok(Message::Text(text)) => {
+                    state_recv.metrics.record_message();
                     let text: String = text.to_string();
-                    if let Ok(msg) = serde_json::from_str::<SyncMessage>(&text) {
-                        match msg {
-                            SyncMessage::Handshake { actor_id, repo_id } => {
-                                println!(
-                                    "{} Peer handshake: actor={} repo={}",
-                                    "↔".bright_blue(),
-                                    actor_id.bright_yellow(),
-                                    repo_id.bright_white()
-                                );
-                            }
-                            SyncMessage::Operation { operation: op } => {
-                                if insert_seen(&state_recv.seen, op.id) {
-                                    if let Some(lamport) = op.lamport() {
-                                        GLOBAL_CLOCK.observe(lamport);
-                                    }
-                                    let _ = oplog.append(op.clone());
-                                    let _ = state_recv.sync.publish(Arc::new(op));
-                                }
-                            }
-                        }
-                    } else if let Ok(op) = serde_json::from_str::<Operation>(&text) {
-                        if insert_seen(&state_recv.seen, op.id) {
-                            if let Some(lamport) = op.lamport() {
-                                GLOBAL_CLOCK.observe(lamport);
-                            }
-                            let _ = oplog.append(op.clone());
-                            let _ = state_recv.sync.publish(Arc::new(op));
-                        }
-                    }
+                    serde_json::from_str::<SyncMessage>(&text).ok().or_else(|| {
+                        serde_json::from_str::<Operation>(&text)
+                            .ok()
+                            .map(SyncMessage::operation)
+                    })
                 }
                 Ok(Message::Binary(bin)) => {
-                    if let Ok(op) = serde_cbor::from_slice::<Operation>(&bin) {
-                        if insert_seen(&state_recv.seen, op.id) {
-                            if let Some(lamport) = op.lamport() {
-                                GLOBAL_CLOCK.observe(lamport);
-                            }
-                            let _ = oplog.append(op.clone());
-                            let _ = state_recv.sync.publish(Arc::new(op));
-                        }
+                    state_recv.metrics.record_message();
+                    match SyncMessage::decode_compressed(&bin) {
+                        Some(decoded) => decoded.ok(),
+                        None => serde_cbor::from_slice::<Operation>(&bin)
+                            .ok()
+                            .map(SyncMessage::operation),
                     }
                 }
-                Ok(Message::Close(_)) | Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => {}
-                Err(_) => break,
+                Ok(Message::Close(_)) | Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => continue,
+                Err(err) => return oversized_close(err),
+            };
+
+            // Every operation in a batch costs a token, and so does any
+            // other message
+            let cost = match &msg {
+                Some(SyncMessage::OperationBatch { operations }) => operations.len().max(1),
+                _ => 1,
+            };
+            if bucket.try_acquire_n(u32::try_from(cost).unwrap_or(u32::MAX)) {
+                dropped_in_a_row = 0;
+            } else {
+                dropped_in_a_row += 1;
+                if dropped_in_a_row >= DISCONNECT_AFTER_DROPS {
+                    println!(
+                        "{} Disconnecting peer exceeding {} ops/s",
+                        "⚠️".bright_red(),
+                        state_recv.ws_ops_per_sec
+                    );
+                    break;
+                }
+                continue;
+            }
+            if let Some(msg) = msg {
+                handle_message(&state_recv, &reply_tx, &compress, msg).await;
             }
         }
+        None
     });
 
-    let _ = tokio::join!(send_task, recv_task);
+    // The forwarder only notices a dead socket on its next send, so stop it as
+    // soon as the client side of the connection ends, after telling the
+    // client why if it was cut off.
+    if let Ok(Some(frame)) = recv_task.await {
+        let _ = close_tx.send(frame);
+        let _ = tokio::time::timeout(CLOSE_TIMEOUT, &mut send_task).await;
+    }
+    send_task.abort();
+    state.metrics.connection_closed();
+}
+
+/// Act on one message from a client.
+async fn handle_message(
+    state: &AppState,
+    reply_tx: &mpsc::Sender<SyncMessage>,
+    compress: &AtomicBool,
+    msg: SyncMessage,
+) {
+    if let SyncMessage::Handshake { .. } = &msg {
+        compress.store(msg.accepts_compression(), Ordering::Relaxed);
+    }
+    match msg {
+        SyncMessage::Handshake {
+            actor_id,
+            repo_id,
+            actor_name,
+            actor_email,
+            ..
+        } => {
+            let identity = ActorIdentity {
+                actor_id,
+                name: actor_name,
+                email: actor_email,
+            };
+            if !identity.is_anonymous() {
+                let _ = state.db.record_actor(&identity);
+            }
+            println!(
+                "{} Peer handshake: actor={} repo={}",
+                "↔".bright_blue(),
+                identity.display_name().bright_yellow(),
+                repo_id.bright_white()
+            );
+        }
+        SyncMessage::Operation { operation: op } => {
+            ingest(state, reply_tx, vec![op]).await;
+        }
+        SyncMessage::OperationBatch { operations } => {
+            ingest(state, reply_tx, operations).await;
+        }
+        SyncMessage::Rejected { .. } | SyncMessage::Ack { .. } => {}
+    }
+}
+
+/// Close frame for a read that failed because the client sent a message
+/// over `MAX_MESSAGE_BYTES`; the frame was dropped unparsed.
+fn oversized_close(err: axum::Error) -> Option<CloseFrame> {
+    let err = err.into_inner();
+    let too_big = matches!(
+        err.downcast_ref::<tungstenite::Error>(),
+        Some(tungstenite::Error::Capacity(_))
+    );
+    too_big.then(|| {
+        println!("{} Disconnecting peer: {}", "⚠️".bright_red(), err);
+        CloseFrame {
+            code: close_code::SIZE,
+            reason: format!("messages are limited to {MAX_MESSAGE_BYTES} bytes").into(),
+        }
+    })
+}
+
+/// Replies (acks, rejections) queued for a client that isn't reading them
+/// are dropped past this many.
+const REPLY_QUEUE_LIMIT: usize = 1_024;
+
+/// How long a closing connection waits for its close frame to go out.
+const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Consecutive rate-limited messages after which a peer is disconnected.
+const DISCONNECT_AFTER_DROPS: u32 = 1_000;
+
+/// Run [`ingest_batch`] on a blocking thread, since storing an operation can
+/// wait on the database. The next message from the peer waits for it, so its
+/// operations are still ingested in order.
+async fn ingest(state: &AppState, reply_tx: &mpsc::Sender<SyncMessage>, ops: Vec<Operation>) {
+    let state = state.clone();
+    let reply_tx = reply_tx.clone();
+    let _ = tokio::task::spawn_blocking(move || ingest_batch(&state, &reply_tx, ops)).await;
+}
+
+/// Ingest operations a peer sent together and rebroadcast the accepted ones
+/// as a single batch, telling the peer about any it refused and acking the
+/// last one stored. Rejected operations are logged and never persisted; a
+/// read-only server rejects them all.
+fn ingest_batch(
+    state: &AppState,
+    reply_tx: &mpsc::Sender<SyncMessage>,
+    ops: Vec<Operation>,
+) {
+    if state.read_only {
+        for op in ops {
+            let _ = reply_tx.try_send(SyncMessage::rejected(op.id, "server is read-only".into()));
+        }
+        return;
+    }
+
+    let mut accepted = Vec::with_capacity(ops.len());
+    let mut stored = None;
+    for op in ops {
+        let op_id = op.id;
+        match ingest_operation(state, op) {
+            Ok(Some(op)) => {
+                stored = Some(op_id);
+                accepted.push(Arc::new(op));
+            }
+            // Already here, which is as good as stored
+            Ok(None) => stored = Some(op_id),
+            Err(err) => {
+                println!("{} Rejected operation {}: {}", "⚠️".bright_red(), op_id, err);
+                let _ = reply_tx.try_send(SyncMessage::rejected(op_id, err.to_string()));
+            }
+        }
+    }
+    if !accepted.is_empty() {
+        let _ = state.sync.publish_batch(accepted);
+    }
+    if let Some(op_id) = stored {
+        let _ = reply_tx.try_send(SyncMessage::ack(op_id));
+    }
+}
+
+/// Persist an operation received from a peer, returning it if it's new to
+/// this server. Operations already seen here are dropped; malformed ones are
+/// returned as errors. An edit is only checked on its own, not against this
+/// server's copy of the file, which may not have the state the peer edited.
+fn ingest_operation(state: &AppState, op: Operation) -> Result<Option<Operation>> {
+    if state.seen.contains(&op.id) {
+        return Ok(None);
+    }
+    op.validate()?;
+    if !insert_seen(&state.seen, op.id) {
+        return Ok(None);
+    }
+    if let Some(lamport) = op.lamport() {
+        GLOBAL_CLOCK.observe(lamport);
+    }
+    if let Ok(true) = state.oplog.append(op.clone()) {
+        state.metrics.record_append();
+    }
+    Ok(Some(op))
 }
 
 #[derive(Deserialize)]
@@ -206,6 +486,97 @@ async fn get_ops(
     }
 }
 
+#[derive(Deserialize)]
+struct StreamQuery {
+    file: Option<String>,
+}
+
+/// Server-Sent Events feed of live operations, optionally limited to a
+/// single file path. The subscription is dropped with the response stream
+/// when the client disconnects.
+async fn stream_ops(
+    State(state): State<AppState>,
+    Query(query): Query<StreamQuery>,
+) -> Sse<impl futures::Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.sync.subscribe();
+    let file = query.file;
+    let oplog = state.oplog.clone();
+
+    // Operations recovered from the log after lagging are sent first
+    let missed = VecDeque::new();
+    let stream = futures::stream::unfold((rx, missed), move |(mut rx, mut missed)| {
+        let file = file.clone();
+        let oplog = oplog.clone();
+        async move {
+            loop {
+                let op = match missed.pop_front() {
+                    Some(op) => op,
+                    None => match rx.recv().await {
+                        Ok(op) => op,
+                        Err(broadcast::error::RecvError::Lagged(_)) => {
+                            missed.extend(rx.catch_up(&oplog).await.unwrap_or_default());
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    },
+                };
+                if file.as_deref().is_some_and(|f| f != op.file_path) {
+                    continue;
+                }
+                if let Ok(event) = Event::default().json_data(&*op) {
+                    return Some((Ok(event), (rx, missed)));
+                }
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[derive(Deserialize)]
+struct FileQuery {
+    timestamp: Option<String>,
+}
+
+/// Reconstructed content of a file, either current or as of `?timestamp=`.
+/// Served as `/api/v1/files/{path}` and `/api/v1/files/{path}/at`; axum
+/// wildcards must end a route, so the `/at` is stripped here. A file named
+/// `at` is reached as `{dir}/at/at`.
+async fn get_file(
+    State(state): State<AppState>,
+    axum::extract::Path(path): axum::extract::Path<String>,
+    Query(query): Query<FileQuery>,
+) -> Result<String, axum::http::StatusCode> {
+    let target_time = match query.timestamp.as_deref() {
+        Some(ts) => chrono::DateTime::parse_from_rfc3339(ts)
+            .map_err(|_| axum::http::StatusCode::BAD_REQUEST)?
+            .with_timezone(&chrono::Utc),
+        None => chrono::Utc::now(),
+    };
+    let path = path.strip_suffix("/at").unwrap_or(&path);
+
+    for candidate in file_path_candidates(&state.repo_root, path) {
+        match reconstruct::reconstruct_at(&state.db, &candidate, target_time) {
+            Ok(Some(content)) => return Ok(content),
+            Ok(None) => continue,
+            Err(_) => return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    Err(axum::http::StatusCode::NOT_FOUND)
+}
+
+/// Operations are keyed by whatever path the recording peer saw, so try the
+/// request path verbatim, as an absolute path, and relative to the repo root.
+fn file_path_candidates(repo_root: &std::path::Path, path: &str) -> Vec<String> {
+    let mut candidates = vec![path.to_string(), format!("/{path}")];
+    let joined = repo_root.join(path);
+    let joined = joined.canonicalize().unwrap_or(joined);
+    candidates.push(joined.display().to_string());
+    candidates.dedup();
+    candidates
+}
+
 const SEEN_LIMIT: usize = 10_000;
 
 fn insert_seen(cache: &DashSet<Uuid>, id: Uuid) -> bool {
@@ -0,0 +1,123 @@
+use parking_lot::Mutex;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Process-wide server counters rendered in the Prometheus text format.
+pub struct Metrics {
+    started_at: Instant,
+    operations_appended: AtomicU64,
+    messages_received: AtomicU64,
+    active_connections: AtomicU64,
+    last_scrape: Mutex<(Instant, u64)>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started_at: now,
+            operations_appended: AtomicU64::new(0),
+            messages_received: AtomicU64::new(0),
+            active_connections: AtomicU64::new(0),
+            last_scrape: Mutex::new((now, 0)),
+        }
+    }
+
+    pub fn record_append(&self) {
+        self.operations_appended.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_message(&self) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_opened(&self) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Render all metrics. The ops/sec gauge covers the window since the
+    /// previous scrape.
+    pub fn render(&self) -> String {
+        let appended = self.operations_appended.load(Ordering::Relaxed);
+        let ops_per_sec = {
+            let mut last = self.last_scrape.lock();
+            let elapsed = last.0.elapsed().as_secs_f64().max(f64::EPSILON);
+            let rate = appended.saturating_sub(last.1) as f64 / elapsed;
+            *last = (Instant::now(), appended);
+            rate
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP forge_operations_appended_total Operations appended to the log."
+        );
+        let _ = writeln!(out, "# TYPE forge_operations_appended_total counter");
+        let _ = writeln!(out, "forge_operations_appended_total {appended}");
+        let _ = writeln!(
+            out,
+            "# HELP forge_operations_per_second Append rate since the previous scrape."
+        );
+        let _ = writeln!(out, "# TYPE forge_operations_per_second gauge");
+        let _ = writeln!(out, "forge_operations_per_second {ops_per_sec:.3}");
+        let _ = writeln!(
+            out,
+            "# HELP forge_ws_messages_received_total WebSocket messages received from peers."
+        );
+        let _ = writeln!(out, "# TYPE forge_ws_messages_received_total counter");
+        let _ = writeln!(
+            out,
+            "forge_ws_messages_received_total {}",
+            self.messages_received.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP forge_ws_active_connections Open WebSocket connections."
+        );
+        let _ = writeln!(out, "# TYPE forge_ws_active_connections gauge");
+        let _ = writeln!(
+            out,
+            "forge_ws_active_connections {}",
+            self.active_connections.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP forge_uptime_seconds Seconds since the server started."
+        );
+        let _ = writeln!(out, "# TYPE forge_uptime_seconds gauge");
+        let _ = writeln!(
+            out,
+            "forge_uptime_seconds {}",
+            self.started_at.elapsed().as_secs()
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters() {
+        let metrics = Metrics::new();
+        metrics.record_append();
+        metrics.record_append();
+        metrics.connection_opened();
+
+        let text = metrics.render();
+        assert!(text.contains("forge_operations_appended_total 2"));
+        assert!(text.contains("forge_ws_active_connections 1"));
+    }
+}
@@ -1,8 +1,25 @@
 pub mod api;
+pub mod auth;
+pub mod metrics;
+pub mod rate_limit;
 
 use anyhow::Result;
 use std::path::PathBuf;
 
+/// Optional server behaviour toggled from the CLI.
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    /// Advertise this repository to LAN peers over mDNS.
+    pub advertise: bool,
+    /// Serve history but refuse operations from clients.
+    pub read_only: bool,
+}
+
+#[allow(dead_code)]
 pub async fn start(port: u16, path: PathBuf) -> Result<()> {
-    api::serve(port, path).await
+    api::serve(port, path, ServeOptions::default()).await
+}
+
+pub async fn start_with_options(port: u16, path: PathBuf, options: ServeOptions) -> Result<()> {
+    api::serve(port, path, options).await
 }
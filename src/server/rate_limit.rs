@@ -0,0 +1,97 @@
+use std::time::Instant;
+
+/// Default sustained operation rate accepted from a single WebSocket peer.
+pub const DEFAULT_WS_OPS_PER_SEC: u32 = 500;
+
+/// Token bucket refilled continuously at `rate` tokens per second, holding at
+/// most `capacity` tokens.
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    rate: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32, capacity: u32) -> Self {
+        Self {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            rate: rate as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Bucket allowing `rate` ops/sec with bursts of up to twice that.
+    pub fn per_second(rate: u32) -> Self {
+        Self::new(rate, rate.saturating_mul(2))
+    }
+
+    /// Take one token if available.
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_n(1)
+    }
+
+    /// Take `n` tokens if available. More than the bucket holds is granted
+    /// once it's full and leaves it in debt, so the sustained rate still
+    /// holds.
+    pub fn try_acquire_n(&mut self, n: u32) -> bool {
+        self.refill();
+        let n = n as f64;
+        if self.tokens >= n.min(self.capacity) {
+            self.tokens -= n;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the bucket has refilled completely, making it no different
+    /// from a new one.
+    pub fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.capacity
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_limits_bursts() {
+        let mut bucket = TokenBucket::new(1, 3);
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+    }
+
+    #[test]
+    fn bucket_charges_every_token_taken_at_once() {
+        let mut bucket = TokenBucket::new(1, 3);
+        assert!(bucket.try_acquire_n(2));
+        assert!(!bucket.try_acquire_n(2));
+        assert!(bucket.try_acquire());
+
+        // An oversized request drains a full bucket and leaves it owing
+        let mut bucket = TokenBucket::new(1, 3);
+        assert!(bucket.try_acquire_n(10));
+        assert!(!bucket.try_acquire());
+    }
+
+    #[test]
+    fn bucket_is_full_until_a_token_is_taken() {
+        let mut bucket = TokenBucket::new(1, 3);
+        assert!(bucket.is_full());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.is_full());
+    }
+}
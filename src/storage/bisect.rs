@@ -0,0 +1,103 @@
+use anyhow::Result;
+
+use super::db::{Database, QueryFilter};
+use super::reconstruct;
+use crate::crdt::Operation;
+
+/// Find the operation after which `predicate` first holds for the content of
+/// `file_path`, by binary search over the file's operations. Like `git
+/// bisect`, this assumes the condition stays true once introduced. Returns
+/// `None` when it doesn't hold for the latest content.
+pub fn bisect(
+    db: &Database,
+    file_path: &str,
+    predicate: impl Fn(&str) -> bool,
+) -> Result<Option<Operation>> {
+    let filter = QueryFilter {
+        file: Some(file_path.into()),
+        ..Default::default()
+    };
+    let operations = db.query_operations(&filter)?;
+
+    // Content right after operation `idx`; a deleted file has none
+    let holds_after = |idx: usize| -> Result<bool> {
+        let op: &Operation = &operations[idx];
+        let content = reconstruct::reconstruct_at(db, file_path, op.timestamp)?;
+        Ok(content.is_some_and(|content| predicate(&content)))
+    };
+
+    let Some(last) = operations.len().checked_sub(1) else {
+        return Ok(None);
+    };
+    if !holds_after(last)? {
+        return Ok(None);
+    }
+
+    // Invariant: the condition holds after `high` and not before `low`
+    let (mut low, mut high) = (0, last);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if holds_after(mid)? {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Ok(operations.into_iter().nth(high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{OperationType, Position};
+    use crate::sync::GLOBAL_CLOCK;
+    use chrono::{Duration, Utc};
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_the_operation_that_introduced_a_condition() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let mut ops = vec![Operation::new(
+            "main.rs".into(),
+            OperationType::FileCreate {
+                content: String::new(),
+            },
+            "actor".into(),
+        )];
+        let lines = ["fn a() {}\n", "fn b() {}\n", "panic!()\n", "fn c() {}\n"];
+        let mut offset = 0;
+        for line in lines {
+            ops.push(Operation::new(
+                "main.rs".into(),
+                OperationType::Insert {
+                    position: Position::new(1, 1, offset, "actor".into(), GLOBAL_CLOCK.tick()),
+                    content: line.into(),
+                    length: line.len(),
+                },
+                "actor".into(),
+            ));
+            offset += line.len();
+        }
+        let start = Utc::now() - Duration::seconds(10);
+        for (idx, op) in ops.iter_mut().enumerate() {
+            op.timestamp = start + Duration::seconds(idx as i64);
+            db.store_operation(op).unwrap();
+        }
+
+        let culprit = bisect(&db, "main.rs", |content| content.contains("panic!")).unwrap();
+        assert_eq!(culprit.map(|op| op.id), Some(ops[3].id));
+
+        assert!(
+            bisect(&db, "main.rs", |content| content.contains("todo!"))
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            bisect(&db, "main.rs", |_| true).unwrap().map(|op| op.id),
+            Some(ops[0].id)
+        );
+    }
+}
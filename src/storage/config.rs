@@ -0,0 +1,262 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use super::hash::HashAlgorithm;
+use super::identity::ActorIdentity;
+use super::oplog::DEFAULT_CHECKPOINT_INTERVAL;
+use crate::server::rate_limit::DEFAULT_WS_OPS_PER_SEC;
+use crate::sync::protocol::DEFAULT_SYNC_CHANNEL_CAPACITY;
+
+pub const CONFIG_FILE: &str = "config.json";
+
+/// Keys `forge config set` refuses to change: the actor id is the stable key
+/// on every recorded operation, and the version is written by Forge itself.
+const READ_ONLY_KEYS: &[&str] = &["actor_id", "version"];
+
+/// Largest file the watcher tracks unless configured otherwise.
+pub const DEFAULT_MAX_TRACKED_BYTES: u64 = 1_000_000;
+
+/// How long the watcher waits for a deleted file to be re-created.
+pub const DEFAULT_ATOMIC_SAVE_WINDOW_MS: u64 = 500;
+
+/// Changes per second a single file may record before they're coalesced.
+pub const DEFAULT_MAX_FILE_OPS_PER_SEC: u32 = 50;
+
+/// Most operations the watcher buffers before writing, when buffering is on.
+pub const DEFAULT_MAX_BUFFERED_OPS: usize = 1_000;
+
+/// How the watcher trades latency against never reading a half-written file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchProfile {
+    /// Diff as soon as events arrive (1ms debounce). A file caught mid-write
+    /// can be recorded in a partial state and then corrected.
+    #[default]
+    Latency,
+    /// Debounce for 50ms and wait for a file's size and mtime to stop
+    /// changing before diffing it.
+    Accuracy,
+}
+
+impl WatchProfile {
+    /// Debounce used when `debounce_ms` isn't set.
+    pub fn default_debounce_ms(self) -> u64 {
+        match self {
+            WatchProfile::Latency => 1,
+            WatchProfile::Accuracy => 50,
+        }
+    }
+}
+
+/// Contents of `.dx/forge/config.json`. Every field has a default so configs
+/// written by older versions, or edited by hand, still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForgeConfig {
+    pub version: String,
+    pub actor_id: String,
+    pub actor_name: Option<String>,
+    pub actor_email: Option<String>,
+    pub repo_id: Option<String>,
+    pub git_interop: bool,
+    pub real_time_sync: bool,
+    pub checkpoint_interval: usize,
+    pub max_tracked_bytes: u64,
+    /// Gitignore-style globs the watcher never tracks, on top of `--exclude`.
+    pub ignore_globs: Vec<String>,
+    pub ws_ops_per_sec: u32,
+    /// Algorithm for new content hashes; existing hashes keep theirs.
+    pub hash_algorithm: HashAlgorithm,
+    /// Milliseconds a delete is held so an editor's delete + re-create
+    /// records as an edit; 0 records deletes immediately.
+    pub atomic_save_window_ms: u64,
+    pub watch_profile: WatchProfile,
+    /// Watcher debounce in milliseconds; 0 uses the profile's default.
+    /// `DX_WATCH_DEBOUNCE_MS` overrides it.
+    pub debounce_ms: u64,
+    /// Keep the content of binary files as blobs so they can be restored,
+    /// rather than recording only their hashes.
+    pub track_binary: bool,
+    /// When the watcher starts, record changes made to tracked files while
+    /// it wasn't running.
+    pub reconcile_on_start: bool,
+    /// Milliseconds recorded operations may wait in memory to be written in
+    /// a batch; 0 writes each one immediately.
+    pub flush_interval_ms: u64,
+    /// Write a batch early once this many operations are waiting.
+    pub max_buffered_ops: usize,
+    /// Changes per second recorded individually for one file; beyond that
+    /// they're coalesced into a periodic snapshot. 0 disables the limit.
+    pub max_file_ops_per_sec: u32,
+    /// Published batches held for slow sync subscribers before they lag
+    /// and have to catch up from the log.
+    pub sync_channel_capacity: usize,
+    // Keys this version doesn't know about, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for ForgeConfig {
+    fn default() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            actor_id: String::new(),
+            actor_name: None,
+            actor_email: None,
+            repo_id: None,
+            git_interop: true,
+            real_time_sync: false,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            max_tracked_bytes: DEFAULT_MAX_TRACKED_BYTES,
+            ignore_globs: Vec::new(),
+            ws_ops_per_sec: DEFAULT_WS_OPS_PER_SEC,
+            hash_algorithm: HashAlgorithm::default(),
+            atomic_save_window_ms: DEFAULT_ATOMIC_SAVE_WINDOW_MS,
+            watch_profile: WatchProfile::default(),
+            debounce_ms: 0,
+            track_binary: false,
+            reconcile_on_start: true,
+            flush_interval_ms: 0,
+            max_buffered_ops: DEFAULT_MAX_BUFFERED_OPS,
+            max_file_ops_per_sec: DEFAULT_MAX_FILE_OPS_PER_SEC,
+            sync_channel_capacity: DEFAULT_SYNC_CHANNEL_CAPACITY,
+            extra: serde_json::Map::new(),
+        }
+    }
+}
+
+impl ForgeConfig {
+    pub fn path(forge_dir: &Path) -> PathBuf {
+        forge_dir.join(CONFIG_FILE)
+    }
+
+    pub fn load(forge_dir: &Path) -> Result<Self> {
+        let path = Self::path(forge_dir);
+        let raw = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&raw).with_context(|| format!("invalid config in {}", path.display()))
+    }
+
+    pub fn save(&self, forge_dir: &Path) -> Result<()> {
+        std::fs::write(Self::path(forge_dir), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Every known setting and its current value, sorted by key.
+    pub fn entries(&self) -> Result<Vec<(String, Value)>> {
+        let Value::Object(map) = serde_json::to_value(self)? else {
+            bail!("config did not serialize to an object");
+        };
+        Ok(map
+            .into_iter()
+            .filter(|(key, _)| !self.extra.contains_key(key))
+            .collect())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Value> {
+        self.entries()?
+            .into_iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+            .ok_or_else(|| anyhow!("unknown config key `{key}`"))
+    }
+
+    /// Parse `value` as the type of `key` and store it. Lists are written
+    /// comma-separated and an empty value clears optional settings.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let current = self.get(key)?;
+        if READ_ONLY_KEYS.contains(&key) {
+            bail!("`{key}` can't be changed");
+        }
+
+        let parsed = match current {
+            Value::Bool(_) => Value::Bool(
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("`{key}` must be true or false"))?,
+            ),
+            Value::Number(_) => Value::Number(
+                value
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("`{key}` must be a non-negative integer"))?
+                    .into(),
+            ),
+            Value::Array(_) => Value::Array(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            ),
+            Value::Null | Value::String(_) if value.is_empty() => Value::Null,
+            _ => Value::String(value.to_string()),
+        };
+
+        let mut updated = serde_json::to_value(&*self)?;
+        updated[key] = parsed;
+        *self = serde_json::from_value(updated).with_context(|| format!("invalid value for `{key}`"))?;
+        Ok(())
+    }
+
+    pub fn identity(&self) -> ActorIdentity {
+        let non_empty = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+        ActorIdentity {
+            actor_id: self.actor_id.clone(),
+            name: non_empty(&self.actor_name),
+            email: non_empty(&self.actor_email),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn loads_partial_configs_and_keeps_unknown_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            ForgeConfig::path(temp_dir.path()),
+            r#"{ "real_time_sync": true, "custom": 7 }"#,
+        )
+        .unwrap();
+
+        let config = ForgeConfig::load(temp_dir.path()).unwrap();
+        assert!(config.actor_id.is_empty());
+        assert!(config.real_time_sync);
+        assert_eq!(config.max_tracked_bytes, DEFAULT_MAX_TRACKED_BYTES);
+
+        config.save(temp_dir.path()).unwrap();
+        let saved = ForgeConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(saved.extra["custom"], 7);
+        assert_eq!(saved, config);
+    }
+
+    #[test]
+    fn set_validates_types_and_keys() {
+        let mut config = ForgeConfig::default();
+        config.set("real_time_sync", "true").unwrap();
+        config.set("max_tracked_bytes", "2048").unwrap();
+        config.set("ignore_globs", "*.log, dist/**").unwrap();
+        config.set("actor_name", "Alice").unwrap();
+        assert!(config.real_time_sync);
+        assert_eq!(config.max_tracked_bytes, 2048);
+        assert_eq!(config.ignore_globs, vec!["*.log", "dist/**"]);
+        assert_eq!(config.get("actor_name").unwrap(), "Alice");
+
+        assert!(config.set("real_time_sync", "yes").is_err());
+        assert!(config.set("checkpoint_interval", "-1").is_err());
+        assert!(config.set("no_such_key", "1").is_err());
+        assert!(config.set("actor_id", "someone-else").is_err());
+        assert!(config.get("no_such_key").is_err());
+        assert!(config.set("hash_algorithm", "md5").is_err());
+        config.set("hash_algorithm", "sha256").unwrap();
+        config.set("watch_profile", "accuracy").unwrap();
+        assert_eq!(config.watch_profile, WatchProfile::Accuracy);
+        assert!(config.set("watch_profile", "fast").is_err());
+    }
+}
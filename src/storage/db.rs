@@ -1,25 +1,144 @@
 use anyhow::Result;
-use parking_lot::Mutex;
-use rusqlite::{Connection, params};
-use std::path::Path;
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex, MutexGuard};
+use rusqlite::{Connection, Row, params};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use uuid::Uuid;
 
-use crate::crdt::{Anchor, Operation};
+use super::hash;
+use super::identity::ActorIdentity;
+use super::store::{OperationStore, REPLAY_BATCH_SIZE};
+use crate::crdt::{Anchor, Operation, OperationType};
 
+/// Criteria for selecting operations out of the log. Unset fields don't
+/// constrain the query.
+#[derive(Debug, Clone, Default)]
+pub struct QueryFilter {
+    pub file: Option<PathBuf>,
+    pub actor_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+/// Full file content captured at a specific operation, used as a starting
+/// point for replay.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub file_path: String,
+    pub op_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub content_hash: String,
+    pub content: String,
+}
+
+impl Checkpoint {
+    pub fn new(
+        file_path: String,
+        op_id: Uuid,
+        timestamp: DateTime<Utc>,
+        content: String,
+    ) -> Result<Self> {
+        let content_hash = hash::digest(content.as_bytes())?;
+        Ok(Self {
+            file_path,
+            op_id,
+            timestamp,
+            content_hash,
+            content,
+        })
+    }
+}
+
+/// Latest state of a file as recorded in the operation log.
+#[derive(Debug, Clone)]
+pub struct FileSummary {
+    pub file_path: String,
+    /// Whether the file's most recent change deleted it or renamed it away.
+    pub deleted: bool,
+    pub last_changed: DateTime<Utc>,
+    pub operation_count: usize,
+}
+
+/// The last local operation a peer acknowledged, by its place in replay
+/// order. One actor's operations are stamped in the order they're made, so
+/// everything that actor recorded since comes after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncMarker {
+    pub op_id: Uuid,
+    pub order_stamp: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Size of the operation log and how far it has outgrown its checkpoints.
+#[derive(Debug, Clone, Default)]
+pub struct DbStats {
+    pub total_operations: usize,
+    /// Bytes used by the main database file, not counting the WAL.
+    pub db_size_bytes: u64,
+    /// Files by the number of operations recorded after their latest
+    /// checkpoint, most first. That's how many operations reconstructing the
+    /// file's current content has to replay. Files with none are left out.
+    pub replay_costs: Vec<(String, usize)>,
+}
+
+/// Number of read-only connections opened alongside the writer.
+const READ_POOL_SIZE: usize = 4;
+
+/// How long a connection waits on a locked database before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Rows read per page while filling in a column added by `migrate`.
+const BACKFILL_BATCH_SIZE: usize = 1_000;
+
+/// SQLite-backed store. All writes go through `conn`; queries are served by
+/// a small pool of reader connections, which WAL mode lets run alongside the
+/// writer instead of queueing behind it.
 pub struct Database {
     pub conn: Arc<Mutex<Connection>>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
 }
 
 impl Database {
     pub fn new(forge_path: &Path) -> Result<Self> {
         let db_path = forge_path.join("forge.db");
-        let conn = Connection::open(db_path)?;
+        let conn = Connection::open(&db_path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+            row.get::<_, String>(0)
+        })?;
+        migrate(&conn)?;
+
+        let readers = (0..READ_POOL_SIZE)
+            .map(|_| -> Result<Mutex<Connection>> {
+                let reader = Connection::open(&db_path)?;
+                reader.busy_timeout(BUSY_TIMEOUT)?;
+                reader.pragma_update(None, "query_only", true)?;
+                Ok(Mutex::new(reader))
+            })
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
+            readers,
+            next_reader: AtomicUsize::new(0),
         })
     }
 
+    /// A reader connection, preferring one that is idle.
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        if let Some(guard) = self.readers.iter().find_map(|reader| reader.try_lock()) {
+            return guard;
+        }
+        let next = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.readers[next].lock()
+    }
+
     pub fn open(forge_path: &str) -> Result<Self> {
         Self::new(Path::new(forge_path))
     }
@@ -35,7 +154,9 @@ impl Database {
                 file_path TEXT NOT NULL,
                 op_type TEXT NOT NULL,
                 op_data BLOB NOT NULL,
-                parent_ops TEXT
+                parent_ops TEXT,
+                lamport INTEGER NOT NULL DEFAULT 0,
+                order_stamp INTEGER NOT NULL DEFAULT 0
             )",
             [],
         )?;
@@ -74,6 +195,12 @@ impl Database {
             [],
         )?;
 
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_ops_replay
+             ON operations(order_stamp, timestamp, actor_id, id)",
+            [],
+        )?;
+
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_anchors_file
              ON anchors(file_path)",
@@ -86,79 +213,650 @@ impl Database {
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS checkpoints (
+                file_path TEXT NOT NULL,
+                op_id TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                content_hash TEXT NOT NULL,
+                blob BLOB NOT NULL,
+                PRIMARY KEY(file_path, op_id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_checkpoints_file_time
+             ON checkpoints(file_path, timestamp)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS blobs (
+                hash TEXT PRIMARY KEY,
+                data BLOB NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sync_state (
+                peer_id TEXT PRIMARY KEY,
+                last_acked_op_id TEXT NOT NULL,
+                last_acked_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                last_acked_stamp INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
+        // Operations set aside by `forge fsck --repair`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS quarantined_operations (
+                id TEXT PRIMARY KEY,
+                timestamp TEXT NOT NULL,
+                actor_id TEXT NOT NULL,
+                file_path TEXT NOT NULL,
+                op_type TEXT NOT NULL,
+                op_data BLOB NOT NULL,
+                parent_ops TEXT,
+                reason TEXT NOT NULL,
+                quarantined_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS actors (
+                actor_id TEXT PRIMARY KEY,
+                name TEXT,
+                email TEXT,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Searchable copy of inserted text. Prefer an FTS5 trigram index so
+        // substring `LIKE` scans are index-assisted; fall back to a plain table
+        // with the same shape when SQLite was built without FTS5.
+        let fts_exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'operations_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+
+        if !fts_exists {
+            let created_fts = conn
+                .execute(
+                    "CREATE VIRTUAL TABLE operations_fts USING fts5(
+                        op_id UNINDEXED,
+                        file_path UNINDEXED,
+                        content,
+                        tokenize = 'trigram'
+                    )",
+                    [],
+                )
+                .is_ok();
+
+            if !created_fts {
+                conn.execute(
+                    "CREATE TABLE operations_fts (
+                        op_id TEXT PRIMARY KEY,
+                        file_path TEXT NOT NULL,
+                        content TEXT NOT NULL
+                    )",
+                    [],
+                )?;
+            }
+
+            // Backfill content for operations recorded before the index existed
+            index_content(&conn)?;
+        }
+
         Ok(())
     }
 
+    /// Drop and repopulate the content search index from the operations
+    /// table. Returns the number of operations indexed.
+    pub fn rebuild_search_index(&self) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM operations_fts", [])?;
+        let indexed = index_content(&tx)?;
+        tx.commit()?;
+        Ok(indexed)
+    }
+
     pub fn store_operation(&self, op: &Operation) -> Result<bool> {
+        insert_operation(&self.conn.lock(), op)
+    }
+
+    /// Store `ops` in a single transaction. Returns whether each was new.
+    pub fn append_many(&self, ops: &[Operation]) -> Result<Vec<bool>> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let stored = ops
+            .iter()
+            .map(|op| insert_operation(&tx, op))
+            .collect::<Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    pub fn has_operation(&self, id: &Uuid) -> Result<bool> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT 1 FROM operations WHERE id = ?1")?;
+        Ok(stmt.exists(params![id.to_string()])?)
+    }
+
+    /// The stored operation with id `id`, if any.
+    pub fn operation(&self, id: &Uuid) -> Result<Option<Operation>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport
+             FROM operations WHERE id = ?1",
+        )?;
+        let mut rows = stmt.query_map(params![id.to_string()], row_to_operation)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Operations matching `filter`, oldest first.
+    pub fn query_operations(&self, filter: &QueryFilter) -> Result<Vec<Operation>> {
+        let conn = self.reader();
+        let (where_clause, mut values) = filter_clause(filter);
+
+        let mut query = String::from(
+            "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport FROM operations",
+        );
+        query.push_str(&where_clause);
+        query.push_str(" ORDER BY timestamp ASC");
+        if let Some(limit) = filter.limit {
+            values.push(Box::new(limit as i64));
+            query.push_str(&format!(" LIMIT ?{}", values.len()));
+        }
+
+        let mut stmt = conn.prepare_cached(&query)?;
+        let ops = stmt
+            .query_map(
+                rusqlite::params_from_iter(values.iter().map(|v| v.as_ref())),
+                row_to_operation,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ops)
+    }
+
+    /// Operations stored after `op_id`, in the order they were stored, for a
+    /// live subscriber picking up what it missed. Empty if `op_id` isn't
+    /// stored.
+    pub fn operations_stored_after(&self, op_id: &Uuid) -> Result<Vec<Operation>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport
+             FROM operations
+             WHERE rowid > (SELECT rowid FROM operations WHERE id = ?1)
+             ORDER BY rowid",
+        )?;
+        let ops = stmt
+            .query_map(params![op_id.to_string()], row_to_operation)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ops)
+    }
+
+    /// Every operation on `file_path` in the order replay applies them
+    /// (`Operation::order_key`): by lamport clock, falling back to the
+    /// recorded timestamp only for operations without one, then actor and id.
+    /// Unlike `ORDER BY timestamp`, peers holding the same operations always
+    /// agree on this order, however their clocks drifted.
+    pub fn operations_causal_order(&self, file_path: &str) -> Result<Vec<Operation>> {
+        let filter = QueryFilter {
+            file: Some(file_path.into()),
+            ..Default::default()
+        };
+        OperationStore::query_operations_causal(self, &filter)
+    }
+
+    /// Operations matching `filter`, oldest first, read `batch_size` rows at
+    /// a time so a long history never has to fit in memory. Pages are keyed
+    /// by `(timestamp, id)`, which also breaks ties between operations
+    /// sharing a timestamp.
+    #[allow(dead_code)]
+    pub fn iter_operations<'a>(
+        &'a self,
+        filter: &QueryFilter,
+        batch_size: usize,
+    ) -> impl Iterator<Item = Result<Operation>> + 'a {
+        OperationPages::new(self, filter, batch_size, PageOrder::Recorded)
+    }
+
+    /// Like [`iter_operations`](Self::iter_operations), in replay order
+    /// (`Operation::order_key`) instead. The order is kept in the stored
+    /// `order_stamp` column, so it's sorted by SQLite a page at a time.
+    pub fn iter_operations_causal<'a>(
+        &'a self,
+        filter: &QueryFilter,
+        batch_size: usize,
+    ) -> impl Iterator<Item = Result<Operation>> + 'a {
+        OperationPages::new(self, filter, batch_size, PageOrder::Replay)
+    }
+
+    /// Like [`iter_operations_causal`](Self::iter_operations_causal),
+    /// starting just after `after` in replay order.
+    pub fn iter_operations_causal_after<'a>(
+        &'a self,
+        filter: &QueryFilter,
+        after: &Operation,
+        batch_size: usize,
+    ) -> impl Iterator<Item = Result<Operation>> + 'a {
+        let mut pages = OperationPages::new(self, filter, batch_size, PageOrder::Replay);
+        pages.cursor = Some(PageOrder::Replay.cursor(after));
+        pages
+    }
+
+    /// Number of operations matching `filter`. The filter's limit is ignored.
+    #[allow(dead_code)]
+    pub fn count_operations(&self, filter: &QueryFilter) -> Result<usize> {
+        let conn = self.reader();
+        let (where_clause, values) = filter_clause(filter);
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM operations{where_clause}"),
+            rusqlite::params_from_iter(values.iter().map(|v| v.as_ref())),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    /// Operations of the given kinds (`OperationType` variant names, e.g.
+    /// `"FileRename"`) recorded at or before `until`, optionally for a single
+    /// file, oldest first.
+    pub fn operations_of_types(
+        &self,
+        op_types: &[&str],
+        file: Option<&str>,
+        until: DateTime<Utc>,
+    ) -> Result<Vec<Operation>> {
+        let conn = self.reader();
+        let placeholders = (3..3 + op_types.len())
+            .map(|i| format!("?{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport FROM operations
+             WHERE (?1 IS NULL OR file_path = ?1) AND timestamp <= ?2
+               AND rtrim(op_type) IN ({placeholders})
+             ORDER BY timestamp ASC"
+        ))?;
+
+        let until = until.to_rfc3339();
+        let mut values: Vec<&dyn rusqlite::ToSql> = vec![&file, &until];
+        values.extend(op_types.iter().map(|t| t as &dyn rusqlite::ToSql));
+        let ops = stmt
+            .query_map(values.as_slice(), row_to_operation)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ops)
+    }
+
+    /// One entry per file with history, most recently changed first.
+    /// Directories aren't files and are left out.
+    pub fn file_summaries(&self) -> Result<Vec<FileSummary>> {
+        self.file_summaries_until(None)
+    }
+
+    /// [`file_summaries`](Self::file_summaries) as of `at`, counting only
+    /// the operations recorded by then.
+    pub fn file_summaries_at(&self, at: DateTime<Utc>) -> Result<Vec<FileSummary>> {
+        self.file_summaries_until(Some(at))
+    }
+
+    fn file_summaries_until(&self, until: Option<DateTime<Utc>>) -> Result<Vec<FileSummary>> {
+        let conn = self.reader();
+        let until = until.map(|at| at.to_rfc3339());
+        // SQLite returns the bare columns from the row holding MAX(timestamp).
+        let mut stmt = conn.prepare(
+            "SELECT file_path, op_type, MAX(timestamp), COUNT(*) FROM operations
+             WHERE rtrim(op_type) NOT IN ('DirCreate', 'DirDelete', 'DirRename')
+               AND (?1 IS NULL OR timestamp <= ?1)
+             GROUP BY file_path ORDER BY MAX(timestamp) DESC",
+        )?;
+        let mut summaries = stmt
+            .query_map(params![until], |row| {
+                let op_type: String = row.get(1)?;
+                let timestamp: String = row.get(2)?;
+                let count: i64 = row.get(3)?;
+                Ok(FileSummary {
+                    file_path: row.get(0)?,
+                    deleted: op_type.trim() == "FileDelete",
+                    last_changed: DateTime::parse_from_rfc3339(&timestamp)
+                        .map(|t| t.with_timezone(&Utc))
+                        .unwrap_or_default(),
+                    operation_count: count as usize,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // A rename is recorded under its new path, so the path it left is
+        // only marked gone here: when nothing touched it after the rename
+        let mut renamed_away: HashMap<String, DateTime<Utc>> = HashMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport FROM operations
+             WHERE rtrim(op_type) = 'FileRename' AND (?1 IS NULL OR timestamp <= ?1)",
+        )?;
+        for op in stmt.query_map(params![until], row_to_operation)? {
+            let op = op?;
+            if let OperationType::FileRename { old_path, .. } = op.op_type {
+                let renamed_at = renamed_away.entry(old_path).or_insert(op.timestamp);
+                *renamed_at = (*renamed_at).max(op.timestamp);
+            }
+        }
+        if !renamed_away.is_empty() {
+            for summary in &mut summaries {
+                if let Some(&renamed_at) = renamed_away.get(&summary.file_path)
+                    && summary.last_changed < renamed_at
+                {
+                    summary.deleted = true;
+                    summary.last_changed = renamed_at;
+                }
+            }
+            summaries.sort_by_key(|summary| std::cmp::Reverse(summary.last_changed));
+        }
+        Ok(summaries)
+    }
+
+    /// How big the log is and how much replay each file's history costs.
+    pub fn stats(&self) -> Result<DbStats> {
+        let conn = self.reader();
+        let total_operations: i64 =
+            conn.query_row("SELECT COUNT(*) FROM operations", [], |row| row.get(0))?;
+        let db_size_bytes: i64 = conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT o.file_path, COUNT(*) FROM operations o
+             LEFT JOIN (
+                 SELECT file_path, MAX(timestamp) AS timestamp FROM checkpoints GROUP BY file_path
+             ) c ON c.file_path = o.file_path
+             WHERE c.timestamp IS NULL OR o.timestamp > c.timestamp
+             GROUP BY o.file_path
+             ORDER BY COUNT(*) DESC, o.file_path",
+        )?;
+        let replay_costs = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(DbStats {
+            total_operations: total_operations as usize,
+            db_size_bytes: db_size_bytes as u64,
+            replay_costs,
+        })
+    }
+
+    /// The newest operation for each file that still exists, i.e. the parent
+    /// the next local operation on that file should chain to.
+    pub fn file_heads(&self) -> Result<HashMap<String, Uuid>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT file_path, id, op_type, MAX(timestamp) FROM operations GROUP BY file_path",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows
+            .into_iter()
+            .filter(|(_, _, op_type)| op_type.trim() != "FileDelete")
+            .filter_map(|(file_path, id, _)| Some((file_path, Uuid::parse_str(&id).ok()?)))
+            .collect())
+    }
+
+    pub fn store_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
         let conn = self.conn.lock();
-        let op_data = bincode::serialize(&op.op_type)?;
-        let parent_ops = serde_json::to_string(&op.parent_ops)?;
+        let blob = lz4::block::compress(checkpoint.content.as_bytes(), None, true)?;
 
         conn.execute(
-            "INSERT OR IGNORE INTO operations (id, timestamp, actor_id, file_path, op_type, op_data, parent_ops)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            "INSERT OR REPLACE INTO checkpoints (file_path, op_id, timestamp, content_hash, blob)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
             params![
-                op.id.to_string(),
-                op.timestamp.to_rfc3339(),
-                op.actor_id,
-                op.file_path,
-                format!("{:?}", op.op_type).split('{').next().unwrap(),
-                op_data,
-                parent_ops,
+                checkpoint.file_path,
+                checkpoint.op_id.to_string(),
+                checkpoint.timestamp.to_rfc3339(),
+                checkpoint.content_hash,
+                blob,
             ],
-        )
-        .map(|changes| changes > 0)
-        .map_err(Into::into)
+        )?;
+
+        Ok(())
     }
 
-    pub fn get_operations(&self, file: Option<&Path>, limit: usize) -> Result<Vec<Operation>> {
+    /// Store binary file content under its `hash`, compressed. Content is
+    /// addressed by hash, so storing the same bytes again is a no-op.
+    pub fn store_blob(&self, hash: &str, data: &[u8]) -> Result<()> {
         let conn = self.conn.lock();
+        let blob = lz4::block::compress(data, None, true)?;
+
+        conn.execute(
+            "INSERT OR IGNORE INTO blobs (hash, data) VALUES (?1, ?2)",
+            params![hash, blob],
+        )?;
+
+        Ok(())
+    }
+
+    /// Binary content stored under `hash`, if any.
+    pub fn blob(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT data FROM blobs WHERE hash = ?1")?;
+        let mut rows = stmt.query(params![hash])?;
+
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        let blob: Vec<u8> = row.get(0)?;
+        Ok(Some(lz4::block::decompress(&blob, None)?))
+    }
+
+    /// The newest checkpoint for `file_path` taken at or before `at`.
+    pub fn latest_checkpoint(&self, file_path: &str, at: DateTime<Utc>) -> Result<Option<Checkpoint>> {
+        let conn = self.reader();
+        // Newest in replay order, which a clock stepped back can make differ
+        // from the newest by timestamp
+        let mut stmt = conn.prepare_cached(
+            "SELECT c.op_id, c.timestamp, c.content_hash, c.blob
+             FROM checkpoints c JOIN operations o ON o.id = c.op_id
+             WHERE c.file_path = ?1 AND c.timestamp <= ?2
+             ORDER BY o.order_stamp DESC, o.timestamp DESC, o.actor_id DESC, o.id DESC
+             LIMIT 1",
+        )?;
+        let mut rows = stmt.query(params![file_path, at.to_rfc3339()])?;
+
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        let op_id: String = row.get(0)?;
+        let timestamp: String = row.get(1)?;
+        let content_hash: String = row.get(2)?;
+        let blob: Vec<u8> = row.get(3)?;
+
+        let content = String::from_utf8(lz4::block::decompress(&blob, None)?)?;
 
-        let query = if let Some(f) = file {
-            format!(
-                "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops
+        Ok(Some(Checkpoint {
+            file_path: file_path.to_string(),
+            op_id: Uuid::parse_str(&op_id)?,
+            timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+            content_hash,
+            content,
+        }))
+    }
+
+    /// The last local operation `peer_id` has received.
+    pub fn sync_marker(&self, peer_id: &str) -> Result<Option<SyncMarker>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT last_acked_op_id, last_acked_stamp, last_acked_at FROM sync_state
+             WHERE peer_id = ?1",
+        )?;
+        let mut rows = stmt.query(params![peer_id])?;
+
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        let op_id: String = row.get(0)?;
+        let order_stamp: i64 = row.get(1)?;
+        let acked_at: String = row.get(2)?;
+
+        Ok(Some(SyncMarker {
+            op_id: Uuid::parse_str(&op_id)?,
+            order_stamp: order_stamp as u64,
+            timestamp: DateTime::parse_from_rfc3339(&acked_at)?.with_timezone(&Utc),
+        }))
+    }
+
+    /// Advance the sync position for `peer_id` to `op`. Markers never move
+    /// backwards in replay order, so out-of-order deliveries don't cause ops
+    /// to be skipped.
+    pub fn set_sync_marker(&self, peer_id: &str, op: &Operation) -> Result<()> {
+        let conn = self.conn.lock();
+        // Runs once per op sent to a peer, so keep the statement cached
+        conn.prepare_cached(
+            "INSERT INTO sync_state (peer_id, last_acked_op_id, last_acked_stamp, last_acked_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(peer_id) DO UPDATE SET
+                last_acked_op_id = excluded.last_acked_op_id,
+                last_acked_stamp = excluded.last_acked_stamp,
+                last_acked_at = excluded.last_acked_at,
+                updated_at = excluded.updated_at
+             WHERE (excluded.last_acked_stamp, excluded.last_acked_at, excluded.last_acked_op_id)
+                >= (sync_state.last_acked_stamp, sync_state.last_acked_at, sync_state.last_acked_op_id)",
+        )?
+        .execute(params![
+            peer_id,
+            op.id.to_string(),
+            op.order_key().0 as i64,
+            op.timestamp.to_rfc3339(),
+            Utc::now().to_rfc3339(),
+        ])?;
+
+        Ok(())
+    }
+
+    /// Operations by `actor_id` that come after `marker` in replay order, or
+    /// all of them without one.
+    pub fn operations_after_marker(
+        &self,
+        actor_id: &str,
+        marker: Option<&SyncMarker>,
+    ) -> Result<Vec<Operation>> {
+        let filter = QueryFilter {
+            actor_id: Some(actor_id.to_string()),
+            ..Default::default()
+        };
+        let mut pages = OperationPages::new(self, &filter, REPLAY_BATCH_SIZE, PageOrder::Replay);
+        pages.cursor = marker.map(|marker| -> Vec<Box<dyn rusqlite::ToSql>> {
+            vec![
+                Box::new(marker.order_stamp as i64),
+                Box::new(marker.timestamp.to_rfc3339()),
+                Box::new(actor_id.to_string()),
+                Box::new(marker.op_id.to_string()),
+            ]
+        });
+        pages.collect()
+    }
+
+    /// Remember the display name and email for an actor, replacing what was
+    /// known before.
+    pub fn record_actor(&self, identity: &ActorIdentity) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "INSERT INTO actors (actor_id, name, email, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(actor_id) DO UPDATE SET
+                name = excluded.name,
+                email = excluded.email,
+                updated_at = excluded.updated_at",
+            params![
+                identity.actor_id,
+                identity.name,
+                identity.email,
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn actor(&self, actor_id: &str) -> Result<Option<ActorIdentity>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare("SELECT actor_id, name, email FROM actors WHERE actor_id = ?1")?;
+        let mut rows = stmt.query_map(params![actor_id], row_to_actor)?;
+        Ok(rows.next().transpose()?)
+    }
+
+    /// Every known actor keyed by id.
+    pub fn actors(&self) -> Result<HashMap<String, ActorIdentity>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare("SELECT actor_id, name, email FROM actors")?;
+        let actors = stmt
+            .query_map([], row_to_actor)?
+            .map(|actor| actor.map(|actor| (actor.actor_id.clone(), actor)))
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(actors)
+    }
+
+    /// Find operations whose inserted or replacement text contains `needle`.
+    /// Matching is a case-insensitive substring match, newest first.
+    pub fn search_content(&self, needle: &str, limit: usize) -> Result<Vec<Operation>> {
+        let conn = self.reader();
+        let pattern = format!("%{}%", escape_like(needle));
+
+        let mut stmt = conn.prepare(
+            "SELECT o.id, o.timestamp, o.actor_id, o.file_path, o.op_data, o.parent_ops, o.lamport
+             FROM operations_fts f
+             JOIN operations o ON o.id = f.op_id
+             WHERE f.content LIKE ?1 ESCAPE '\\'
+             ORDER BY o.timestamp DESC
+             LIMIT ?2",
+        )?;
+        let ops = stmt
+            .query_map(params![pattern, limit as i64], row_to_operation)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ops)
+    }
+
+    pub fn get_operations(&self, file: Option<&Path>, limit: usize) -> Result<Vec<Operation>> {
+        let conn = self.reader();
+        let limit = limit as i64;
+
+        let ops = if let Some(f) = file {
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport
                  FROM operations
-                 WHERE file_path = '{}'
+                 WHERE file_path = ?1
                  ORDER BY timestamp DESC
-                 LIMIT {}",
-                f.display(),
-                limit
-            )
+                 LIMIT ?2",
+            )?;
+            stmt.query_map(params![f.display().to_string(), limit], row_to_operation)?
+                .collect::<Result<Vec<_>, _>>()?
         } else {
-            format!(
-                "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport
                  FROM operations
                  ORDER BY timestamp DESC
-                 LIMIT {}",
-                limit
-            )
+                 LIMIT ?1",
+            )?;
+            stmt.query_map(params![limit], row_to_operation)?
+                .collect::<Result<Vec<_>, _>>()?
         };
 
-        let mut stmt = conn.prepare(&query)?;
-        let ops = stmt.query_map([], |row| {
-            let id: String = row.get(0)?;
-            let timestamp: String = row.get(1)?;
-            let actor_id: String = row.get(2)?;
-            let file_path: String = row.get(3)?;
-            let op_data: Vec<u8> = row.get(4)?;
-            let parent_ops: String = row.get(5)?;
-
-            let op_type = bincode::deserialize(&op_data).unwrap();
-            let parents: Vec<uuid::Uuid> = serde_json::from_str(&parent_ops).unwrap();
-
-            Ok(Operation {
-                id: uuid::Uuid::parse_str(&id).unwrap(),
-                timestamp: chrono::DateTime::parse_from_rfc3339(&timestamp)
-                    .unwrap()
-                    .into(),
-                actor_id,
-                file_path,
-                op_type,
-                parent_ops: parents,
-            })
-        })?;
-
-        Ok(ops.collect::<Result<Vec<_>, _>>()?)
+        Ok(ops)
     }
 
     pub fn store_anchor(&self, anchor: &Anchor) -> Result<()> {
@@ -182,4 +880,827 @@ impl Database {
 
         Ok(())
     }
+
+    pub fn anchor(&self, id: &Uuid) -> Result<Option<Anchor>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT file_path, stable_id, position, created_at, message, tags
+             FROM anchors WHERE id = ?1",
+        )?;
+        let mut rows = stmt.query(params![id.to_string()])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+
+        let position: Vec<u8> = row.get(2)?;
+        let created_at: String = row.get(3)?;
+        let tags: Option<String> = row.get(5)?;
+        Ok(Some(Anchor {
+            id: *id,
+            position: bincode::deserialize(&position)?,
+            stable_id: row.get(1)?,
+            file_path: row.get(0)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+            message: row.get(4)?,
+            tags: tags
+                .map(|tags| serde_json::from_str(&tags))
+                .transpose()?
+                .unwrap_or_default(),
+        }))
+    }
+}
+
+/// Index the searchable content of every stored operation.
+fn index_content(conn: &Connection) -> Result<usize> {
+    let mut stmt = conn.prepare("SELECT id, file_path, op_data FROM operations")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Vec<u8>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut indexed = 0;
+    for (id, file_path, op_data) in rows {
+        let Ok(op_type) = bincode::deserialize::<OperationType>(&op_data) else {
+            continue;
+        };
+        if let Some(content) = searchable_content(&op_type) {
+            conn.execute(
+                "INSERT INTO operations_fts (op_id, file_path, content) VALUES (?1, ?2, ?3)",
+                params![id, file_path, content],
+            )?;
+            indexed += 1;
+        }
+    }
+    Ok(indexed)
+}
+
+/// Bring a database created by an older version up to the current schema.
+/// Runs on open, since not every command calls `initialize`.
+fn migrate(conn: &Connection) -> Result<()> {
+    // Operations didn't always carry their own clock value
+    let missing_lamport: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'operations')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('operations') WHERE name = 'lamport')",
+        [],
+        |row| row.get(0),
+    )?;
+    if missing_lamport {
+        conn.execute(
+            "ALTER TABLE operations ADD COLUMN lamport INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+    }
+
+    // Nor their replay order, which is worked out from each stored operation
+    let missing_order_stamp: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'operations')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('operations') WHERE name = 'order_stamp')",
+        [],
+        |row| row.get(0),
+    )?;
+    if missing_order_stamp {
+        // One transaction, so an interrupted upgrade never leaves the column
+        // in place with only some rows filled in
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "ALTER TABLE operations ADD COLUMN order_stamp INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        let mut select = tx.prepare(
+            "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport, rowid
+             FROM operations WHERE rowid > ?1 ORDER BY rowid LIMIT ?2",
+        )?;
+        let mut update = tx.prepare("UPDATE operations SET order_stamp = ?1 WHERE id = ?2")?;
+        let mut after = 0i64;
+        loop {
+            let page = select
+                .query_map(params![after, BACKFILL_BATCH_SIZE as i64], |row| {
+                    Ok((row_to_operation(row)?, row.get::<_, i64>(7)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            let Some((_, last)) = page.last() else {
+                break;
+            };
+            after = *last;
+            for (op, _) in &page {
+                update.execute(params![op.order_key().0 as i64, op.id.to_string()])?;
+            }
+        }
+        drop((select, update));
+        tx.commit()?;
+    }
+
+    // Sync markers didn't record the replay position of the acked operation
+    let missing_marker_stamp: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'sync_state')
+             AND NOT EXISTS(SELECT 1 FROM pragma_table_info('sync_state') WHERE name = 'last_acked_stamp')",
+        [],
+        |row| row.get(0),
+    )?;
+    if missing_marker_stamp {
+        let tx = conn.unchecked_transaction()?;
+        tx.execute(
+            "ALTER TABLE sync_state ADD COLUMN last_acked_stamp INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        tx.execute(
+            "UPDATE sync_state SET last_acked_stamp = COALESCE(
+                 (SELECT order_stamp FROM operations WHERE id = last_acked_op_id), 0)",
+            [],
+        )?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Insert `op` and index its content through `conn`, which may be a
+/// transaction. Returns false if the operation was already stored.
+pub(super) fn insert_operation(conn: &Connection, op: &Operation) -> Result<bool> {
+    let op_data = bincode::serialize(&op.op_type)?;
+    let parent_ops = serde_json::to_string(&op.parent_ops)?;
+
+    let inserted = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO operations (id, timestamp, actor_id, file_path, op_type, op_data, parent_ops, lamport, order_stamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?
+        .execute(params![
+            op.id.to_string(),
+            op.timestamp.to_rfc3339(),
+            op.actor_id,
+            op.file_path,
+            format!("{:?}", op.op_type).split('{').next().unwrap(),
+            op_data,
+            parent_ops,
+            op.lamport as i64,
+            op.order_key().0 as i64,
+        ])?
+        > 0;
+
+    if inserted && let Some(content) = searchable_content(&op.op_type) {
+        conn.prepare_cached(
+            "INSERT INTO operations_fts (op_id, file_path, content) VALUES (?1, ?2, ?3)",
+        )?
+        .execute(params![op.id.to_string(), op.file_path, content])?;
+    }
+
+    Ok(inserted)
+}
+
+/// Text worth indexing for content search. Deletes and renames carry no new
+/// text, so only inserts, replacements and file creations are indexed.
+fn searchable_content(op_type: &OperationType) -> Option<&str> {
+    match op_type {
+        OperationType::Insert { content, .. } => Some(content),
+        OperationType::Replace { new_content, .. } => Some(new_content),
+        OperationType::FileCreate { content } => Some(content),
+        _ => None,
+    }
+}
+
+fn escape_like(needle: &str) -> String {
+    let mut escaped = String::with_capacity(needle.len());
+    for ch in needle.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Translate `filter` into a ` WHERE ...` clause (empty if unconstrained)
+/// and its positional parameters.
+fn filter_clause(filter: &QueryFilter) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
+    let mut clauses = Vec::new();
+    let mut values: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+    if let Some(file) = &filter.file {
+        values.push(Box::new(file.display().to_string()));
+        clauses.push(format!("file_path = ?{}", values.len()));
+    }
+    if let Some(actor_id) = &filter.actor_id {
+        values.push(Box::new(actor_id.clone()));
+        clauses.push(format!("actor_id = ?{}", values.len()));
+    }
+    if let Some(since) = &filter.since {
+        values.push(Box::new(since.to_rfc3339()));
+        clauses.push(format!("timestamp >= ?{}", values.len()));
+    }
+    if let Some(until) = &filter.until {
+        values.push(Box::new(until.to_rfc3339()));
+        clauses.push(format!("timestamp <= ?{}", values.len()));
+    }
+
+    if clauses.is_empty() {
+        (String::new(), values)
+    } else {
+        (format!(" WHERE {}", clauses.join(" AND ")), values)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum PageOrder {
+    // By timestamp, then id
+    Recorded,
+    // By `Operation::order_key`
+    Replay,
+}
+
+impl PageOrder {
+    fn columns(self) -> &'static str {
+        match self {
+            PageOrder::Recorded => "timestamp, id",
+            PageOrder::Replay => "order_stamp, timestamp, actor_id, id",
+        }
+    }
+
+    // Values of `columns` for `op`, where the next page resumes
+    fn cursor(self, op: &Operation) -> Vec<Box<dyn rusqlite::ToSql>> {
+        let timestamp = Box::new(op.timestamp.to_rfc3339());
+        let id = Box::new(op.id.to_string());
+        match self {
+            PageOrder::Recorded => vec![timestamp, id],
+            PageOrder::Replay => vec![
+                Box::new(op.order_key().0 as i64),
+                timestamp,
+                Box::new(op.actor_id.clone()),
+                id,
+            ],
+        }
+    }
+}
+
+struct OperationPages<'a> {
+    db: &'a Database,
+    filter: QueryFilter,
+    batch_size: usize,
+    order: PageOrder,
+    // Sort key of the last operation handed out
+    cursor: Option<Vec<Box<dyn rusqlite::ToSql>>>,
+    page: VecDeque<Operation>,
+    remaining: Option<usize>,
+    done: bool,
+}
+
+impl<'a> OperationPages<'a> {
+    fn new(db: &'a Database, filter: &QueryFilter, batch_size: usize, order: PageOrder) -> Self {
+        Self {
+            db,
+            filter: filter.clone(),
+            batch_size: batch_size.max(1),
+            order,
+            cursor: None,
+            page: VecDeque::new(),
+            remaining: filter.limit,
+            done: false,
+        }
+    }
+
+    fn fetch(&mut self) -> Result<()> {
+        let (where_clause, mut values) = filter_clause(&self.filter);
+        let mut query = String::from(
+            "SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport FROM operations",
+        );
+        query.push_str(&where_clause);
+        let columns = self.order.columns();
+        if let Some(cursor) = self.cursor.take() {
+            let first = values.len() + 1;
+            values.extend(cursor);
+            let placeholders = (first..=values.len())
+                .map(|i| format!("?{i}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            query.push_str(if where_clause.is_empty() { " WHERE " } else { " AND " });
+            query.push_str(&format!("({columns}) > ({placeholders})"));
+        }
+        let batch = self.remaining.map_or(self.batch_size, |n| n.min(self.batch_size));
+        values.push(Box::new(batch as i64));
+        let order_by = columns
+            .split(", ")
+            .map(|column| format!("{column} ASC"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        query.push_str(&format!(" ORDER BY {order_by} LIMIT ?{}", values.len()));
+
+        let conn = self.db.reader();
+        let mut stmt = conn.prepare_cached(&query)?;
+        let ops = stmt
+            .query_map(
+                rusqlite::params_from_iter(values.iter().map(|v| v.as_ref())),
+                row_to_operation,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.done = ops.len() < batch;
+        if let Some(last) = ops.last() {
+            self.cursor = Some(self.order.cursor(last));
+        }
+        self.page.extend(ops);
+        Ok(())
+    }
+}
+
+impl Iterator for OperationPages<'_> {
+    type Item = Result<Operation>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        if self.page.is_empty()
+            && !self.done
+            && let Err(err) = self.fetch()
+        {
+            self.done = true;
+            return Some(Err(err));
+        }
+        let op = self.page.pop_front()?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+        Some(Ok(op))
+    }
+}
+
+fn row_to_actor(row: &Row<'_>) -> rusqlite::Result<ActorIdentity> {
+    Ok(ActorIdentity {
+        actor_id: row.get(0)?,
+        name: row.get(1)?,
+        email: row.get(2)?,
+    })
+}
+
+/// Map a `SELECT id, timestamp, actor_id, file_path, op_data, parent_ops, lamport` row
+/// back into an [`Operation`].
+fn row_to_operation(row: &Row<'_>) -> rusqlite::Result<Operation> {
+    let id: String = row.get(0)?;
+    let timestamp: String = row.get(1)?;
+    let actor_id: String = row.get(2)?;
+    let file_path: String = row.get(3)?;
+    let op_data: Vec<u8> = row.get(4)?;
+    let parent_ops: String = row.get(5)?;
+    let lamport: i64 = row.get(6)?;
+
+    let op_type = bincode::deserialize(&op_data).unwrap();
+    let parents: Vec<uuid::Uuid> = serde_json::from_str(&parent_ops).unwrap();
+
+    Ok(Operation {
+        id: uuid::Uuid::parse_str(&id).unwrap(),
+        timestamp: chrono::DateTime::parse_from_rfc3339(&timestamp)
+            .unwrap()
+            .into(),
+        actor_id,
+        file_path,
+        op_type,
+        parent_ops: parents,
+        lamport: lamport as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn get_operations_handles_quotes_in_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let op = Operation::new(
+            "it's.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&op).unwrap();
+
+        let ops = db.get_operations(Some(Path::new("it's.txt")), 10).unwrap();
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].id, op.id);
+    }
+
+    #[test]
+    fn causal_order_ignores_wall_clock_steps_backwards() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let mut create = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: String::new(),
+            },
+            "actor".into(),
+        );
+        create.timestamp -= chrono::Duration::seconds(10);
+        let lamport = crate::sync::GLOBAL_CLOCK.tick();
+        let insert = |offset, content: &str, lamport, seconds_ago| {
+            let mut op = Operation::new(
+                "a.txt".to_string(),
+                OperationType::Insert {
+                    position: crate::crdt::Position::new(1, 1, offset, "actor".into(), lamport),
+                    content: content.into(),
+                    length: content.len(),
+                },
+                "actor".into(),
+            );
+            op.timestamp = Utc::now() - chrono::Duration::seconds(seconds_ago);
+            op
+        };
+        // The clock was stepped back between these two edits
+        let first = insert(0, "a", lamport, 2);
+        let second = insert(1, "b", lamport + 1, 5);
+        for op in [&create, &second, &first] {
+            db.store_operation(op).unwrap();
+        }
+
+        let ids: Vec<Uuid> = db
+            .operations_causal_order("a.txt")
+            .unwrap()
+            .iter()
+            .map(|op| op.id)
+            .collect();
+        assert_eq!(ids, vec![create.id, first.id, second.id]);
+        let content = crate::storage::reconstruct::reconstruct_at(&db, "a.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn migrate_backfills_the_replay_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let ops: Vec<_> = (0..3)
+            .map(|n| {
+                Operation::new(
+                    format!("{n}.txt"),
+                    OperationType::FileCreate {
+                        content: String::new(),
+                    },
+                    "actor".into(),
+                )
+            })
+            .collect();
+        for op in &ops {
+            db.store_operation(op).unwrap();
+        }
+
+        // Roll the schema back to before operations carried their order
+        let conn = db.conn.lock();
+        conn.execute_batch(
+            "DROP INDEX idx_ops_replay;
+             ALTER TABLE operations DROP COLUMN order_stamp;",
+        )
+        .unwrap();
+        migrate(&conn).unwrap();
+
+        for op in &ops {
+            let stamp: i64 = conn
+                .query_row(
+                    "SELECT order_stamp FROM operations WHERE id = ?1",
+                    params![op.id.to_string()],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(stamp as u64, op.order_key().0);
+        }
+    }
+
+    #[test]
+    fn sync_marker_keeps_edits_made_after_a_clock_step() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let edit = |path: &str| {
+            Operation::new(
+                path.to_string(),
+                OperationType::FileCreate {
+                    content: String::new(),
+                },
+                "me".into(),
+            )
+        };
+        let acked = edit("a.txt");
+        // Made after the acked edit, once the clock had been stepped back
+        let mut later = edit("b.txt");
+        later.timestamp = acked.timestamp - chrono::Duration::minutes(5);
+        for op in [&acked, &later] {
+            db.store_operation(op).unwrap();
+        }
+
+        db.set_sync_marker("peer", &acked).unwrap();
+        let marker = db.sync_marker("peer").unwrap().unwrap();
+        assert_eq!(marker.op_id, acked.id);
+        let pending: Vec<Uuid> = db
+            .operations_after_marker("me", Some(&marker))
+            .unwrap()
+            .iter()
+            .map(|op| op.id)
+            .collect();
+        assert_eq!(pending, vec![later.id]);
+
+        // A late ack for the earlier edit doesn't move the marker back
+        db.set_sync_marker("peer", &later).unwrap();
+        db.set_sync_marker("peer", &acked).unwrap();
+        assert_eq!(db.sync_marker("peer").unwrap().unwrap().op_id, later.id);
+    }
+
+    #[test]
+    fn search_content_finds_inserted_text() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let create = Operation::new(
+            "src/lib.rs".to_string(),
+            OperationType::FileCreate {
+                content: "fn main() {}\n".into(),
+            },
+            "actor".into(),
+        );
+        let insert = Operation::new(
+            "src/lib.rs".to_string(),
+            OperationType::Insert {
+                position: crate::crdt::Position::new(2, 1, 13, "actor".into(), 1),
+                content: "// TODO: remove this\n".into(),
+                length: 21,
+            },
+            "actor".into(),
+        );
+        db.store_operation(&create).unwrap();
+        db.store_operation(&insert).unwrap();
+
+        let hits = db.search_content("TODO: remove this", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, insert.id);
+
+        assert!(db.search_content("100%", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_summaries_report_latest_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let create = |path: &str| {
+            Operation::new(
+                path.to_string(),
+                OperationType::FileCreate {
+                    content: "x".into(),
+                },
+                "actor".into(),
+            )
+        };
+        let mut delete =
+            Operation::new("a.txt".to_string(), OperationType::FileDelete, "actor".into());
+        delete.timestamp += chrono::Duration::seconds(1);
+        db.store_operation(&create("a.txt")).unwrap();
+        db.store_operation(&delete).unwrap();
+        db.store_operation(&create("b.txt")).unwrap();
+
+        let summaries = db.file_summaries().unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].file_path, "a.txt");
+        assert!(summaries[0].deleted);
+        assert_eq!(summaries[0].operation_count, 2);
+        assert!(!summaries[1].deleted);
+
+        assert_eq!(db.count_operations(&QueryFilter::default()).unwrap(), 3);
+    }
+
+    #[test]
+    fn file_summaries_mark_renamed_away_paths_gone() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let start = Utc::now() - chrono::Duration::seconds(10);
+        let steps = [
+            (
+                "a.txt",
+                OperationType::FileCreate {
+                    content: "x".into(),
+                },
+            ),
+            (
+                "b.txt",
+                OperationType::FileRename {
+                    old_path: "a.txt".into(),
+                    new_path: "b.txt".into(),
+                },
+            ),
+        ];
+        for (idx, (path, op_type)) in steps.into_iter().enumerate() {
+            let mut op = Operation::new(path.to_string(), op_type, "actor".into());
+            op.timestamp = start + chrono::Duration::seconds(idx as i64);
+            db.store_operation(&op).unwrap();
+        }
+
+        let summaries = db.file_summaries().unwrap();
+        let gone: Vec<_> = summaries
+            .iter()
+            .map(|summary| (summary.file_path.as_str(), summary.deleted))
+            .collect();
+        assert_eq!(gone, [("b.txt", false), ("a.txt", true)]);
+        assert_eq!(summaries[1].last_changed, start + chrono::Duration::seconds(1));
+
+        // Before the rename, a.txt was still there
+        let before = db.file_summaries_at(start).unwrap();
+        assert_eq!(before.len(), 1);
+        assert!(!before[0].deleted);
+    }
+
+    #[test]
+    fn record_actor_replaces_the_known_identity() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        db.record_actor(&ActorIdentity::anonymous("1234")).unwrap();
+        db.record_actor(&ActorIdentity {
+            actor_id: "1234".into(),
+            name: Some("Alice".into()),
+            email: None,
+        })
+        .unwrap();
+
+        let actor = db.actor("1234").unwrap().unwrap();
+        assert_eq!(actor.name.as_deref(), Some("Alice"));
+        assert_eq!(db.actors().unwrap().len(), 1);
+        assert!(db.actor("5678").unwrap().is_none());
+    }
+
+    #[test]
+    fn iter_operations_pages_through_ties() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let at = Utc::now();
+        let mut stored = Vec::new();
+        for i in 0..5 {
+            let mut op = Operation::new(
+                if i % 2 == 0 { "a.txt" } else { "b.txt" }.to_string(),
+                OperationType::FileCreate {
+                    content: i.to_string(),
+                },
+                "actor".into(),
+            );
+            // Pairs of operations share a timestamp
+            op.timestamp = at + chrono::Duration::seconds(i / 2);
+            db.store_operation(&op).unwrap();
+            stored.push(op);
+        }
+        stored.sort_by_key(|op| (op.timestamp, op.id.to_string()));
+
+        let ids = |filter: &QueryFilter| {
+            db.iter_operations(filter, 2)
+                .map(|op| op.unwrap().id)
+                .collect::<Vec<_>>()
+        };
+        let all = ids(&QueryFilter::default());
+        assert_eq!(all, stored.iter().map(|op| op.id).collect::<Vec<_>>());
+
+        let only_a = ids(&QueryFilter {
+            file: Some("a.txt".into()),
+            ..Default::default()
+        });
+        assert_eq!(only_a.len(), 3);
+        let limited = ids(&QueryFilter {
+            limit: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(limited, all[..3]);
+    }
+
+    #[test]
+    fn iter_operations_causal_pages_in_replay_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        // Clocks that disagree with the lamport order, and ties between them
+        let at = Utc::now();
+        for i in 0..7 {
+            let mut op = Operation::new(
+                if i % 2 == 0 { "a.txt" } else { "b.txt" }.to_string(),
+                OperationType::FileCreate {
+                    content: i.to_string(),
+                },
+                "actor".into(),
+            );
+            op.timestamp = at - chrono::Duration::seconds(i / 2);
+            db.store_operation(&op).unwrap();
+        }
+
+        let expected = db
+            .query_operations_causal(&QueryFilter::default())
+            .unwrap()
+            .into_iter()
+            .map(|op| op.id)
+            .collect::<Vec<_>>();
+        let paged = db
+            .iter_operations_causal(&QueryFilter::default(), 2)
+            .map(|op| op.unwrap().id)
+            .collect::<Vec<_>>();
+        assert_eq!(paged, expected);
+    }
+
+    #[test]
+    fn looks_up_operations_and_anchors_by_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let op = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&op).unwrap();
+        let anchor = Anchor::new(
+            "a.txt".into(),
+            crate::crdt::Position::new(1, 2, 1, "actor".into(), 0),
+            Some("here".into()),
+        );
+        db.store_anchor(&anchor).unwrap();
+
+        assert_eq!(db.operation(&op.id).unwrap().unwrap().file_path, "a.txt");
+        assert!(db.operation(&Uuid::new_v4()).unwrap().is_none());
+        let stored = db.anchor(&anchor.id).unwrap().unwrap();
+        assert_eq!(stored.stable_id, anchor.stable_id);
+        assert_eq!(stored.message.as_deref(), Some("here"));
+        assert!(db.anchor(&Uuid::new_v4()).unwrap().is_none());
+    }
+
+    #[test]
+    fn stats_count_operations_since_each_files_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let start = Utc::now() - chrono::Duration::seconds(60);
+        let mut ops = Vec::new();
+        for (idx, file) in ["a.txt", "a.txt", "a.txt", "b.txt"].into_iter().enumerate() {
+            let mut op = Operation::new(
+                file.to_string(),
+                OperationType::FileCreate {
+                    content: idx.to_string(),
+                },
+                "actor".into(),
+            );
+            op.timestamp = start + chrono::Duration::seconds(idx as i64);
+            db.store_operation(&op).unwrap();
+            ops.push(op);
+        }
+        db.store_checkpoint(&Checkpoint::new(
+            "a.txt".into(),
+            ops[0].id,
+            ops[0].timestamp,
+            "0".into(),
+        ).unwrap())
+        .unwrap();
+
+        let stats = db.stats().unwrap();
+        assert_eq!(stats.total_operations, 4);
+        assert!(stats.db_size_bytes > 0);
+        assert_eq!(
+            stats.replay_costs,
+            vec![("a.txt".to_string(), 2), ("b.txt".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn reads_do_not_wait_for_the_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+        let op = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "x".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&op).unwrap();
+
+        // Holding the writer would deadlock this thread if reads shared it.
+        let _writer = db.conn.lock();
+        assert!(db.has_operation(&op.id).unwrap());
+        assert_eq!(db.query_operations(&QueryFilter::default()).unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,385 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+
+use super::db::Database;
+use super::hash;
+use crate::crdt::Operation;
+use crate::storage::QueryFilter;
+
+/// An operation naming a parent that isn't in the log.
+#[derive(Debug, Clone)]
+pub struct DanglingParent {
+    pub op_id: Uuid,
+    pub file_path: String,
+    pub parent_id: Uuid,
+}
+
+/// An operation that fails [`Operation::validate`].
+#[derive(Debug, Clone)]
+pub struct Malformed {
+    pub op_id: Uuid,
+    pub file_path: String,
+    pub reason: String,
+}
+
+/// A checkpoint whose stored content doesn't match its hash.
+#[derive(Debug, Clone)]
+pub struct CorruptCheckpoint {
+    pub file_path: String,
+    pub op_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    pub operations_checked: usize,
+    pub dangling_parents: Vec<DanglingParent>,
+    pub malformed: Vec<Malformed>,
+    pub corrupt_checkpoints: Vec<CorruptCheckpoint>,
+    /// Anchor ids whose file is neither tracked nor on disk.
+    pub orphaned_anchors: Vec<String>,
+    /// Annotation ids whose anchor no longer exists.
+    pub orphaned_annotations: Vec<String>,
+    /// Whether the problems above were repaired.
+    pub repaired: bool,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.dangling_parents.is_empty()
+            && self.malformed.is_empty()
+            && self.corrupt_checkpoints.is_empty()
+            && self.orphaned_anchors.is_empty()
+            && self.orphaned_annotations.is_empty()
+    }
+}
+
+/// Verify the causal links, operations, checkpoint hashes and context
+/// rows in `db`.
+/// Anchors count as valid if their file is live in the log or exists under
+/// `repo_root`. With `repair`, dangling parent references are removed,
+/// malformed operations are moved to `quarantined_operations`, and
+/// corrupt checkpoints and orphaned context rows are deleted.
+pub fn check(db: &Database, repo_root: &Path, repair: bool) -> Result<FsckReport> {
+    let operations = db.query_operations(&QueryFilter::default())?;
+    let mut report = FsckReport {
+        operations_checked: operations.len(),
+        dangling_parents: dangling_parents(db, &operations)?,
+        ..Default::default()
+    };
+
+    report.malformed = malformed(&operations);
+
+    report.corrupt_checkpoints = corrupt_checkpoints(db)?;
+
+    let (anchors, annotations) = orphaned_context(db, repo_root)?;
+    report.orphaned_anchors = anchors;
+    report.orphaned_annotations = annotations;
+
+    if repair && !report.is_clean() {
+        apply_repairs(db, &report)?;
+        report.repaired = true;
+    }
+
+    Ok(report)
+}
+
+/// Parents missing from the log. History before a checkpoint may have been
+/// compacted away by `forge gc --aggressive`, so operations at or before one
+/// of their file's checkpoints are allowed to lose their parents.
+fn dangling_parents(db: &Database, operations: &[Operation]) -> Result<Vec<DanglingParent>> {
+    let known: HashSet<Uuid> = operations.iter().map(|op| op.id).collect();
+    let newest_checkpoint = newest_checkpoints(db)?;
+
+    let mut dangling = Vec::new();
+    for op in operations {
+        let compacted = newest_checkpoint
+            .get(&op.file_path)
+            .is_some_and(|&at| op.timestamp <= at);
+        if compacted {
+            continue;
+        }
+        for parent_id in &op.parent_ops {
+            if !known.contains(parent_id) {
+                dangling.push(DanglingParent {
+                    op_id: op.id,
+                    file_path: op.file_path.clone(),
+                    parent_id: *parent_id,
+                });
+            }
+        }
+    }
+    Ok(dangling)
+}
+
+/// Operations that are malformed on their own. Ranges aren't checked
+/// against the file's content, since an edit made concurrently with others
+/// may fall outside it once they're merged and replay clamps it anyway.
+fn malformed(operations: &[Operation]) -> Vec<Malformed> {
+    operations
+        .iter()
+        .filter_map(|op| {
+            let err = op.validate().err()?;
+            Some(Malformed {
+                op_id: op.id,
+                file_path: op.file_path.clone(),
+                reason: err.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Checkpoints whose content no longer hashes to the stored hash, checked
+/// with whichever algorithm wrote each one.
+fn corrupt_checkpoints(db: &Database) -> Result<Vec<CorruptCheckpoint>> {
+    let conn = db.conn.lock();
+    let mut stmt = conn.prepare("SELECT file_path, op_id, content_hash, blob FROM checkpoints")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Vec<u8>>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut corrupt = Vec::new();
+    for (file_path, op_id, content_hash, blob) in rows {
+        let problem = match lz4::block::decompress(&blob, None) {
+            Ok(content) => match hash::verify(&content, &content_hash) {
+                Ok(true) => continue,
+                Ok(false) => "content does not match its hash".to_string(),
+                Err(err) => err.to_string(),
+            },
+            Err(err) => format!("unreadable content: {err}"),
+        };
+        corrupt.push(CorruptCheckpoint {
+            file_path,
+            op_id,
+            reason: problem,
+        });
+    }
+    Ok(corrupt)
+}
+
+/// Anchors for files that are neither live in the log nor on disk, and
+/// annotations whose anchor is gone.
+fn orphaned_context(db: &Database, repo_root: &Path) -> Result<(Vec<String>, Vec<String>)> {
+    let live: HashSet<String> = db
+        .file_summaries()?
+        .into_iter()
+        .filter(|summary| !summary.deleted)
+        .map(|summary| summary.file_path)
+        .collect();
+    let exists = |file_path: &str| {
+        let path = Path::new(file_path);
+        live.contains(file_path)
+            || if path.is_absolute() {
+                path.exists()
+            } else {
+                repo_root.join(path).exists()
+            }
+    };
+
+    let conn = db.conn.lock();
+    let mut stmt = conn.prepare("SELECT id, file_path FROM anchors")?;
+    let anchors = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|(_, file_path)| !exists(file_path))
+        .map(|(id, _)| id)
+        .collect();
+
+    let mut stmt = conn.prepare(
+        "SELECT id FROM annotations
+         WHERE anchor_id IS NOT NULL AND anchor_id NOT IN (SELECT id FROM anchors)",
+    )?;
+    let annotations = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((anchors, annotations))
+}
+
+fn apply_repairs(db: &Database, report: &FsckReport) -> Result<()> {
+    let mut missing: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+    for dangling in &report.dangling_parents {
+        missing
+            .entry(dangling.op_id)
+            .or_default()
+            .push(dangling.parent_id);
+    }
+
+    let mut conn = db.conn.lock();
+    let tx = conn.transaction()?;
+
+    for (op_id, parents) in missing {
+        let parent_ops: String = tx.query_row(
+            "SELECT parent_ops FROM operations WHERE id = ?1",
+            params![op_id.to_string()],
+            |row| row.get(0),
+        )?;
+        let kept: Vec<Uuid> = serde_json::from_str::<Vec<Uuid>>(&parent_ops)?
+            .into_iter()
+            .filter(|parent| !parents.contains(parent))
+            .collect();
+        tx.execute(
+            "UPDATE operations SET parent_ops = ?2 WHERE id = ?1",
+            params![op_id.to_string(), serde_json::to_string(&kept)?],
+        )?;
+    }
+
+    let now = Utc::now().to_rfc3339();
+    for broken in &report.malformed {
+        let op_id = broken.op_id.to_string();
+        tx.execute(
+            "INSERT OR REPLACE INTO quarantined_operations
+             SELECT id, timestamp, actor_id, file_path, op_type, op_data, parent_ops, ?2, ?3
+             FROM operations WHERE id = ?1",
+            params![op_id, broken.reason, now],
+        )?;
+        tx.execute(
+            "DELETE FROM operations_fts WHERE op_id = ?1",
+            params![op_id],
+        )?;
+        tx.execute("DELETE FROM operations WHERE id = ?1", params![op_id])?;
+    }
+
+    for checkpoint in &report.corrupt_checkpoints {
+        tx.execute(
+            "DELETE FROM checkpoints WHERE file_path = ?1 AND op_id = ?2",
+            params![checkpoint.file_path, checkpoint.op_id],
+        )?;
+    }
+
+    for id in &report.orphaned_annotations {
+        tx.execute("DELETE FROM annotations WHERE id = ?1", params![id])?;
+    }
+    for id in &report.orphaned_anchors {
+        tx.execute("DELETE FROM annotations WHERE anchor_id = ?1", params![id])?;
+        tx.execute("DELETE FROM anchors WHERE id = ?1", params![id])?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Timestamp of each file's newest checkpoint.
+fn newest_checkpoints(db: &Database) -> Result<HashMap<String, DateTime<Utc>>> {
+    let conn = db.conn.lock();
+    let mut stmt =
+        conn.prepare("SELECT file_path, MAX(timestamp) FROM checkpoints GROUP BY file_path")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    rows.into_iter()
+        .map(|(file_path, at)| {
+            Ok((
+                file_path,
+                DateTime::parse_from_rfc3339(&at)?.with_timezone(&Utc),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{OperationType, Position};
+    use crate::storage::reconstruct;
+    use crate::sync::GLOBAL_CLOCK;
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_and_repairs_broken_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let create = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "actor".into(),
+        );
+        let orphan = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, 6, 5, "actor".into(), GLOBAL_CLOCK.tick()),
+                content: "!".into(),
+                length: 1,
+            },
+            "actor".into(),
+        )
+        .with_parents(vec![Uuid::new_v4()]);
+        // Past the end of "hello!", as an edit made concurrently with a
+        // deletion would be, but well formed
+        let past_end = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, 21, 20, "actor".into(), GLOBAL_CLOCK.tick()),
+                content: "?".into(),
+                length: 1,
+            },
+            "actor".into(),
+        )
+        .with_parents(vec![orphan.id]);
+        let empty = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Delete {
+                position: Position::new(1, 1, 0, "actor".into(), GLOBAL_CLOCK.tick()),
+                length: 0,
+            },
+            "actor".into(),
+        )
+        .with_parents(vec![past_end.id]);
+        for op in [&create, &orphan, &past_end, &empty] {
+            db.store_operation(op).unwrap();
+        }
+
+        let report = check(&db, temp_dir.path(), false).unwrap();
+        assert_eq!(report.operations_checked, 4);
+        assert_eq!(report.dangling_parents.len(), 1);
+        assert_eq!(report.dangling_parents[0].op_id, orphan.id);
+        assert_eq!(report.malformed.len(), 1);
+        assert_eq!(report.malformed[0].op_id, empty.id);
+        assert!(!report.repaired);
+
+        db.store_checkpoint(
+            &crate::storage::db::Checkpoint::new(
+                "a.txt".into(),
+                create.id,
+                create.timestamp,
+                "hello".into(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        db.conn
+            .lock()
+            .execute("UPDATE checkpoints SET content_hash = 'ff'", [])
+            .unwrap();
+        let report = check(&db, temp_dir.path(), false).unwrap();
+        assert_eq!(report.corrupt_checkpoints.len(), 1);
+
+        let repaired = check(&db, temp_dir.path(), true).unwrap();
+        assert!(repaired.repaired);
+        assert!(check(&db, temp_dir.path(), false).unwrap().is_clean());
+        assert!(db.has_operation(&past_end.id).unwrap());
+        assert!(!db.has_operation(&empty.id).unwrap());
+        let content = reconstruct::reconstruct_at(&db, "a.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("hello!?"));
+    }
+}
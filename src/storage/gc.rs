@@ -0,0 +1,443 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::db::{Checkpoint, Database};
+use super::reconstruct;
+use crate::crdt::OperationType;
+
+/// Deleted files keep their history for this long before `forge gc` drops it.
+pub const DEFAULT_DELETED_RETENTION_DAYS: i64 = 30;
+
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// Also replace each live file's operation history with a snapshot of its
+    /// current content.
+    pub aggressive: bool,
+    /// History of files deleted before this moment is removed.
+    pub deleted_before: DateTime<Utc>,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            aggressive: false,
+            deleted_before: Utc::now() - chrono::Duration::days(DEFAULT_DELETED_RETENTION_DAYS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    pub pruned_files: usize,
+    pub compacted_files: usize,
+    pub removed_operations: usize,
+    pub removed_checkpoints: usize,
+    pub removed_anchors: usize,
+    pub removed_annotations: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl GcReport {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Prune and compact the database stored in `forge_path`. Anchors and
+/// annotations are checked against the files under `repo_root`.
+pub fn collect(
+    db: &Database,
+    forge_path: &Path,
+    repo_root: &Path,
+    options: &GcOptions,
+) -> Result<GcReport> {
+    let mut report = GcReport {
+        bytes_before: database_size(forge_path),
+        ..Default::default()
+    };
+
+    let summaries = db.file_summaries()?;
+    let mut prunable: HashSet<String> = summaries
+        .iter()
+        .filter(|summary| summary.deleted && summary.last_changed < options.deleted_before)
+        .map(|summary| summary.file_path.clone())
+        .collect();
+    keep_rename_sources(db, &mut prunable)?;
+
+    for summary in summaries {
+        if prunable.contains(&summary.file_path) {
+            let (ops, checkpoints) = drop_file_history(db, &summary.file_path)?;
+            report.pruned_files += 1;
+            report.removed_operations += ops;
+            report.removed_checkpoints += checkpoints;
+        } else if options.aggressive && !summary.deleted {
+            let (ops, checkpoints) = compact_file(db, &summary.file_path)?;
+            if ops > 0 {
+                report.compacted_files += 1;
+            }
+            report.removed_operations += ops;
+            report.removed_checkpoints += checkpoints;
+        }
+    }
+
+    report.removed_checkpoints += drop_superseded_checkpoints(db)?;
+    let (anchors, annotations) = drop_orphaned_context(db, repo_root)?;
+    report.removed_anchors = anchors;
+    report.removed_annotations = annotations;
+
+    db.conn.lock().execute_batch("VACUUM")?;
+    report.bytes_after = database_size(forge_path);
+
+    Ok(report)
+}
+
+/// Take out of `prunable` every path a file that's kept was renamed from,
+/// directly or through other renames: reconstructing a renamed file replays
+/// its source path's history.
+fn keep_rename_sources(db: &Database, prunable: &mut HashSet<String>) -> Result<()> {
+    let renames: Vec<(String, String)> = db
+        .operations_of_types(&["FileRename"], None, Utc::now())?
+        .into_iter()
+        .filter_map(|op| match op.op_type {
+            OperationType::FileRename { old_path, new_path } => Some((old_path, new_path)),
+            _ => None,
+        })
+        .collect();
+    loop {
+        let needed: Vec<String> = renames
+            .iter()
+            .filter(|(old_path, new_path)| {
+                prunable.contains(old_path) && !prunable.contains(new_path)
+            })
+            .map(|(old_path, _)| old_path.clone())
+            .collect();
+        if needed.is_empty() {
+            return Ok(());
+        }
+        for path in needed {
+            prunable.remove(&path);
+        }
+    }
+}
+
+/// Remove every operation and checkpoint recorded for `file_path`.
+fn drop_file_history(db: &Database, file_path: &str) -> Result<(usize, usize)> {
+    let mut conn = db.conn.lock();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "DELETE FROM operations_fts WHERE op_id IN (SELECT id FROM operations WHERE file_path = ?1)",
+        params![file_path],
+    )?;
+    let ops = tx.execute(
+        "DELETE FROM operations WHERE file_path = ?1",
+        params![file_path],
+    )?;
+    let checkpoints = tx.execute(
+        "DELETE FROM checkpoints WHERE file_path = ?1",
+        params![file_path],
+    )?;
+    tx.commit()?;
+    Ok((ops, checkpoints))
+}
+
+/// Snapshot `file_path` at its latest operation and drop everything before it.
+fn compact_file(db: &Database, file_path: &str) -> Result<(usize, usize)> {
+    let Some(last) = db.operations_causal_order(file_path)?.pop() else {
+        return Ok((0, 0));
+    };
+    let Some(content) = reconstruct::reconstruct_through(db, &last)? else {
+        return Ok((0, 0));
+    };
+    db.store_checkpoint(&Checkpoint::new(
+        file_path.to_string(),
+        last.id,
+        last.timestamp,
+        content,
+    )?)?;
+
+    // Binary content and symlink targets are read from the newest operation
+    // of a few kinds rather than from checkpoints, so a `BinaryModify` or
+    // symlink operation that's still the newest of its kind stays. Older
+    // ones go, or they'd outlive the text that replaced them.
+    let mut kept = Vec::new();
+    for types in [reconstruct::CONTENT_TYPES, reconstruct::SYMLINK_TYPES] {
+        let newest = db
+            .operations_of_types(types, Some(file_path), last.timestamp)?
+            .pop();
+        if let Some(op) = newest
+            && matches!(
+                op.op_type,
+                OperationType::BinaryModify { .. }
+                    | OperationType::SymlinkCreate { .. }
+                    | OperationType::SymlinkChange { .. }
+            )
+        {
+            kept.push(op.id.to_string());
+        }
+    }
+    kept.resize(2, String::new());
+
+    // The checkpointed operation itself stays so replay can tell which
+    // operations sharing its timestamp come after it. Mode changes aren't
+    // captured by checkpoints, so they stay too.
+    let mut conn = db.conn.lock();
+    let tx = conn.transaction()?;
+    let dropped = "file_path = ?1 AND timestamp < ?2 AND rtrim(op_type) != 'ChmodChange'
+                   AND id NOT IN (?3, ?4)";
+    let dropping = params![file_path, last.timestamp.to_rfc3339(), kept[0], kept[1]];
+    tx.execute(
+        &format!(
+            "DELETE FROM operations_fts WHERE op_id IN
+             (SELECT id FROM operations WHERE {dropped})"
+        ),
+        dropping,
+    )?;
+    let ops = tx.execute(&format!("DELETE FROM operations WHERE {dropped}"), dropping)?;
+    let checkpoints = tx.execute(
+        "DELETE FROM checkpoints WHERE file_path = ?1 AND op_id != ?2",
+        params![file_path, last.id.to_string()],
+    )?;
+    tx.commit()?;
+    Ok((ops, checkpoints))
+}
+
+/// Only the newest checkpoint per file, in replay order, is needed to bound
+/// replay cost.
+fn drop_superseded_checkpoints(db: &Database) -> Result<usize> {
+    let conn = db.conn.lock();
+    Ok(conn.execute(
+        "DELETE FROM checkpoints WHERE EXISTS (
+             SELECT 1 FROM checkpoints newer
+             JOIN operations n ON n.id = newer.op_id
+             JOIN operations o ON o.id = checkpoints.op_id
+             WHERE newer.file_path = checkpoints.file_path
+               AND (n.order_stamp, n.timestamp, n.actor_id, n.id)
+                 > (o.order_stamp, o.timestamp, o.actor_id, o.id)
+         )",
+        [],
+    )?)
+}
+
+/// Remove anchors and annotations for files that no longer exist, and
+/// annotations pointing at anchors that are gone.
+fn drop_orphaned_context(db: &Database, repo_root: &Path) -> Result<(usize, usize)> {
+    let exists = |file_path: &str| {
+        let path = Path::new(file_path);
+        if path.is_absolute() {
+            path.exists()
+        } else {
+            repo_root.join(path).exists()
+        }
+    };
+    let missing_files = |table: &str| -> Result<Vec<String>> {
+        let conn = db.conn.lock();
+        let mut stmt = conn.prepare(&format!("SELECT DISTINCT file_path FROM {table}"))?;
+        let paths = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(paths.into_iter().filter(|path| !exists(path)).collect())
+    };
+    let anchor_files = missing_files("anchors")?;
+    let annotation_files = missing_files("annotations")?;
+
+    let mut conn = db.conn.lock();
+    let tx = conn.transaction()?;
+    let mut annotations = 0;
+    for file_path in &annotation_files {
+        annotations += tx.execute(
+            "DELETE FROM annotations WHERE file_path = ?1",
+            params![file_path],
+        )?;
+    }
+    let mut anchors = 0;
+    for file_path in &anchor_files {
+        anchors += tx.execute(
+            "DELETE FROM anchors WHERE file_path = ?1",
+            params![file_path],
+        )?;
+    }
+    annotations += tx.execute(
+        "DELETE FROM annotations
+         WHERE anchor_id IS NOT NULL AND anchor_id NOT IN (SELECT id FROM anchors)",
+        [],
+    )?;
+    tx.commit()?;
+    Ok((anchors, annotations))
+}
+
+fn database_size(forge_path: &Path) -> u64 {
+    ["forge.db", "forge.db-wal"]
+        .iter()
+        .filter_map(|name| std::fs::metadata(forge_path.join(name)).ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Operation, OperationType, Position};
+    use crate::storage::QueryFilter;
+    use crate::sync::GLOBAL_CLOCK;
+    use tempfile::TempDir;
+
+    #[test]
+    fn aggressive_gc_keeps_content_and_drops_old_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let mut create = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "actor".into(),
+        );
+        create.timestamp -= chrono::Duration::seconds(2);
+        let mut gone = Operation::new(
+            "gone.txt".to_string(),
+            OperationType::FileDelete,
+            "actor".into(),
+        );
+        gone.timestamp -= chrono::Duration::days(DEFAULT_DELETED_RETENTION_DAYS + 1);
+        let insert = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, 6, 5, "actor".into(), GLOBAL_CLOCK.tick()),
+                content: " world".into(),
+                length: 6,
+            },
+            "actor".into(),
+        );
+        for op in [&create, &gone, &insert] {
+            db.store_operation(op).unwrap();
+        }
+
+        let options = GcOptions {
+            aggressive: true,
+            ..Default::default()
+        };
+        let report = collect(&db, temp_dir.path(), temp_dir.path(), &options).unwrap();
+
+        assert_eq!(report.pruned_files, 1);
+        assert_eq!(report.compacted_files, 1);
+        assert_eq!(report.removed_operations, 2);
+        assert_eq!(db.count_operations(&QueryFilter::default()).unwrap(), 1);
+        let content = reconstruct::reconstruct_at(&db, "a.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("hello world"));
+    }
+
+    #[test]
+    fn gc_keeps_the_history_a_rename_replays() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        // a.txt is renamed to b.txt, then re-created and deleted, all long ago
+        let long_ago = Utc::now() - chrono::Duration::days(DEFAULT_DELETED_RETENTION_DAYS + 1);
+        let steps = [
+            (
+                "a.txt",
+                OperationType::FileCreate {
+                    content: "original".into(),
+                },
+            ),
+            (
+                "b.txt",
+                OperationType::FileRename {
+                    old_path: "a.txt".into(),
+                    new_path: "b.txt".into(),
+                },
+            ),
+            (
+                "a.txt",
+                OperationType::FileCreate {
+                    content: "second".into(),
+                },
+            ),
+            ("a.txt", OperationType::FileDelete),
+        ];
+        for (idx, (path, op_type)) in steps.into_iter().enumerate() {
+            let mut op = Operation::new(path.to_string(), op_type, "actor".into());
+            op.timestamp = long_ago + chrono::Duration::seconds(idx as i64);
+            db.store_operation(&op).unwrap();
+        }
+
+        let report = collect(&db, temp_dir.path(), temp_dir.path(), &GcOptions::default()).unwrap();
+
+        assert_eq!(report.pruned_files, 0);
+        let content = reconstruct::reconstruct_at(&db, "b.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("original"));
+    }
+
+    #[test]
+    fn aggressive_gc_keeps_what_binary_and_symlink_lookups_read() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        // Both files' last operation is a mode change, which says nothing
+        // about their content
+        let bytes = [0x89, b'P', b'N', b'G'];
+        let hash = crate::storage::hash::digest(&bytes).unwrap();
+        let steps = [
+            (
+                "logo.png",
+                OperationType::BinaryModify {
+                    old_hash: None,
+                    new_hash: hash.clone(),
+                    size: bytes.len() as u64,
+                },
+            ),
+            (
+                "link",
+                OperationType::SymlinkCreate {
+                    target: "logo.png".into(),
+                },
+            ),
+            (
+                "logo.png",
+                OperationType::ChmodChange {
+                    old_mode: 0o644,
+                    new_mode: 0o600,
+                },
+            ),
+            (
+                "link",
+                OperationType::ChmodChange {
+                    old_mode: 0o777,
+                    new_mode: 0o755,
+                },
+            ),
+        ];
+        let start = Utc::now() - chrono::Duration::seconds(10);
+        for (idx, (path, op_type)) in steps.into_iter().enumerate() {
+            let mut op = Operation::new(path.to_string(), op_type, "actor".into());
+            op.timestamp = start + chrono::Duration::seconds(idx as i64);
+            db.store_operation(&op).unwrap();
+        }
+
+        let options = GcOptions {
+            aggressive: true,
+            ..Default::default()
+        };
+        collect(&db, temp_dir.path(), temp_dir.path(), &options).unwrap();
+
+        let now = Utc::now();
+        assert_eq!(
+            reconstruct::binary_hash_at(&db, "logo.png", now).unwrap(),
+            Some(hash)
+        );
+        assert_eq!(
+            reconstruct::symlink_target_at(&db, "link", now)
+                .unwrap()
+                .as_deref(),
+            Some("logo.png")
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Algorithm used for content hashes (checkpoints, tree manifests, repo ids).
+/// SHA-256 hashes are stored as bare hex so hashes written before this
+/// setting existed stay valid; other algorithms prefix the hex with their
+/// name (`blake3:…`), so a repository can mix algorithms and still verify
+/// every hash it holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// Much faster on large content. Hashing with it needs the `blake3`
+    /// feature; without it the setting still loads, but hashing fails.
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Hash `bytes`, tagged with this algorithm's prefix.
+    pub fn digest(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            HashAlgorithm::Sha256 => Ok(format!("{:x}", Sha256::digest(bytes))),
+            #[cfg(feature = "blake3")]
+            HashAlgorithm::Blake3 => Ok(format!("blake3:{}", blake3::hash(bytes).to_hex())),
+            #[cfg(not(feature = "blake3"))]
+            HashAlgorithm::Blake3 => bail!("BLAKE3 hashes need forge built with `blake3`"),
+        }
+    }
+
+    /// The algorithm that produced `hash`, from its prefix.
+    pub fn of(hash: &str) -> Result<Self> {
+        match hash.split_once(':') {
+            None => Ok(HashAlgorithm::Sha256),
+            Some(("blake3", _)) => Ok(HashAlgorithm::Blake3),
+            Some((prefix, _)) => bail!("unsupported hash algorithm `{prefix}`"),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            HashAlgorithm::Sha256 => 0,
+            HashAlgorithm::Blake3 => 1,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => HashAlgorithm::Blake3,
+            _ => HashAlgorithm::Sha256,
+        }
+    }
+}
+
+/// Whether `bytes` hash to `hash`, using whichever algorithm produced it.
+pub fn verify(bytes: &[u8], hash: &str) -> Result<bool> {
+    Ok(HashAlgorithm::of(hash)?.digest(bytes)? == hash)
+}
+
+static ACTIVE: AtomicU8 = AtomicU8::new(0);
+
+/// Hash new content in this process with `algorithm` (`hash_algorithm` in
+/// config.json).
+pub fn set_active(algorithm: HashAlgorithm) {
+    ACTIVE.store(algorithm.to_u8(), Ordering::Relaxed);
+}
+
+pub fn active() -> HashAlgorithm {
+    HashAlgorithm::from_u8(ACTIVE.load(Ordering::Relaxed))
+}
+
+/// Hash `bytes` with the active algorithm.
+pub fn digest(bytes: &[u8]) -> Result<String> {
+    active().digest(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_hashes_stay_unprefixed_and_verify() {
+        let hash = HashAlgorithm::Sha256.digest(b"hello").unwrap();
+        assert_eq!(
+            hash,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert!(verify(b"hello", &hash).unwrap());
+        assert!(!verify(b"hello!", &hash).unwrap());
+        assert!(verify(b"hello", "md5:5d41402abc4b2a76b9719d911017c592").is_err());
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_hashes_are_prefixed() {
+        let hash = HashAlgorithm::Blake3.digest(b"hello").unwrap();
+        assert!(hash.starts_with("blake3:"));
+        assert_eq!(HashAlgorithm::of(&hash).unwrap(), HashAlgorithm::Blake3);
+        assert!(verify(b"hello", &hash).unwrap());
+    }
+
+    #[cfg(not(feature = "blake3"))]
+    #[test]
+    fn blake3_loads_but_fails_to_hash_without_the_feature() {
+        let algorithm: HashAlgorithm = serde_json::from_str("\"blake3\"").unwrap();
+        assert_eq!(algorithm, HashAlgorithm::Blake3);
+        assert!(algorithm.digest(b"hello").is_err());
+        assert!(verify(b"hello", "blake3:ea8f163db38682925e4491c5e58d4bb3").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Who an actor is. `actor_id` is the stable key recorded on every operation;
+/// the name and email are for display and can change at any time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActorIdentity {
+    pub actor_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl ActorIdentity {
+    /// An identity known only by its id.
+    pub fn anonymous(actor_id: impl Into<String>) -> Self {
+        Self {
+            actor_id: actor_id.into(),
+            ..Default::default()
+        }
+    }
+
+    /// `Name <email>`, falling back to whichever part is known and finally to
+    /// the actor id.
+    pub fn display_name(&self) -> String {
+        match (&self.name, &self.email) {
+            (Some(name), Some(email)) => format!("{name} <{email}>"),
+            (Some(name), None) => name.clone(),
+            (None, Some(email)) => format!("<{email}>"),
+            (None, None) => self.actor_id.clone(),
+        }
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        self.name.is_none() && self.email.is_none()
+    }
+}
+
+/// Default name and email for a new actor: `$GIT_AUTHOR_NAME` and
+/// `$GIT_AUTHOR_EMAIL` when set, otherwise git's `user.name` and `user.email`.
+pub fn detect(repo_root: &Path) -> (Option<String>, Option<String>) {
+    let git_config = git2::Repository::discover(repo_root)
+        .and_then(|repo| repo.config())
+        .or_else(|_| git2::Config::open_default())
+        .ok();
+    let lookup = |env: &str, key: &str| {
+        std::env::var(env)
+            .ok()
+            .or_else(|| git_config.as_ref()?.get_string(key).ok())
+            .filter(|value| !value.trim().is_empty())
+    };
+    (
+        lookup("GIT_AUTHOR_NAME", "user.name"),
+        lookup("GIT_AUTHOR_EMAIL", "user.email"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_name_falls_back_to_actor_id() {
+        let identity = ActorIdentity {
+            actor_id: "1234".into(),
+            name: Some("Alice".into()),
+            email: Some("alice@example.com".into()),
+        };
+        assert_eq!(identity.display_name(), "Alice <alice@example.com>");
+
+        let anonymous = ActorIdentity::anonymous("1234");
+        assert!(anonymous.is_anonymous());
+        assert_eq!(anonymous.display_name(), "1234");
+    }
+}
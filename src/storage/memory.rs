@@ -0,0 +1,197 @@
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use dashmap::{DashMap, DashSet};
+use parking_lot::RwLock;
+use std::cmp::Reverse;
+use std::path::Path;
+use uuid::Uuid;
+
+use super::db::{Checkpoint, QueryFilter};
+use super::store::OperationStore;
+use crate::crdt::{Anchor, Operation};
+
+/// Operation store that lives entirely in memory, for tests and short-lived
+/// tools that shouldn't create `.dx/forge` on disk. Query ordering matches
+/// the SQLite store: by timestamp, with ties kept in insertion order.
+#[derive(Default)]
+#[allow(dead_code)]
+pub struct MemoryStore {
+    // Insertion order, which stands in for SQLite's rowid
+    operations: RwLock<Vec<Operation>>,
+    ids: DashSet<Uuid>,
+    checkpoints: DashMap<String, Vec<Checkpoint>>,
+    anchors: DashMap<Uuid, Anchor>,
+}
+
+#[allow(dead_code)]
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OperationStore for MemoryStore {
+    fn store_operation(&self, op: &Operation) -> Result<bool> {
+        if !self.ids.insert(op.id) {
+            return Ok(false);
+        }
+        self.operations.write().push(op.clone());
+        Ok(true)
+    }
+
+    fn has_operation(&self, id: &Uuid) -> Result<bool> {
+        Ok(self.ids.contains(id))
+    }
+
+    fn operation(&self, id: &Uuid) -> Result<Option<Operation>> {
+        Ok(self.operations.read().iter().find(|op| op.id == *id).cloned())
+    }
+
+    fn get_operations(&self, file: Option<&Path>, limit: usize) -> Result<Vec<Operation>> {
+        let file = file.map(|f| f.display().to_string());
+        let mut ops: Vec<Operation> = self
+            .operations
+            .read()
+            .iter()
+            .filter(|op| file.as_ref().is_none_or(|f| &op.file_path == f))
+            .cloned()
+            .collect();
+        ops.sort_by_key(|op| Reverse(op.timestamp));
+        ops.truncate(limit);
+        Ok(ops)
+    }
+
+    fn query_operations(&self, filter: &QueryFilter) -> Result<Vec<Operation>> {
+        let file = filter.file.as_ref().map(|f| f.display().to_string());
+        let mut ops: Vec<Operation> = self
+            .operations
+            .read()
+            .iter()
+            .filter(|op| {
+                file.as_ref().is_none_or(|f| &op.file_path == f)
+                    && filter.actor_id.as_ref().is_none_or(|a| &op.actor_id == a)
+                    && filter.since.is_none_or(|since| op.timestamp >= since)
+                    && filter.until.is_none_or(|until| op.timestamp <= until)
+            })
+            .cloned()
+            .collect();
+        ops.sort_by_key(|op| op.timestamp);
+        if let Some(limit) = filter.limit {
+            ops.truncate(limit);
+        }
+        Ok(ops)
+    }
+
+    fn store_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        let mut checkpoints = self
+            .checkpoints
+            .entry(checkpoint.file_path.clone())
+            .or_default();
+        checkpoints.retain(|existing| existing.op_id != checkpoint.op_id);
+        checkpoints.push(checkpoint.clone());
+        Ok(())
+    }
+
+    fn latest_checkpoint(&self, file_path: &str, at: DateTime<Utc>) -> Result<Option<Checkpoint>> {
+        let Some(checkpoints) = self.checkpoints.get(file_path) else {
+            return Ok(None);
+        };
+        let operations = self.operations.read();
+        Ok(checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.timestamp <= at)
+            .filter_map(|checkpoint| {
+                let op = operations.iter().find(|op| op.id == checkpoint.op_id)?;
+                Some((op.order_key(), checkpoint))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, checkpoint)| checkpoint.clone()))
+    }
+
+    fn store_anchor(&self, anchor: &Anchor) -> Result<()> {
+        // Same uniqueness rules as the anchors table
+        if self.anchors.contains_key(&anchor.id)
+            || self
+                .anchors
+                .iter()
+                .any(|existing| existing.stable_id == anchor.stable_id)
+        {
+            bail!("anchor {} already exists", anchor.stable_id);
+        }
+        self.anchors.insert(anchor.id, anchor.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::OperationType;
+    use crate::storage::{Database, OperationLog, reconstruct};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn create(path: &str, content: &str) -> Operation {
+        Operation::new(
+            path.to_string(),
+            OperationType::FileCreate {
+                content: content.into(),
+            },
+            "actor".into(),
+        )
+    }
+
+    #[test]
+    fn orders_operations_like_the_sqlite_store() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+        let memory = MemoryStore::new();
+
+        let first = create("a.txt", "one");
+        let mut earlier = create("b.txt", "two");
+        earlier.timestamp = first.timestamp - chrono::Duration::seconds(1);
+        let last = create("a.txt", "three");
+        for op in [&first, &earlier, &last] {
+            assert!(db.store_operation(op).unwrap());
+            assert!(memory.store_operation(op).unwrap());
+        }
+        assert!(!memory.store_operation(&first).unwrap());
+
+        let ids = |ops: Vec<Operation>| ops.into_iter().map(|op| op.id).collect::<Vec<_>>();
+        let filter = QueryFilter {
+            file: Some("a.txt".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(memory.query_operations(&QueryFilter::default()).unwrap()),
+            ids(db.query_operations(&QueryFilter::default()).unwrap())
+        );
+        assert_eq!(
+            ids(memory.query_operations(&filter).unwrap()),
+            ids(db.query_operations(&filter).unwrap())
+        );
+        assert_eq!(
+            ids(memory.get_operations(None, 2).unwrap()),
+            ids(db.get_operations(None, 2).unwrap())
+        );
+    }
+
+    #[test]
+    fn backs_an_operation_log_without_touching_disk() {
+        let store = Arc::new(MemoryStore::new());
+        let oplog = OperationLog::new(store.clone());
+        let op = create("a.txt", "hello");
+        assert!(oplog.append(op.clone()).unwrap());
+
+        // Persistence happens on the log's writer thread
+        for _ in 0..100 {
+            if store.has_operation(&op.id).unwrap() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let content = reconstruct::reconstruct_at(store.as_ref(), "a.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("hello"));
+    }
+}
@@ -1,14 +1,35 @@
+pub mod bisect;
+pub mod config;
 pub mod db;
+pub mod fsck;
+pub mod gc;
+pub mod hash;
 pub mod git_interop;
+pub mod identity;
+pub mod memory;
 pub mod oplog;
+pub mod portable;
+pub mod reconstruct;
+pub mod records;
+pub mod reindex;
+pub mod squash;
+pub mod store;
+pub mod tree;
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use colored::*;
-use ropey::Rope;
+use std::collections::HashMap;
 use std::path::Path;
 
-pub use db::Database;
+pub use bisect::bisect;
+pub use config::ForgeConfig;
+pub use db::{Database, QueryFilter};
+pub use identity::ActorIdentity;
 pub use oplog::OperationLog;
+pub use squash::squash;
+pub use store::OperationStore;
+pub use tree::TreeManifest;
 
 const FORGE_DIR: &str = ".dx/forge";
 
@@ -26,53 +47,383 @@ pub async fn init(path: &Path) -> Result<()> {
     db.initialize()?;
 
     // Create config
-    let config = serde_json::json!({
-        "version": "0.1.0",
-        "actor_id": uuid::Uuid::new_v4().to_string(),
-        "repo_id": uuid::Uuid::new_v4().to_string(),
-        "git_interop": true,
-        "real_time_sync": false,
-    });
+    let (actor_name, actor_email) = identity::detect(path);
+    let config = ForgeConfig {
+        actor_id: uuid::Uuid::new_v4().to_string(),
+        actor_name,
+        actor_email,
+        repo_id: Some(uuid::Uuid::new_v4().to_string()),
+        ..Default::default()
+    };
+    config.save(&forge_path)?;
+    db.record_actor(&config.identity())?;
+
+    Ok(())
+}
+
+/// Show or update this repository's actor identity. The actor id never
+/// changes; only the name and email shown alongside it do.
+pub async fn configure(
+    path: &Path,
+    actor_name: Option<String>,
+    actor_email: Option<String>,
+) -> Result<()> {
+    let forge_path = path.join(FORGE_DIR);
+    let mut config = ForgeConfig::load(&forge_path)?;
+
+    let changed = actor_name.is_some() || actor_email.is_some();
+    if let Some(name) = actor_name {
+        config.actor_name = Some(name);
+    }
+    if let Some(email) = actor_email {
+        config.actor_email = Some(email);
+    }
 
-    tokio::fs::write(
-        forge_path.join("config.json"),
-        serde_json::to_string_pretty(&config)?,
-    )
-    .await?;
+    let identity = config.identity();
+    if changed {
+        config.save(&forge_path)?;
+        let db = Database::new(&forge_path)?;
+        db.initialize()?;
+        db.record_actor(&identity)?;
+        println!("{} Actor identity updated", "✓".green());
+    }
 
+    println!(
+        "{} {}",
+        "Actor:".bright_black(),
+        identity.display_name().bright_white()
+    );
+    println!("{} {}", "Actor ID:".bright_black(), identity.actor_id.bright_black());
     Ok(())
 }
 
-pub async fn show_log(file: Option<std::path::PathBuf>, limit: usize) -> Result<()> {
+pub async fn show_log(file: Option<std::path::PathBuf>, limit: usize, json: bool) -> Result<()> {
     let db = Database::open(".dx/forge")?;
     let operations = db.get_operations(file.as_deref(), limit)?;
+    if json {
+        let records: Vec<_> = operations.iter().map(records::OperationRecord::from).collect();
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+    let actors = db.actors()?;
 
     println!("{}", "Operation Log".cyan().bold());
     println!("{}", "═".repeat(80).bright_black());
 
     for op in operations {
-        let time = op.timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
-        let op_type = match &op.op_type {
-            crate::crdt::OperationType::Insert { length, .. } => {
-                format!("+{} chars", length).green()
-            }
-            crate::crdt::OperationType::Delete { length, .. } => format!("-{} chars", length).red(),
-            crate::crdt::OperationType::Replace {
-                old_content,
-                new_content,
-                ..
-            } => format!("~{}->{} chars", old_content.len(), new_content.len()).yellow(),
-            crate::crdt::OperationType::FileCreate { .. } => "FILE_CREATE".bright_green(),
-            crate::crdt::OperationType::FileDelete => "FILE_DELETE".bright_red(),
-            crate::crdt::OperationType::FileRename { old_path, new_path } => {
-                format!("RENAME {} -> {}", old_path, new_path).bright_yellow()
-            }
+        print_op_line(&op, &actors);
+    }
+
+    Ok(())
+}
+
+/// One line of `forge oplog` output.
+fn print_op_line(op: &crate::crdt::Operation, actors: &HashMap<String, ActorIdentity>) {
+    let time = op.timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
+    let op_type = match &op.op_type {
+        crate::crdt::OperationType::Insert { length, .. } => {
+            format!("+{} chars", length).green()
+        }
+        crate::crdt::OperationType::Delete { length, .. } => format!("-{} chars", length).red(),
+        crate::crdt::OperationType::Replace {
+            old_content,
+            new_content,
+            ..
+        } => format!("~{}->{} chars", old_content.len(), new_content.len()).yellow(),
+        crate::crdt::OperationType::FileCreate { .. } => "FILE_CREATE".bright_green(),
+        crate::crdt::OperationType::FileDelete => "FILE_DELETE".bright_red(),
+        crate::crdt::OperationType::FileRename { old_path, new_path } => {
+            format!("RENAME {} -> {}", old_path, new_path).bright_yellow()
+        }
+        crate::crdt::OperationType::DirCreate => "DIR_CREATE".bright_green(),
+        crate::crdt::OperationType::DirDelete => "DIR_DELETE".bright_red(),
+        crate::crdt::OperationType::DirRename { old_path, new_path } => {
+            format!("DIR_RENAME {} -> {}", old_path, new_path).bright_yellow()
+        }
+        crate::crdt::OperationType::ChmodChange { old_mode, new_mode } => {
+            format!("CHMOD {:o} -> {:o}", old_mode, new_mode).bright_magenta()
+        }
+        crate::crdt::OperationType::SymlinkCreate { target } => {
+            format!("SYMLINK -> {}", target).bright_cyan()
+        }
+        crate::crdt::OperationType::SymlinkChange {
+            old_target,
+            new_target,
+        } => format!("SYMLINK {} -> {}", old_target, new_target).bright_cyan(),
+        crate::crdt::OperationType::BinaryModify { size, .. } => {
+            format!("BINARY {} bytes", size).bright_magenta()
+        }
+    };
+
+    let author = actors
+        .get(&op.actor_id)
+        .map(ActorIdentity::display_name)
+        .unwrap_or_else(|| op.actor_id.clone());
+    println!(
+        "{} {} {} {} {}",
+        format!("[{}]", time).bright_black(),
+        op_type.bold(),
+        op.file_path.bright_white(),
+        author.bright_blue(),
+        format!("({})", op.id).bright_black()
+    );
+}
+
+/// Print everything stored for one operation.
+pub async fn show_op(id: &str) -> Result<()> {
+    let id = uuid::Uuid::parse_str(id)?;
+    let db = Database::open(FORGE_DIR)?;
+    let Some(op) = db.operation(&id)? else {
+        anyhow::bail!("no operation {id}");
+    };
+    let author = db
+        .actor(&op.actor_id)?
+        .map(|identity| identity.display_name())
+        .unwrap_or_else(|| op.actor_id.clone());
+
+    println!("{} {}", "Operation".cyan().bold(), op.id.to_string().bright_yellow());
+    println!("{}", "═".repeat(80).bright_black());
+    println!("{} {}", "File:     ".bright_black(), op.file_path.bright_white());
+    println!("{} {}", "Time:     ".bright_black(), op.timestamp.to_rfc3339());
+    println!("{} {} ({})", "Actor:    ".bright_black(), author.bright_blue(), op.actor_id);
+    if let Some(lamport) = op.lamport() {
+        println!("{} {}", "Lamport:  ".bright_black(), lamport);
+    }
+    if op.parent_ops.is_empty() {
+        println!("{} {}", "Parents:  ".bright_black(), "none".bright_black());
+    }
+    for parent in &op.parent_ops {
+        let known = if db.has_operation(parent)? {
+            String::new()
+        } else {
+            " (missing)".red().to_string()
         };
+        println!("{} {}{}", "Parent:   ".bright_black(), parent, known);
+    }
+    println!("{}", "Type:".bright_black());
+    for line in serde_json::to_string_pretty(&op.op_type)?.lines() {
+        println!("  {line}");
+    }
+    Ok(())
+}
+
+/// Print a stored anchor.
+pub async fn show_anchor(id: &str, history: bool) -> Result<()> {
+    let id = uuid::Uuid::parse_str(id)?;
+    let db = Database::open(FORGE_DIR)?;
+    let Some(anchor) = db.anchor(&id)? else {
+        anyhow::bail!("no anchor {id}");
+    };
+
+    println!("{} {}", "Anchor".cyan().bold(), anchor.id.to_string().bright_yellow());
+    println!("{}", "═".repeat(80).bright_black());
+    println!("{} {}", "File:     ".bright_black(), anchor.file_path.bright_white());
+    println!(
+        "{} {}:{} (offset {}, lamport {}, actor {})",
+        "Position: ".bright_black(),
+        anchor.position.line,
+        anchor.position.column,
+        anchor.position.offset,
+        anchor.position.lamport_timestamp,
+        anchor.position.actor_id
+    );
+    println!("{} {}", "Stable id:".bright_black(), anchor.stable_id);
+    println!("{} {}", "Created:  ".bright_black(), anchor.created_at.to_rfc3339());
+    if let Some(message) = &anchor.message {
+        println!("{} {}", "Message:  ".bright_black(), message);
+    }
+    if !anchor.tags.is_empty() {
+        println!("{} {}", "Tags:     ".bright_black(), anchor.tags.join(", "));
+    }
+    println!("{} {}", "Permalink:".bright_black(), anchor.permalink().bright_blue());
+
+    if history {
+        let actors = db.actors()?;
+        println!("\n{}", "History".cyan().bold());
+        for op in crate::context::anchor_history(&db, &anchor.id)? {
+            print_op_line(&op, &actors);
+        }
+    }
+    Ok(())
+}
+
+/// Print one setting from `config.json`.
+pub async fn config_get(path: &Path, key: &str) -> Result<()> {
+    let config = ForgeConfig::load(&path.join(FORGE_DIR))?;
+    println!("{}", format_config_value(&config.get(key)?));
+    Ok(())
+}
+
+/// Change one setting, checking that `value` fits the setting's type.
+pub async fn config_set(path: &Path, key: &str, value: &str) -> Result<()> {
+    let forge_path = path.join(FORGE_DIR);
+    let mut config = ForgeConfig::load(&forge_path)?;
+    config.set(key, value)?;
+    config.save(&forge_path)?;
 
+    if matches!(key, "actor_name" | "actor_email") {
+        let db = Database::new(&forge_path)?;
+        db.initialize()?;
+        db.record_actor(&config.identity())?;
+    }
+
+    println!(
+        "{} {} = {}",
+        "✓".green(),
+        key.bright_white(),
+        format_config_value(&config.get(key)?)
+    );
+    Ok(())
+}
+
+pub async fn config_list(path: &Path) -> Result<()> {
+    let config = ForgeConfig::load(&path.join(FORGE_DIR))?;
+    for (key, value) in config.entries()? {
+        println!("{:<22} {}", key.bright_black(), format_config_value(&value));
+    }
+    Ok(())
+}
+
+fn format_config_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(format_config_value)
+            .collect::<Vec<_>>()
+            .join(","),
+        other => other.to_string(),
+    }
+}
+
+/// Number of recently changed files listed by `forge status`.
+const STATUS_RECENT_FILES: usize = 20;
+
+/// `forge status` warns about files whose reconstruction replays more
+/// operations than this, four times the default checkpoint interval.
+const REPLAY_COST_WARNING: usize = 4 * oplog::DEFAULT_CHECKPOINT_INTERVAL;
+
+pub async fn status(path: &Path) -> Result<()> {
+    let forge_path = path.join(FORGE_DIR);
+    if !ForgeConfig::path(&forge_path).exists() {
         println!(
-            "{} {} {} {}",
+            "{} Not a Forge repository: {} has no {}. Run {} to create one.",
+            "⚠️".yellow(),
+            path.display(),
+            FORGE_DIR,
+            "forge init".bright_white()
+        );
+        return Ok(());
+    }
+
+    let config = ForgeConfig::load(&forge_path)?;
+    let actor_id = config.actor_id.as_str();
+
+    let db = Database::new(&forge_path)?;
+    db.initialize()?;
+    let files = db.file_summaries()?;
+    let tracked = files.iter().filter(|file| !file.deleted).count();
+    let stats = db.stats()?;
+
+    println!("{}", "Forge Status".cyan().bold());
+    println!("{}", "═".repeat(80).bright_black());
+    println!("  Tracked files:    {}", tracked.to_string().bright_white());
+    println!(
+        "  Total operations: {}",
+        stats.total_operations.to_string().bright_white()
+    );
+    println!(
+        "  Database size:    {}",
+        format!("{} bytes", stats.db_size_bytes).bright_white()
+    );
+
+    let run_state = crate::watcher::run_state::read(&forge_path).filter(|state| state.is_alive());
+    match &run_state {
+        Some(state) => println!(
+            "  Watcher:          {} (pid {}, since {})",
+            "running".green(),
+            state.pid,
+            state.started_at.format("%Y-%m-%d %H:%M:%S")
+        ),
+        None => println!("  Watcher:          {}", "not running".red()),
+    }
+
+    if let Some(state) = run_state.filter(|state| state.sync) {
+        println!("\n{}", "Sync peers".yellow());
+        if state.peers.is_empty() {
+            println!("  {}", "no peers connected".bright_black());
+        }
+        for peer in &state.peers {
+            // Replay resends everything after the marker
+            let marker = db.sync_marker(peer)?;
+            let pending = db.operations_after_marker(actor_id, marker.as_ref())?.len();
+            println!(
+                "  {} {}",
+                peer.bright_white(),
+                format!("({} unsynced)", pending).bright_black()
+            );
+        }
+    }
+
+    let costly: Vec<_> = stats
+        .replay_costs
+        .iter()
+        .take_while(|(_, ops)| *ops > REPLAY_COST_WARNING)
+        .collect();
+    if !costly.is_empty() {
+        println!("\n{}", "Slow to reconstruct".yellow());
+        for (file_path, ops) in costly.iter().take(STATUS_RECENT_FILES) {
+            println!(
+                "  {} {} {}",
+                "⚠️".yellow(),
+                file_path.bright_white(),
+                format!("{} ops since last checkpoint", ops).bright_black()
+            );
+        }
+        println!(
+            "  Run {} to compact their history.",
+            "forge gc --aggressive".bright_white()
+        );
+    }
+
+    if !files.is_empty() {
+        println!("\n{}", "Recently changed".yellow());
+        for file in files.iter().take(STATUS_RECENT_FILES) {
+            let time = file.last_changed.format("%Y-%m-%d %H:%M:%S%.3f");
+            let name = if file.deleted {
+                format!("{} (deleted)", file.file_path).bright_red()
+            } else {
+                file.file_path.bright_white()
+            };
+            println!(
+                "  {} {} {}",
+                format!("[{}]", time).bright_black(),
+                name,
+                format!("{} ops", file.operation_count).bright_black()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn search(query: &str, limit: usize) -> Result<()> {
+    let db = Database::open(".dx/forge")?;
+    db.initialize()?;
+    let operations = db.search_content(query, limit)?;
+
+    println!(
+        "{}",
+        format!("Search results for \"{}\"", query).cyan().bold()
+    );
+    println!("{}", "═".repeat(80).bright_black());
+
+    for op in operations {
+        let time = op.timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
+        println!(
+            "{} {} {}",
             format!("[{}]", time).bright_black(),
-            op_type.bold(),
             op.file_path.bright_white(),
             format!("({})", op.id).bright_black()
         );
@@ -81,11 +432,160 @@ pub async fn show_log(file: Option<std::path::PathBuf>, limit: usize) -> Result<
     Ok(())
 }
 
+pub async fn export_ops(out: &Path, filter: QueryFilter) -> Result<usize> {
+    let db = Database::open(FORGE_DIR)?;
+    db.initialize()?;
+    portable::write_jsonl(&db, out, &filter)
+}
+
+pub async fn import_ops(input: &Path) -> Result<usize> {
+    let db = Database::open(FORGE_DIR)?;
+    db.initialize()?;
+    let count = portable::read_jsonl(&db, input)?;
+    if count > 0 {
+        reindex::rebuild(&db)?;
+    }
+    Ok(count)
+}
+
+pub async fn reindex(path: &Path) -> Result<()> {
+    let forge_path = path.join(FORGE_DIR);
+    let db = Database::new(&forge_path)?;
+    db.initialize()?;
+
+    let report = reindex::rebuild(&db)?;
+
+    println!("{}", "Reindex".cyan().bold());
+    println!("{}", "═".repeat(80).bright_black());
+    println!("  Files:                {}", report.files);
+    println!("  Checkpoints removed:  {}", report.checkpoints_removed);
+    println!("  Checkpoints written:  {}", report.checkpoints_written);
+    println!("  Operations indexed:   {}", report.operations_indexed);
+    println!("  File heads:           {}", report.heads);
+    println!("{} Derived data rebuilt from the operation log", "✓".green());
+
+    Ok(())
+}
+
+pub async fn gc(path: &Path, aggressive: bool) -> Result<()> {
+    let forge_path = path.join(FORGE_DIR);
+    let db = Database::new(&forge_path)?;
+    db.initialize()?;
+
+    let options = gc::GcOptions {
+        aggressive,
+        ..Default::default()
+    };
+    let report = gc::collect(&db, &forge_path, path, &options)?;
+
+    println!("{}", "Garbage collection".cyan().bold());
+    println!("{}", "═".repeat(80).bright_black());
+    println!("  Deleted files pruned: {}", report.pruned_files);
+    if aggressive {
+        println!("  Files compacted:      {}", report.compacted_files);
+    }
+    println!("  Operations removed:   {}", report.removed_operations);
+    println!("  Checkpoints removed:  {}", report.removed_checkpoints);
+    println!("  Anchors removed:      {}", report.removed_anchors);
+    println!("  Annotations removed:  {}", report.removed_annotations);
+    println!(
+        "{} Reclaimed {} bytes ({} → {})",
+        "✓".green(),
+        report.bytes_reclaimed().to_string().bright_white(),
+        report.bytes_before,
+        report.bytes_after
+    );
+
+    Ok(())
+}
+
+pub async fn fsck(path: &Path, repair: bool) -> Result<()> {
+    let forge_path = path.join(FORGE_DIR);
+    let db = Database::new(&forge_path)?;
+    db.initialize()?;
+
+    let report = fsck::check(&db, path, repair)?;
+
+    println!("{}", "Integrity check".cyan().bold());
+    println!("{}", "═".repeat(80).bright_black());
+    println!("  Operations checked:   {}", report.operations_checked);
+    println!("  Dangling parents:     {}", report.dangling_parents.len());
+    println!("  Malformed ops:        {}", report.malformed.len());
+    println!("  Corrupt checkpoints:  {}", report.corrupt_checkpoints.len());
+    println!("  Orphaned anchors:     {}", report.orphaned_anchors.len());
+    println!("  Orphaned annotations: {}", report.orphaned_annotations.len());
+
+    for dangling in &report.dangling_parents {
+        println!(
+            "  {} {} {} has missing parent {}",
+            "✗".red(),
+            dangling.file_path.bright_white(),
+            dangling.op_id.to_string().bright_black(),
+            dangling.parent_id
+        );
+    }
+    for broken in &report.malformed {
+        println!(
+            "  {} {} {} {}",
+            "✗".red(),
+            broken.file_path.bright_white(),
+            broken.op_id.to_string().bright_black(),
+            broken.reason
+        );
+    }
+    for checkpoint in &report.corrupt_checkpoints {
+        println!(
+            "  {} {} checkpoint at {}: {}",
+            "✗".red(),
+            checkpoint.file_path.bright_white(),
+            checkpoint.op_id.bright_black(),
+            checkpoint.reason
+        );
+    }
+
+    if report.is_clean() {
+        println!("{} No problems found", "✓".green());
+    } else if report.repaired {
+        println!(
+            "{} Repaired: dangling parents unlinked, malformed operations quarantined, corrupt checkpoints and orphaned context removed",
+            "✓".green()
+        );
+    } else {
+        println!(
+            "{} Run {} to fix these",
+            "⚠️".yellow(),
+            "forge fsck --repair".bright_white()
+        );
+    }
+
+    Ok(())
+}
+
+/// Manifest of the tracked tree in the current repository as of `at`
+/// (default: now).
+pub async fn build_tree(at: Option<DateTime<Utc>>) -> Result<TreeManifest> {
+    let repo_root = std::env::current_dir()?;
+    let repo_root = repo_root.canonicalize().unwrap_or(repo_root);
+    let db = Database::new(&repo_root.join(FORGE_DIR))?;
+    db.initialize()?;
+    tree::build(&db, &repo_root, at.unwrap_or_else(Utc::now))
+}
+
+/// Write the tracked tree of the current repository as of `at` (default:
+/// now) under `out`.
+pub async fn export_tree(at: Option<DateTime<Utc>>, out: &Path) -> Result<tree::ExportSummary> {
+    let repo_root = std::env::current_dir()?;
+    let repo_root = repo_root.canonicalize().unwrap_or(repo_root);
+    let db = Database::new(&repo_root.join(FORGE_DIR))?;
+    db.initialize()?;
+    tree::export(&db, &repo_root, at.unwrap_or_else(Utc::now), out)
+}
+
 pub async fn git_sync(path: &Path) -> Result<()> {
     git_interop::sync_with_git(path).await
 }
 
-pub async fn time_travel(file: &Path, timestamp: Option<String>) -> Result<()> {
+pub async fn time_travel(file: &Path, timestamp: Option<String>, diff: bool) -> Result<()> {
     println!(
         "{}",
         format!("🕐 Time traveling: {}", file.display())
@@ -105,8 +605,6 @@ pub async fn time_travel(file: &Path, timestamp: Option<String>) -> Result<()> {
     };
     let target_canon = normalize_path(&target_path);
 
-    let mut operations = db.get_operations(None, 2000)?;
-
     // Reconstruct file state at timestamp
     let target_time = if let Some(ts) = timestamp {
         chrono::DateTime::parse_from_rfc3339(&ts)?.with_timezone(&chrono::Utc)
@@ -114,55 +612,35 @@ pub async fn time_travel(file: &Path, timestamp: Option<String>) -> Result<()> {
         chrono::Utc::now()
     };
 
-    operations.retain(|op| {
-        op.timestamp <= target_time
-            && normalize_path(std::path::Path::new(&op.file_path)) == target_canon
-    });
-    operations.sort_by_key(|op| op.timestamp);
-
-    let mut rope = Rope::new();
+    let target_key = target_canon.display().to_string();
+    let recorded = reconstruct::reconstruct_at(&db, &target_key, target_time)?;
+    if diff {
+        print_drift(&target_path, recorded.as_deref());
+        return Ok(());
+    }
+    let content = recorded.unwrap_or_default();
 
-    for op in operations.iter() {
-        match &op.op_type {
-            crate::crdt::OperationType::FileCreate { content: c } => {
-                rope = Rope::from_str(c);
-            }
-            crate::crdt::OperationType::Insert {
-                position, content, ..
-            } => {
-                let char_idx = clamp_offset(&rope, position.offset);
-                rope.insert(char_idx, content);
-            }
-            crate::crdt::OperationType::Delete { position, length } => {
-                let start = clamp_offset(&rope, position.offset);
-                let end = clamp_offset(&rope, start + *length);
-                if start < end {
-                    rope.remove(start..end);
-                }
-            }
-            crate::crdt::OperationType::Replace {
-                position,
-                old_content,
-                new_content,
-            } => {
-                let start = clamp_offset(&rope, position.offset);
-                let end = clamp_offset(&rope, start + old_content.chars().count());
-                if start < end {
-                    rope.remove(start..end);
-                }
-                rope.insert(start, new_content);
-            }
-            crate::crdt::OperationType::FileDelete => {
-                rope = Rope::new();
-            }
-            crate::crdt::OperationType::FileRename { .. } => {
-                // Rename events are handled by resolving the target path above.
-            }
+    if let Some(bytes) = reconstruct::binary_at(&db, &target_key, target_time)? {
+        use std::io::{IsTerminal, Write};
+        // Raw bytes only go to a pipe or file, never the terminal
+        if std::io::stdout().is_terminal() {
+            println!(
+                "{} {} bytes (redirect to a file to restore it)",
+                "Binary:".bright_black(),
+                bytes.len()
+            );
+        } else {
+            std::io::stdout().write_all(&bytes)?;
         }
+        return Ok(());
     }
 
-    let content = rope.to_string();
-
+    if let Some(mode) = reconstruct::mode_at(&db, &target_key, target_time)? {
+        println!("{} {:o}", "Mode:".bright_black(), mode);
+    }
+    if let Some(target) = reconstruct::symlink_target_at(&db, &target_key, target_time)? {
+        println!("{} → {}", "Symlink:".bright_black(), target.bright_cyan());
+    }
     println!("\n{}", "─".repeat(80).bright_black());
     println!("{}", content);
     println!("{}", "─".repeat(80).bright_black());
@@ -170,10 +648,160 @@ pub async fn time_travel(file: &Path, timestamp: Option<String>) -> Result<()> {
     Ok(())
 }
 
-fn normalize_path(path: &Path) -> std::path::PathBuf {
-    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+/// Find the operation that introduced a condition in `file`. `command` is
+/// run through `sh -c` with each candidate version on stdin; exiting with
+/// status 0 means the condition holds.
+pub async fn run_bisect(file: &Path, command: &str) -> Result<()> {
+    println!(
+        "{}",
+        format!("🔎 Bisecting: {}", file.display()).cyan().bold()
+    );
+
+    let repo_root = std::env::current_dir()?;
+    let db = Database::new(&repo_root.join(FORGE_DIR))?;
+    db.initialize()?;
+
+    let target_path = if file.is_absolute() {
+        file.to_path_buf()
+    } else {
+        repo_root.join(file)
+    };
+    let target_key = normalize_path(&target_path).display().to_string();
+
+    let checks = std::cell::Cell::new(0usize);
+    let culprit = bisect(&db, &target_key, |content| {
+        checks.set(checks.get() + 1);
+        run_predicate(command, content).unwrap_or_else(|err| {
+            eprintln!("{} {}", "⚠".yellow(), err);
+            false
+        })
+    })?;
+
+    match culprit {
+        Some(op) => {
+            println!(
+                "{} First operation where the condition holds ({} checks):",
+                "✓".green(),
+                checks.get()
+            );
+            print_op_line(&op, &db.actors()?);
+        }
+        None => println!(
+            "{} The condition doesn't hold for the latest version",
+            "→".bright_blue()
+        ),
+    }
+    Ok(())
 }
 
-fn clamp_offset(rope: &Rope, offset: usize) -> usize {
-    offset.min(rope.len_chars())
+/// Squash rapid runs of edits to `file` into one operation each. Only this
+/// repository's own actor's edits are squashed unless `all_actors` is set.
+pub async fn run_squash(
+    file: &Path,
+    window_ms: u64,
+    actor: Option<String>,
+    all_actors: bool,
+) -> Result<()> {
+    let repo_root = std::env::current_dir()?;
+    let forge_path = repo_root.join(FORGE_DIR);
+    let db = Database::new(&forge_path)?;
+    db.initialize()?;
+
+    let actor = match actor {
+        Some(actor) => Some(actor),
+        None if all_actors => None,
+        None => {
+            let config = ForgeConfig::load(&forge_path)?;
+            if config.actor_id.is_empty() {
+                anyhow::bail!("config.json has no actor_id; pass --actor or --all-actors");
+            }
+            Some(config.actor_id)
+        }
+    };
+
+    let target_path = if file.is_absolute() {
+        file.to_path_buf()
+    } else {
+        repo_root.join(file)
+    };
+    let target_key = normalize_path(&target_path).display().to_string();
+    let window = chrono::Duration::milliseconds(window_ms as i64);
+    let removed = squash(&db, &target_key, window, actor.as_deref())?;
+
+    if removed == 0 {
+        println!("{} Nothing to squash in {}", "→".bright_blue(), file.display());
+    } else {
+        println!(
+            "{} Squashed {} operations out of {}",
+            "✓".green(),
+            removed.to_string().bright_white(),
+            file.display()
+        );
+    }
+    Ok(())
+}
+
+/// Run `command` with `content` on stdin; true if it exits successfully.
+fn run_predicate(command: &str, content: &str) -> Result<bool> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that exits without reading all of stdin closes the pipe
+        if let Err(err) = stdin.write_all(content.as_bytes())
+            && err.kind() != std::io::ErrorKind::BrokenPipe
+        {
+            return Err(err.into());
+        }
+    }
+    Ok(child.wait()?.success())
+}
+
+/// Diff the recorded state of a file against what's on disk now, showing
+/// edits made while no watcher was recording them.
+fn print_drift(path: &Path, recorded: Option<&str>) {
+    let on_disk = std::fs::read_to_string(path).ok();
+    match (recorded, &on_disk) {
+        (None, None) => {
+            println!("{} No recorded history and nothing on disk", "→".bright_blue());
+            return;
+        }
+        (None, Some(_)) => println!(
+            "{} No recorded operations; the whole file is new",
+            "→".bright_blue()
+        ),
+        (Some(_), None) => println!("{} Deleted on disk", "→".bright_blue()),
+        (Some(old), Some(new)) if old == new => {
+            println!("{} No changes since the recorded state", "✓".green());
+            return;
+        }
+        _ => {}
+    }
+
+    let old = recorded.unwrap_or_default();
+    let new = on_disk.as_deref().unwrap_or_default();
+    println!("{}", "--- recorded".red());
+    println!("{}", "+++ on disk".green());
+    let diff = similar::TextDiff::from_lines(old, new);
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        println!("{}", hunk.header().to_string().cyan());
+        for change in hunk.iter_changes() {
+            let line = format!("{}{}", change.tag(), change.value().trim_end_matches('\n'));
+            match change.tag() {
+                similar::ChangeTag::Delete => println!("{}", line.red()),
+                similar::ChangeTag::Insert => println!("{}", line.green()),
+                similar::ChangeTag::Equal => println!("{}", line),
+            }
+        }
+    }
+}
+
+fn normalize_path(path: &Path) -> std::path::PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
 }
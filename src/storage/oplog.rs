@@ -1,55 +1,455 @@
 use anyhow::{Result, anyhow};
-use crossbeam::channel::{self, Sender};
+use chrono::Utc;
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender};
 use dashmap::DashMap;
+use parking_lot::Mutex;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 use super::Database;
+use super::db::Checkpoint;
+use super::reconstruct;
+use super::store::OperationStore;
 use crate::crdt::Operation;
 
-pub struct OperationLog {
+/// Number of operations per file between automatic checkpoints.
+pub const DEFAULT_CHECKPOINT_INTERVAL: usize = 500;
+
+/// How the background writer batches operations. Buffering trades a short
+/// window in which recorded operations exist only in memory for far fewer
+/// store writes. The default writes each operation as soon as it arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteBuffer {
+    /// Longest an operation waits in memory before it's written. Zero
+    /// disables buffering.
+    pub flush_interval: Duration,
+    /// Write as soon as this many operations are waiting.
+    pub max_buffered: usize,
+}
+
+impl Default for WriteBuffer {
+    fn default() -> Self {
+        Self {
+            flush_interval: Duration::ZERO,
+            max_buffered: 1,
+        }
+    }
+}
+
+/// How fast operations are being recorded, from
+/// [`OperationLog::throughput_snapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThroughputStats {
+    /// Operations recorded since the log was opened.
+    pub total_ops: u64,
+    /// Recording rate over roughly the last second.
+    pub ops_per_sec: f64,
+    /// Microseconds the most recent operation took from detection to append.
+    pub last_op_us: u64,
+}
+
+// Rate windows shorter than this are too noisy to report on their own
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(1);
+
+struct Throughput {
+    total_ops: AtomicU64,
+    last_op_us: AtomicU64,
+    // Rate of the last completed window, as f64 bits
+    ops_per_sec: AtomicU64,
+    // Start of the current window and the total when it began
+    window: Mutex<(Instant, u64)>,
+}
+
+impl Throughput {
+    fn new() -> Self {
+        Self {
+            total_ops: AtomicU64::new(0),
+            last_op_us: AtomicU64::new(0),
+            ops_per_sec: AtomicU64::new(0f64.to_bits()),
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    fn record(&self, micros: u64) {
+        let total = self.total_ops.fetch_add(1, Ordering::Relaxed) + 1;
+        self.last_op_us.store(micros, Ordering::Relaxed);
+
+        let mut window = self.window.lock();
+        let elapsed = window.0.elapsed();
+        if elapsed >= THROUGHPUT_WINDOW {
+            let rate = (total - window.1) as f64 / elapsed.as_secs_f64();
+            self.ops_per_sec.store(rate.to_bits(), Ordering::Relaxed);
+            *window = (Instant::now(), total);
+        }
+    }
+
+    fn snapshot(&self) -> ThroughputStats {
+        let total_ops = self.total_ops.load(Ordering::Relaxed);
+        let window = self.window.lock();
+        let elapsed = window.0.elapsed();
+        // A window left open past its length means recording slowed down or
+        // stopped, so its own rate is more current than the last completed one
+        let ops_per_sec = if elapsed >= THROUGHPUT_WINDOW {
+            (total_ops - window.1) as f64 / elapsed.as_secs_f64()
+        } else {
+            f64::from_bits(self.ops_per_sec.load(Ordering::Relaxed))
+        };
+        ThroughputStats {
+            total_ops,
+            ops_per_sec,
+            last_op_us: self.last_op_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+enum WriterMessage {
+    Append(Operation),
+    // Write everything buffered, then signal the sender
+    Flush(Sender<()>),
+}
+
+/// Append-only log of operations, persisted to `S` on a background thread.
+/// Uses the SQLite [`Database`] unless another store is given.
+pub struct OperationLog<S: OperationStore = Database> {
     // In-memory cache for fast lookups and deduplication
     cache: DashMap<Uuid, Operation>,
-    queue: Sender<Operation>,
+    queue: Sender<WriterMessage>,
+    db: Arc<S>,
+    // Most recent operation per file, used as the parent of the next local op
+    heads: DashMap<String, Uuid>,
+    // Serializes head lookup, append and head update for a single file
+    file_locks: DashMap<String, Arc<Mutex<()>>>,
+    throughput: Throughput,
 }
 
-impl OperationLog {
-    pub fn new(db: Arc<Database>) -> Self {
-        let (tx, rx) = channel::unbounded::<Operation>();
+impl<S: OperationStore> OperationLog<S> {
+    pub fn new(db: Arc<S>) -> Self {
+        Self::with_checkpoint_interval(db, DEFAULT_CHECKPOINT_INTERVAL)
+    }
+
+    /// Create a log that snapshots a file's full content every
+    /// `checkpoint_interval` persisted operations. An interval of 0 disables
+    /// automatic checkpoints.
+    pub fn with_checkpoint_interval(db: Arc<S>, checkpoint_interval: usize) -> Self {
+        Self::with_write_buffer(db, checkpoint_interval, WriteBuffer::default())
+    }
+
+    /// Like [`with_checkpoint_interval`](Self::with_checkpoint_interval),
+    /// with operations batched by `buffer` before they're written.
+    pub fn with_write_buffer(db: Arc<S>, checkpoint_interval: usize, buffer: WriteBuffer) -> Self {
+        // Resume each file's chain where the persisted log left off
+        let heads = match db.file_heads() {
+            Ok(heads) => heads.into_iter().collect(),
+            Err(err) => {
+                eprintln!("⚠️  Failed to load file heads: {err}");
+                DashMap::new()
+            }
+        };
+
+        let (tx, rx) = channel::unbounded::<WriterMessage>();
         let worker_db = db.clone();
         thread::Builder::new()
             .name("forge-oplog-writer".to_string())
-            .spawn(move || {
-                while let Ok(op) = rx.recv() {
-                    if let Err(err) = worker_db.store_operation(&op) {
-                        eprintln!("⚠️  Failed to persist operation {}: {err}", op.id);
-                    }
-                }
-            })
+            .spawn(move || run_writer(worker_db.as_ref(), rx, checkpoint_interval, buffer))
             .expect("failed to spawn oplog writer thread");
 
         Self {
             cache: DashMap::new(),
             queue: tx,
+            db,
+            heads,
+            file_locks: DashMap::new(),
+            throughput: Throughput::new(),
         }
     }
 
+    /// Append an operation as-is (e.g. one received from a peer) and make it
+    /// the file's head.
     pub fn append(&self, operation: Operation) -> Result<bool> {
+        let lock = self.file_lock(&operation.file_path);
+        let _guard = lock.lock();
+        self.append_locked(operation)
+    }
+
+    /// Append an operation produced on this machine, chaining it to the
+    /// file's current head. Returns the stored operation, or `None` if it was
+    /// already known.
+    pub fn append_local(&self, mut operation: Operation) -> Result<Option<Operation>> {
+        let lock = self.file_lock(&operation.file_path);
+        let _guard = lock.lock();
+
+        if operation.parent_ops.is_empty()
+            && let Some(head) = self.heads.get(&operation.file_path)
+        {
+            operation = operation.with_parents(vec![*head]);
+        }
+
+        if self.append_locked(operation.clone())? {
+            Ok(Some(operation))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Count an operation recorded by the watcher, which took `micros` from
+    /// detection to append.
+    pub fn record_throughput(&self, micros: u64) {
+        self.throughput.record(micros);
+    }
+
+    /// Current recording throughput, for status output or metrics.
+    pub fn throughput_snapshot(&self) -> ThroughputStats {
+        self.throughput.snapshot()
+    }
+
+    /// Carry a file's head over to its new path after a rename.
+    pub fn move_head(&self, old_path: &str, new_path: String) {
+        if let Some((_, head)) = self.heads.remove(old_path) {
+            self.heads.insert(new_path, head);
+        }
+    }
+
+    /// Forget a file's head so its next operation starts a fresh chain.
+    pub fn clear_head(&self, file_path: &str) {
+        self.heads.remove(file_path);
+    }
+
+    fn file_lock(&self, file_path: &str) -> Arc<Mutex<()>> {
+        self.file_locks
+            .entry(file_path.to_string())
+            .or_default()
+            .clone()
+    }
+
+    // Caller must hold the file lock for `operation.file_path`.
+    fn append_locked(&self, operation: Operation) -> Result<bool> {
         let is_new = self.cache.insert(operation.id, operation.clone()).is_none();
         if !is_new {
             return Ok(false);
         }
 
+        self.heads.insert(operation.file_path.clone(), operation.id);
         self.queue
-            .send(operation)
+            .send(WriterMessage::Append(operation))
             .map_err(|err| anyhow!("failed to enqueue operation for persistence: {err}"))?;
 
         Ok(true)
     }
 
-    #[allow(dead_code)]
+    /// Block until every operation appended so far has been written.
+    pub fn flush(&self) {
+        let (ack, done) = channel::bounded(1);
+        if self.queue.send(WriterMessage::Flush(ack)).is_ok() {
+            let _ = done.recv();
+        }
+    }
+
+    pub fn db(&self) -> &Arc<S> {
+        &self.db
+    }
+
     pub fn get(&self, id: &Uuid) -> Option<Operation> {
         self.cache.get(id).map(|op| op.clone())
     }
+
+    /// Record `content` as the full state of `file` right after `op_id`.
+    #[allow(dead_code)]
+    pub fn checkpoint(&self, file: &str, content: &str, op_id: Uuid) -> Result<()> {
+        let timestamp = self
+            .cache
+            .get(&op_id)
+            .map(|op| op.timestamp)
+            .unwrap_or_else(Utc::now);
+        self.db.store_checkpoint(&Checkpoint::new(
+            file.to_string(),
+            op_id,
+            timestamp,
+            content.to_string(),
+        )?)
+    }
+}
+
+impl<S: OperationStore> Drop for OperationLog<S> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+// Body of the writer thread: buffer operations per `buffer`, write them in
+// batches and take checkpoints as files pass `checkpoint_interval`.
+fn run_writer<S: OperationStore>(
+    db: &S,
+    rx: Receiver<WriterMessage>,
+    checkpoint_interval: usize,
+    buffer: WriteBuffer,
+) {
+    let mut since_checkpoint: HashMap<String, usize> = HashMap::new();
+    let mut pending: Vec<Operation> = Vec::new();
+    let mut deadline: Option<Instant> = None;
+
+    let mut write = |pending: &mut Vec<Operation>| {
+        if pending.is_empty() {
+            return;
+        }
+        let stored = match db.append_many(pending) {
+            Ok(stored) => stored,
+            Err(err) => {
+                eprintln!("⚠️  Failed to persist {} operation(s): {err}", pending.len());
+                pending.clear();
+                return;
+            }
+        };
+        for (op, is_new) in pending.drain(..).zip(stored) {
+            if !is_new || checkpoint_interval == 0 {
+                continue;
+            }
+            let count = since_checkpoint.entry(op.file_path.clone()).or_insert(0);
+            *count += 1;
+            if *count >= checkpoint_interval {
+                *count = 0;
+                if let Err(err) = write_checkpoint(db, &op) {
+                    eprintln!("⚠️  Failed to checkpoint {}: {err}", op.file_path);
+                }
+            }
+        }
+    };
+
+    loop {
+        let message = match deadline {
+            Some(at) => match rx.recv_deadline(at) {
+                Ok(message) => Some(message),
+                Err(RecvTimeoutError::Timeout) => {
+                    write(&mut pending);
+                    deadline = None;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => None,
+            },
+            None => rx.recv().ok(),
+        };
+
+        match message {
+            Some(WriterMessage::Append(op)) => {
+                pending.push(op);
+                if buffer.flush_interval.is_zero() || pending.len() >= buffer.max_buffered {
+                    write(&mut pending);
+                    deadline = None;
+                } else if deadline.is_none() {
+                    deadline = Some(Instant::now() + buffer.flush_interval);
+                }
+            }
+            Some(WriterMessage::Flush(ack)) => {
+                write(&mut pending);
+                deadline = None;
+                let _ = ack.send(());
+            }
+            None => {
+                write(&mut pending);
+                break;
+            }
+        }
+    }
+}
+
+fn write_checkpoint<S: OperationStore>(db: &S, op: &Operation) -> Result<()> {
+    // Checkpoints always describe an existing file; a deleted one has nothing
+    // worth snapshotting.
+    let Some(content) = reconstruct::reconstruct_through(db, op)? else {
+        return Ok(());
+    };
+    db.store_checkpoint(&Checkpoint::new(
+        op.file_path.clone(),
+        op.id,
+        op.timestamp,
+        content,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::OperationType;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn concurrent_local_appends_form_a_single_chain() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+        let oplog = Arc::new(OperationLog::new(Arc::new(db)));
+
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let oplog = oplog.clone();
+                thread::spawn(move || {
+                    (0..50)
+                        .map(|_| {
+                            let op = Operation::new(
+                                "a.txt".to_string(),
+                                OperationType::FileDelete,
+                                format!("actor-{worker}"),
+                            );
+                            oplog.append_local(op).unwrap().unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let ops: Vec<Operation> = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+
+        // Exactly one root, and no operation is the parent of two others.
+        let roots = ops.iter().filter(|op| op.parent_ops.is_empty()).count();
+        assert_eq!(roots, 1);
+        let parents: HashSet<Uuid> = ops.iter().flat_map(|op| op.parent_ops.clone()).collect();
+        assert_eq!(parents.len(), ops.len() - 1);
+    }
+
+    #[test]
+    fn buffered_operations_are_written_on_flush() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        db.initialize().unwrap();
+        let oplog = OperationLog::with_write_buffer(
+            db.clone(),
+            DEFAULT_CHECKPOINT_INTERVAL,
+            WriteBuffer {
+                flush_interval: Duration::from_secs(60),
+                max_buffered: 100,
+            },
+        );
+
+        for _ in 0..3 {
+            let op = Operation::new("a.txt".into(), OperationType::FileDelete, "actor".into());
+            let op = oplog.append_local(op).unwrap().unwrap();
+            assert!(oplog.get(&op.id).is_some());
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(db.count_operations(&Default::default()).unwrap(), 0);
+
+        oplog.flush();
+        assert_eq!(db.count_operations(&Default::default()).unwrap(), 3);
+    }
+
+    #[test]
+    fn throughput_counts_recorded_operations() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Arc::new(Database::new(temp_dir.path()).unwrap());
+        db.initialize().unwrap();
+        let oplog = OperationLog::new(db);
+        assert_eq!(oplog.throughput_snapshot(), ThroughputStats::default());
+
+        oplog.record_throughput(40);
+        oplog.record_throughput(25);
+        let stats = oplog.throughput_snapshot();
+        assert_eq!(stats.total_ops, 2);
+        assert_eq!(stats.last_op_us, 25);
+    }
 }
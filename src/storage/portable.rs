@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+use super::db::{Database, QueryFilter};
+use crate::crdt::Operation;
+
+/// Operations read from the database per page while exporting.
+const EXPORT_BATCH_SIZE: usize = 1_000;
+
+/// Write the operations matching `filter` to `out` as JSON Lines in replay
+/// order (`Operation::order_key`), so peers holding the same operations
+/// export identical files. Returns the number of operations written.
+pub fn write_jsonl(db: &Database, out: &Path, filter: &QueryFilter) -> Result<usize> {
+    let file = File::create(out).with_context(|| format!("creating {}", out.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    let mut written = 0;
+    for op in db.iter_operations_causal(filter, EXPORT_BATCH_SIZE) {
+        serde_json::to_writer(&mut writer, &op?)?;
+        writer.write_all(b"\n")?;
+        written += 1;
+    }
+    writer.flush()?;
+
+    Ok(written)
+}
+
+/// Append the operations stored in a JSON Lines file to `db`. Operations keep
+/// their original ids, timestamps and actors; ids already present are skipped.
+/// Returns the number of operations that were newly stored.
+pub fn read_jsonl(db: &Database, input: &Path) -> Result<usize> {
+    let file = File::open(input).with_context(|| format!("opening {}", input.display()))?;
+    let reader = BufReader::new(file);
+
+    let mut operations = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let op: Operation = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{}: invalid operation", input.display(), idx + 1))?;
+        operations.push(op);
+    }
+
+    let mut imported = 0;
+    for op in causal_order(operations) {
+        if db.store_operation(&op)? {
+            imported += 1;
+        }
+    }
+
+    Ok(imported)
+}
+
+/// Order operations so every parent contained in the batch is stored before
+/// its children. Parents outside the batch are assumed to exist already.
+fn causal_order(operations: Vec<Operation>) -> Vec<Operation> {
+    let order: Vec<Uuid> = operations.iter().map(|op| op.id).collect();
+    let mut pending: HashMap<Uuid, Operation> =
+        operations.into_iter().map(|op| (op.id, op)).collect();
+    let mut ordered = Vec::with_capacity(order.len());
+
+    // Depth-first on an explicit stack of (operation, next parent to visit),
+    // so a long chain listed children first can't overflow the call stack
+    let mut stack: Vec<(Operation, usize)> = Vec::new();
+    for id in order {
+        let Some(op) = pending.remove(&id) else {
+            continue;
+        };
+        stack.push((op, 0));
+        while let Some((op, next)) = stack.last_mut() {
+            if let Some(&parent) = op.parent_ops.get(*next) {
+                *next += 1;
+                if let Some(parent) = pending.remove(&parent) {
+                    stack.push((parent, 0));
+                }
+            } else if let Some((op, _)) = stack.pop() {
+                ordered.push(op);
+            }
+        }
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::OperationType;
+    use tempfile::TempDir;
+
+    #[test]
+    fn jsonl_roundtrip_preserves_operations() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = Database::new(temp_dir.path()).unwrap();
+        source.initialize().unwrap();
+
+        let create = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "alice".into(),
+        );
+        let delete = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileDelete,
+            "alice".into(),
+        )
+        .with_parents(vec![create.id]);
+        source.store_operation(&create).unwrap();
+        source.store_operation(&delete).unwrap();
+
+        let export_path = temp_dir.path().join("ops.jsonl");
+        let written = write_jsonl(&source, &export_path, &QueryFilter::default()).unwrap();
+        assert_eq!(written, 2);
+
+        let target_dir = temp_dir.path().join("target");
+        std::fs::create_dir_all(&target_dir).unwrap();
+        let target = Database::new(&target_dir).unwrap();
+        target.initialize().unwrap();
+
+        assert_eq!(read_jsonl(&target, &export_path).unwrap(), 2);
+        assert_eq!(read_jsonl(&target, &export_path).unwrap(), 0);
+
+        let imported = target.query_operations(&QueryFilter::default()).unwrap();
+        let restored = imported.iter().find(|op| op.id == create.id).unwrap();
+        assert_eq!(restored.timestamp, create.timestamp);
+        assert_eq!(restored.actor_id, "alice");
+    }
+
+    #[test]
+    fn causal_order_puts_parents_first() {
+        let parent = Operation::new("a.txt".to_string(), OperationType::FileDelete, "a".into());
+        let child = Operation::new("a.txt".to_string(), OperationType::FileDelete, "a".into())
+            .with_parents(vec![parent.id]);
+
+        let ordered = causal_order(vec![child.clone(), parent.clone()]);
+        assert_eq!(ordered[0].id, parent.id);
+        assert_eq!(ordered[1].id, child.id);
+    }
+
+    #[test]
+    fn causal_order_handles_long_chains_listed_children_first() {
+        let mut chain = vec![Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileDelete,
+            "a".into(),
+        )];
+        for _ in 1..100_000 {
+            let parent = chain.last().unwrap().id;
+            chain.push(
+                Operation::new("a.txt".to_string(), OperationType::FileDelete, "a".into())
+                    .with_parents(vec![parent]),
+            );
+        }
+        let expected: Vec<Uuid> = chain.iter().map(|op| op.id).collect();
+        chain.reverse();
+
+        let ordered: Vec<Uuid> = causal_order(chain).iter().map(|op| op.id).collect();
+        assert_eq!(ordered, expected);
+    }
+}
@@ -0,0 +1,470 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use super::db::{Database, QueryFilter};
+use super::store::OperationStore;
+use crate::crdt::{CrdtDocument, Operation, OperationType};
+
+/// Rebuild the content of `file_path` as of `target_time`. Replay starts from
+/// the newest checkpoint at or before the target, so only the operations
+/// recorded after that checkpoint are applied. Returns `None` when the file
+/// had no history by then or had been deleted.
+pub fn reconstruct_at<S: OperationStore + ?Sized>(
+    db: &S,
+    file_path: &str,
+    target_time: DateTime<Utc>,
+) -> Result<Option<String>> {
+    reconstruct_following_renames(db, file_path, target_time, MAX_RENAME_DEPTH)
+}
+
+/// How many renames back a reconstruction will follow.
+const MAX_RENAME_DEPTH: usize = 32;
+
+fn reconstruct_following_renames<S: OperationStore + ?Sized>(
+    db: &S,
+    file_path: &str,
+    target_time: DateTime<Utc>,
+    depth: usize,
+) -> Result<Option<String>> {
+    let filter = QueryFilter {
+        file: Some(file_path.into()),
+        until: Some(target_time),
+        ..Default::default()
+    };
+    replay(db, file_path, &filter, target_time, None, depth)
+}
+
+/// Content of `op`'s file once every operation up to and including `op` in
+/// replay order is applied, which is what a checkpoint at `op` holds.
+/// `None` if the file doesn't exist at that point.
+pub fn reconstruct_through<S: OperationStore + ?Sized>(
+    db: &S,
+    op: &Operation,
+) -> Result<Option<String>> {
+    let filter = QueryFilter {
+        file: Some(op.file_path.clone().into()),
+        ..Default::default()
+    };
+    replay(
+        db,
+        &op.file_path,
+        &filter,
+        op.timestamp,
+        Some(op),
+        MAX_RENAME_DEPTH,
+    )
+}
+
+/// Replay the operations matching `filter`, up to `through` if given, from
+/// the newest usable checkpoint taken at or before `checkpoint_time`.
+fn replay<S: OperationStore + ?Sized>(
+    db: &S,
+    file_path: &str,
+    filter: &QueryFilter,
+    checkpoint_time: DateTime<Utc>,
+    through: Option<&Operation>,
+    depth: usize,
+) -> Result<Option<String>> {
+    // A checkpoint holds everything up to its operation in replay order, so
+    // replay resumes after that operation in the same order. Resuming after
+    // its timestamp would drop operations recorded under a clock that was
+    // stepped back.
+    let checkpoint = match db.latest_checkpoint(file_path, checkpoint_time)? {
+        Some(cp) => db
+            .operation(&cp.op_id)?
+            .filter(|op| through.is_none_or(|through| op.order_key() <= through.order_key()))
+            .map(|op| (cp, op)),
+        None => None,
+    };
+
+    let mut exists = checkpoint.is_some();
+    let (base, mut operations) = match &checkpoint {
+        Some((cp, op)) => (
+            cp.content.as_str(),
+            db.query_operations_causal_after(filter, op)?,
+        ),
+        // Replay in the CRDT's total order so every peer reconstructs the
+        // same content no matter the order operations were received in.
+        None => ("", db.query_operations_causal(filter)?),
+    };
+    if let Some(through) = through {
+        operations.retain(|op| op.order_key() <= through.order_key());
+    }
+
+    let document = CrdtDocument::new(file_path.into(), base);
+    for op in &operations {
+        match &op.op_type {
+            // A rename carries no content of its own; the file starts out as
+            // whatever the source path held at that moment.
+            OperationType::FileRename { old_path, .. } if depth > 0 => {
+                let content = reconstruct_following_renames(db, old_path, op.timestamp, depth - 1)?
+                    .unwrap_or_default();
+                document.apply(&Operation {
+                    op_type: OperationType::FileCreate { content },
+                    ..op.clone()
+                })?;
+            }
+            _ => document.apply(op)?,
+        }
+        // A directory has no content to reconstruct
+        exists = !matches!(op.op_type, OperationType::FileDelete) && !op.op_type.is_dir_op();
+    }
+
+    Ok(exists.then(|| document.get_content()))
+}
+
+/// Permission bits of `file_path` as of `target_time`, if a mode change was
+/// recorded for it since it was last created. A renamed file keeps the mode
+/// it had under its old name.
+pub fn mode_at(db: &Database, file_path: &str, target_time: DateTime<Utc>) -> Result<Option<u32>> {
+    mode_following_renames(db, file_path, target_time, MAX_RENAME_DEPTH)
+}
+
+fn mode_following_renames(
+    db: &Database,
+    file_path: &str,
+    target_time: DateTime<Utc>,
+    depth: usize,
+) -> Result<Option<u32>> {
+    let changes = db.operations_of_types(
+        &["ChmodChange", "FileCreate", "FileDelete", "FileRename"],
+        Some(file_path),
+        target_time,
+    )?;
+    let Some(op) = changes.last() else {
+        return Ok(None);
+    };
+    match &op.op_type {
+        OperationType::ChmodChange { new_mode, .. } => Ok(Some(*new_mode)),
+        OperationType::FileRename { old_path, .. } if depth > 0 => {
+            mode_following_renames(db, old_path, op.timestamp, depth - 1)
+        }
+        // Anything before the file was last created belongs to an earlier
+        // file at the same path
+        _ => Ok(None),
+    }
+}
+
+/// Kinds of operation whose newest decides whether a path is a symlink.
+pub(crate) const SYMLINK_TYPES: &[&str] =
+    &["SymlinkCreate", "SymlinkChange", "FileCreate", "FileDelete"];
+
+/// Kinds of operation whose newest decides whether a file's content is binary.
+pub(crate) const CONTENT_TYPES: &[&str] = &[
+    "Insert",
+    "Delete",
+    "Replace",
+    "FileCreate",
+    "FileDelete",
+    "FileRename",
+    "SymlinkCreate",
+    "SymlinkChange",
+    "BinaryModify",
+];
+
+/// Target of `file_path` as of `target_time` if it was a symlink then.
+pub fn symlink_target_at(
+    db: &Database,
+    file_path: &str,
+    target_time: DateTime<Utc>,
+) -> Result<Option<String>> {
+    let changes = db.operations_of_types(SYMLINK_TYPES, Some(file_path), target_time)?;
+    Ok(changes.into_iter().last().and_then(|op| match op.op_type {
+        OperationType::SymlinkCreate { target }
+        | OperationType::SymlinkChange {
+            new_target: target, ..
+        } => Some(target),
+        _ => None,
+    }))
+}
+
+/// Binary content of `file_path` as of `target_time`, if its last content
+/// change by then was a `BinaryModify` whose content was stored as a blob.
+pub fn binary_at(
+    db: &Database,
+    file_path: &str,
+    target_time: DateTime<Utc>,
+) -> Result<Option<Vec<u8>>> {
+    match binary_hash_at(db, file_path, target_time)? {
+        Some(hash) => db.blob(&hash),
+        None => Ok(None),
+    }
+}
+
+/// Hash of the binary content of `file_path` as of `target_time`, if its
+/// last content change by then was a `BinaryModify`.
+pub fn binary_hash_at(
+    db: &Database,
+    file_path: &str,
+    target_time: DateTime<Utc>,
+) -> Result<Option<String>> {
+    let changes = db.operations_of_types(CONTENT_TYPES, Some(file_path), target_time)?;
+    Ok(match changes.into_iter().last().map(|op| op.op_type) {
+        Some(OperationType::BinaryModify { new_hash, .. }) => Some(new_hash),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Operation, Position};
+    use crate::storage::db::Checkpoint;
+    use tempfile::TempDir;
+
+    #[test]
+    fn replays_from_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let create = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&create).unwrap();
+
+        // A checkpoint whose content differs from the raw replay proves the
+        // reconstruction started from it rather than from the first op.
+        db.store_checkpoint(
+            &Checkpoint::new(
+                "a.txt".to_string(),
+                create.id,
+                create.timestamp,
+                "HELLO".to_string(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        let insert = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, 6, 5, "actor".into(), 1),
+                content: " world".into(),
+                length: 6,
+            },
+            "actor".into(),
+        );
+        db.store_operation(&insert).unwrap();
+
+        let content = reconstruct_at(&db, "a.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("HELLO world"));
+        assert!(
+            reconstruct_at(&db, "missing.txt", Utc::now())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn replays_operations_recorded_after_a_clock_step() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let create = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&create).unwrap();
+        db.store_checkpoint(
+            &Checkpoint::new(
+                "a.txt".to_string(),
+                create.id,
+                create.timestamp,
+                "hello".to_string(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        // The wall clock stepped back between the two edits, but the insert
+        // still follows the checkpointed create in replay order.
+        let mut insert = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, 6, 5, "actor".into(), create.lamport + 1),
+                content: " world".into(),
+                length: 6,
+            },
+            "actor".into(),
+        );
+        insert.timestamp = create.timestamp - chrono::Duration::minutes(1);
+        assert!(insert.order_key() > create.order_key());
+        db.store_operation(&insert).unwrap();
+
+        let content = reconstruct_at(&db, "a.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("hello world"));
+        let through = reconstruct_through(&db, &insert).unwrap();
+        assert_eq!(through.as_deref(), Some("hello world"));
+    }
+
+    #[test]
+    fn follows_renames_to_the_source_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let create = Operation::new(
+            "old.txt".to_string(),
+            OperationType::FileCreate {
+                content: "moved".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&create).unwrap();
+        let rename = Operation::new(
+            "new.txt".to_string(),
+            OperationType::FileRename {
+                old_path: "old.txt".into(),
+                new_path: "new.txt".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&rename).unwrap();
+
+        let content = reconstruct_at(&db, "new.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("moved"));
+    }
+
+    #[test]
+    fn symlink_target_tracks_the_latest_link_operation() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let link = Operation::new(
+            "link".to_string(),
+            OperationType::SymlinkCreate {
+                target: "a.txt".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&link).unwrap();
+        let repoint = Operation::new(
+            "link".to_string(),
+            OperationType::SymlinkChange {
+                old_target: "a.txt".into(),
+                new_target: "b.txt".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&repoint).unwrap();
+
+        let target = symlink_target_at(&db, "link", Utc::now()).unwrap();
+        assert_eq!(target.as_deref(), Some("b.txt"));
+        let before = symlink_target_at(&db, "link", link.timestamp).unwrap();
+        assert_eq!(before.as_deref(), Some("a.txt"));
+
+        let replaced = Operation::new(
+            "link".to_string(),
+            OperationType::FileCreate {
+                content: "plain".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&replaced).unwrap();
+        assert!(
+            symlink_target_at(&db, "link", Utc::now())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn mode_follows_renames_and_resets_on_recreate() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let steps = [
+            (
+                "a.sh",
+                OperationType::ChmodChange {
+                    old_mode: 0o644,
+                    new_mode: 0o755,
+                },
+            ),
+            (
+                "b.sh",
+                OperationType::FileRename {
+                    old_path: "a.sh".into(),
+                    new_path: "b.sh".into(),
+                },
+            ),
+            (
+                "b.sh",
+                OperationType::ChmodChange {
+                    old_mode: 0o755,
+                    new_mode: 0o700,
+                },
+            ),
+            ("b.sh", OperationType::FileDelete),
+            (
+                "b.sh",
+                OperationType::FileCreate {
+                    content: "plain".into(),
+                },
+            ),
+        ];
+        let start = Utc::now() - chrono::Duration::seconds(10);
+        let mut times = Vec::new();
+        for (idx, (path, op_type)) in steps.into_iter().enumerate() {
+            let mut op = Operation::new(path.to_string(), op_type, "actor".into());
+            op.timestamp = start + chrono::Duration::seconds(idx as i64);
+            db.store_operation(&op).unwrap();
+            times.push(op.timestamp);
+        }
+
+        assert_eq!(mode_at(&db, "b.sh", times[1]).unwrap(), Some(0o755));
+        assert_eq!(mode_at(&db, "b.sh", times[2]).unwrap(), Some(0o700));
+        assert_eq!(mode_at(&db, "b.sh", times[3]).unwrap(), None);
+        assert_eq!(mode_at(&db, "b.sh", Utc::now()).unwrap(), None);
+    }
+
+    #[test]
+    fn binary_content_is_read_back_from_its_blob() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let bytes = [0x89, b'P', b'N', b'G', 0x00, 0xff];
+        let hash = crate::storage::hash::digest(&bytes).unwrap();
+        db.store_blob(&hash, &bytes).unwrap();
+        let binary = Operation::new(
+            "logo.png".to_string(),
+            OperationType::BinaryModify {
+                old_hash: None,
+                new_hash: hash,
+                size: bytes.len() as u64,
+            },
+            "actor".into(),
+        );
+        db.store_operation(&binary).unwrap();
+
+        let content = binary_at(&db, "logo.png", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some(&bytes[..]));
+
+        let text = Operation::new(
+            "logo.png".to_string(),
+            OperationType::FileCreate {
+                content: "now text".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&text).unwrap();
+        assert!(binary_at(&db, "logo.png", Utc::now()).unwrap().is_none());
+        assert!(
+            binary_at(&db, "logo.png", binary.timestamp)
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::crdt::{Operation, OperationType, Position};
+
+/// Flat, stable JSON shape of an operation for `--json` output. Unlike the
+/// wire format, the variant is a plain `op_type` field and every detail is a
+/// typed top-level field, so tools don't break when the human output does.
+#[derive(Debug, Serialize)]
+pub struct OperationRecord<'a> {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub actor_id: &'a str,
+    pub file_path: &'a str,
+    pub op_type: &'static str,
+    pub parent_ops: &'a [Uuid],
+    pub lamport: Option<u64>,
+    /// Present for `Insert`, `Delete` and `Replace`; null otherwise.
+    pub position: Option<&'a Position>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_target: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_target: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_hash: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_hash: Option<&'a str>,
+}
+
+impl<'a> From<&'a Operation> for OperationRecord<'a> {
+    fn from(op: &'a Operation) -> Self {
+        let mut record = OperationRecord {
+            id: op.id,
+            timestamp: op.timestamp,
+            actor_id: &op.actor_id,
+            file_path: &op.file_path,
+            op_type: op.op_type.name(),
+            parent_ops: &op.parent_ops,
+            lamport: op.lamport(),
+            position: op.op_type.position(),
+            length: None,
+            content: None,
+            old_content: None,
+            new_content: None,
+            old_path: None,
+            new_path: None,
+            old_mode: None,
+            new_mode: None,
+            old_target: None,
+            new_target: None,
+            size: None,
+            old_hash: None,
+            new_hash: None,
+        };
+        match &op.op_type {
+            OperationType::Insert {
+                content, length, ..
+            } => {
+                record.content = Some(content);
+                record.length = Some(*length);
+            }
+            OperationType::Delete { length, .. } => record.length = Some(*length),
+            OperationType::Replace {
+                old_content,
+                new_content,
+                ..
+            } => {
+                record.old_content = Some(old_content);
+                record.new_content = Some(new_content);
+            }
+            OperationType::FileCreate { content } => record.content = Some(content),
+            OperationType::FileDelete | OperationType::DirCreate | OperationType::DirDelete => {}
+            OperationType::FileRename { old_path, new_path }
+            | OperationType::DirRename { old_path, new_path } => {
+                record.old_path = Some(old_path);
+                record.new_path = Some(new_path);
+            }
+            OperationType::ChmodChange { old_mode, new_mode } => {
+                record.old_mode = Some(*old_mode);
+                record.new_mode = Some(*new_mode);
+            }
+            OperationType::SymlinkCreate { target } => record.new_target = Some(target),
+            OperationType::SymlinkChange {
+                old_target,
+                new_target,
+            } => {
+                record.old_target = Some(old_target);
+                record.new_target = Some(new_target);
+            }
+            OperationType::BinaryModify {
+                old_hash,
+                new_hash,
+                size,
+            } => {
+                record.size = Some(*size);
+                record.old_hash = old_hash.as_deref();
+                record.new_hash = Some(new_hash);
+            }
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_flatten_the_operation_type() {
+        let op = Operation::new(
+            "a.txt".into(),
+            OperationType::Insert {
+                position: Position::new(2, 3, 7, "actor".into(), 4),
+                content: "hi".into(),
+                length: 2,
+            },
+            "actor".into(),
+        );
+        let json = serde_json::to_value(OperationRecord::from(&op)).unwrap();
+        assert_eq!(json["op_type"], "Insert");
+        assert_eq!(json["position"]["line"], 2);
+        assert_eq!(json["position"]["offset"], 7);
+        assert_eq!(json["length"], 2);
+        assert_eq!(json["content"], "hi");
+        assert!(json.get("old_path").is_none());
+
+        let delete = Operation::new("a.txt".into(), OperationType::FileDelete, "actor".into());
+        let json = serde_json::to_value(OperationRecord::from(&delete)).unwrap();
+        assert_eq!(json["op_type"], "FileDelete");
+        assert!(json["position"].is_null());
+    }
+}
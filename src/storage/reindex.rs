@@ -0,0 +1,175 @@
+use anyhow::Result;
+use rusqlite::params;
+
+use super::db::{Checkpoint, Database};
+use super::reconstruct;
+use crate::crdt::OperationType;
+
+#[derive(Debug, Clone, Default)]
+pub struct ReindexReport {
+    pub files: usize,
+    pub checkpoints_written: usize,
+    pub checkpoints_removed: usize,
+    pub operations_indexed: usize,
+    /// Files with a live head that the next local operation will chain to.
+    pub heads: usize,
+}
+
+/// Rebuild everything derived from the operation log: per-file checkpoints,
+/// the content search index and file heads. Run after operations arrive out
+/// of band (e.g. `forge import`), since operations older than an existing
+/// checkpoint would otherwise be missing from the snapshot replay starts at.
+pub fn rebuild(db: &Database) -> Result<ReindexReport> {
+    let mut report = ReindexReport {
+        operations_indexed: db.rebuild_search_index()?,
+        ..Default::default()
+    };
+
+    for summary in db.file_summaries()? {
+        report.files += 1;
+        report.checkpoints_removed += drop_derived_checkpoints(db, &summary.file_path)?;
+        if !summary.deleted && checkpoint_latest(db, &summary.file_path)? {
+            report.checkpoints_written += 1;
+        }
+    }
+
+    // Heads are derived from the log itself, so an `OperationLog` opened
+    // after this point picks up the imported operations as parents.
+    report.heads = db.file_heads()?.len();
+    Ok(report)
+}
+
+/// Remove the checkpoints for `file_path` that can be rebuilt from its
+/// operations. A file whose creation is no longer in the log was compacted by
+/// `forge gc --aggressive`; its oldest checkpoint is the only record of that
+/// history and is kept.
+fn drop_derived_checkpoints(db: &Database, file_path: &str) -> Result<usize> {
+    let conn = db.conn.lock();
+    let oldest: Option<(String, String)> = conn
+        .query_row(
+            "SELECT op_id, timestamp FROM checkpoints WHERE file_path = ?1
+             ORDER BY timestamp ASC LIMIT 1",
+            params![file_path],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok();
+    let Some((base_op, base_timestamp)) = oldest else {
+        return Ok(0);
+    };
+
+    let has_origin: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM operations
+         WHERE file_path = ?1 AND timestamp <= ?2
+           AND rtrim(op_type) IN ('FileCreate', 'FileRename'))",
+        params![file_path, base_timestamp],
+        |row| row.get(0),
+    )?;
+
+    let removed = if has_origin {
+        conn.execute(
+            "DELETE FROM checkpoints WHERE file_path = ?1",
+            params![file_path],
+        )?
+    } else {
+        conn.execute(
+            "DELETE FROM checkpoints WHERE file_path = ?1 AND op_id != ?2",
+            params![file_path, base_op],
+        )?
+    };
+    Ok(removed)
+}
+
+/// Snapshot `file_path` at its latest operation.
+fn checkpoint_latest(db: &Database, file_path: &str) -> Result<bool> {
+    let Some(last) = db.operations_causal_order(file_path)?.pop() else {
+        return Ok(false);
+    };
+    if matches!(last.op_type, OperationType::FileDelete) {
+        return Ok(false);
+    }
+    let Some(content) = reconstruct::reconstruct_through(db, &last)? else {
+        return Ok(false);
+    };
+    db.store_checkpoint(&Checkpoint::new(
+        file_path.to_string(),
+        last.id,
+        last.timestamp,
+        content,
+    )?)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Operation, Position};
+    use crate::storage::OperationLog;
+    use crate::sync::GLOBAL_CLOCK;
+    use chrono::Utc;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn rebuilds_checkpoints_index_and_heads_after_import() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let mut create = Operation::new(
+            "a.txt".to_string(),
+            OperationType::FileCreate {
+                content: "hello".into(),
+            },
+            "actor".into(),
+        );
+        create.timestamp -= chrono::Duration::seconds(1);
+        db.store_operation(&create).unwrap();
+
+        // An imported edit that predates the checkpoint taken afterwards
+        let mut imported = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, 6, 5, "peer".into(), GLOBAL_CLOCK.tick()),
+                content: " world".into(),
+                length: 6,
+            },
+            "peer".into(),
+        )
+        .with_parents(vec![create.id]);
+        let checkpoint_at = Utc::now();
+        db.store_checkpoint(
+            &Checkpoint::new("a.txt".into(), create.id, checkpoint_at, "hello".into()).unwrap(),
+        )
+        .unwrap();
+        imported.timestamp = checkpoint_at - chrono::Duration::milliseconds(1);
+        db.store_operation(&imported).unwrap();
+        db.conn
+            .lock()
+            .execute("DELETE FROM operations_fts", [])
+            .unwrap();
+
+        let report = rebuild(&db).unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(report.checkpoints_removed, 1);
+        assert_eq!(report.checkpoints_written, 1);
+        assert_eq!(report.operations_indexed, 2);
+        assert_eq!(report.heads, 1);
+
+        let content = reconstruct::reconstruct_at(&db, "a.txt", Utc::now()).unwrap();
+        assert_eq!(content.as_deref(), Some("hello world"));
+        assert_eq!(db.search_content("world", 10).unwrap().len(), 1);
+
+        let oplog = OperationLog::new(Arc::new(db));
+        let next = Operation::new(
+            "a.txt".to_string(),
+            OperationType::Insert {
+                position: Position::new(1, 12, 11, "actor".into(), GLOBAL_CLOCK.tick()),
+                content: "!".into(),
+                length: 1,
+            },
+            "actor".into(),
+        );
+        let stored = oplog.append_local(next).unwrap().unwrap();
+        assert_eq!(stored.parent_ops, vec![imported.id]);
+    }
+}
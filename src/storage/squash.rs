@@ -0,0 +1,347 @@
+use anyhow::Result;
+use chrono::Duration;
+use ropey::Rope;
+use rusqlite::params;
+use std::collections::HashMap;
+use std::ops::Range;
+use uuid::Uuid;
+
+use super::db::{self, Database};
+use super::reconstruct;
+use crate::crdt::document::apply_to_rope;
+use crate::crdt::{Operation, OperationType, Position};
+
+/// Merge each run of consecutive content edits to `file_path` by one actor,
+/// no more than `window` apart, into a single `Replace` holding the run's net
+/// change. Only `actor`'s edits are squashed, or everyone's if it's `None`;
+/// a run never mixes actors. The replacement takes the last edit's place in
+/// replay order, so reconstruction is unchanged. Squashing rewrites history,
+/// so it's meant for operations that haven't been shared yet. Returns how
+/// many operations were removed from the log.
+pub fn squash(
+    db: &Database,
+    file_path: &str,
+    window: Duration,
+    actor: Option<&str>,
+) -> Result<usize> {
+    let operations = db.operations_causal_order(file_path)?;
+
+    let mut removed = 0;
+    for run in runs(&operations, window, actor) {
+        let before = replay(db, &operations[..run.start])?;
+        removed += squash_run(db, file_path, &before, &operations[run])?;
+    }
+    Ok(removed)
+}
+
+/// Index ranges of the runs of two or more edits that can be squashed
+/// together.
+fn runs(operations: &[Operation], window: Duration, actor: Option<&str>) -> Vec<Range<usize>> {
+    let squashable = |op: &Operation| {
+        op.op_type.position().is_some() && actor.is_none_or(|actor| op.actor_id == actor)
+    };
+
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for idx in 1..=operations.len() {
+        let continues = operations.get(idx).is_some_and(|op| {
+            let prev = &operations[idx - 1];
+            squashable(op)
+                && squashable(prev)
+                && op.actor_id == prev.actor_id
+                && op.timestamp - prev.timestamp <= window
+        });
+        if !continues {
+            if idx - start > 1 {
+                runs.push(start..idx);
+            }
+            start = idx;
+        }
+    }
+    runs
+}
+
+/// Content of the file after `operations`, a prefix of its history in replay
+/// order. A cut by wall clock could miss or include the wrong operations when
+/// peers' clocks disagree, so the prefix itself is replayed.
+fn replay(db: &Database, operations: &[Operation]) -> Result<String> {
+    let mut rope = Rope::new();
+    for op in operations {
+        match &op.op_type {
+            // As in reconstruction, a rename starts the file out as whatever
+            // the source path held at that moment
+            OperationType::FileRename { old_path, .. } => {
+                let content = reconstruct::reconstruct_at(db, old_path, op.timestamp)?;
+                rope = Rope::from_str(&content.unwrap_or_default());
+            }
+            _ => apply_to_rope(&mut rope, op),
+        }
+    }
+    Ok(rope.to_string())
+}
+
+fn squash_run(db: &Database, file_path: &str, before: &str, run: &[Operation]) -> Result<usize> {
+    let (first, last) = (&run[0], &run[run.len() - 1]);
+    let mut rope = Rope::from_str(before);
+    for op in run {
+        apply_to_rope(&mut rope, op);
+    }
+    let after = rope.to_string();
+
+    let run_ids: Vec<Uuid> = run.iter().map(|op| op.id).collect();
+    let parents: Vec<Uuid> = first
+        .parent_ops
+        .iter()
+        .filter(|parent| !run_ids.contains(parent))
+        .copied()
+        .collect();
+    let replacement = net_change(before, &after).map(|(offset, old_content, new_content)| {
+        let rope = Rope::from_str(before);
+        let line = rope.char_to_line(offset);
+        let column = offset - rope.line_to_char(line);
+        let position = Position::new(
+            line + 1,
+            column + 1,
+            offset,
+            last.actor_id.clone(),
+            last.lamport().unwrap_or_default(),
+        );
+        Operation {
+            id: Uuid::new_v4(),
+            timestamp: last.timestamp,
+            actor_id: last.actor_id.clone(),
+            file_path: file_path.to_string(),
+            op_type: OperationType::Replace {
+                position,
+                old_content,
+                new_content,
+            },
+            parent_ops: parents.clone(),
+            lamport: last.lamport,
+        }
+    });
+    // Operations chained to the run now follow its replacement, or whatever
+    // came before the run if it had no net effect
+    let new_parents = match &replacement {
+        Some(op) => vec![op.id],
+        None => parents,
+    };
+
+    let mut conn = db.conn.lock();
+    let tx = conn.transaction()?;
+    for id in &run_ids {
+        let id = id.to_string();
+        tx.execute("DELETE FROM operations_fts WHERE op_id = ?1", params![id])?;
+        tx.execute("DELETE FROM operations WHERE id = ?1", params![id])?;
+        // Checkpoints taken partway through the run no longer match any state
+        tx.execute("DELETE FROM checkpoints WHERE op_id = ?1", params![id])?;
+    }
+
+    let children: Vec<(String, String)> = {
+        let mut stmt =
+            tx.prepare("SELECT id, parent_ops FROM operations WHERE parent_ops LIKE ?1")?;
+        let mut children = HashMap::new();
+        for id in &run_ids {
+            let rows = stmt.query_map(params![format!("%{id}%")], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (child, parent_ops) = row?;
+                children.insert(child, parent_ops);
+            }
+        }
+        children.into_iter().collect()
+    };
+    for (child, parent_ops) in children {
+        let mut rewritten = Vec::new();
+        for parent in serde_json::from_str::<Vec<Uuid>>(&parent_ops)? {
+            let parents = if run_ids.contains(&parent) {
+                new_parents.clone()
+            } else {
+                vec![parent]
+            };
+            for parent in parents {
+                if !rewritten.contains(&parent) {
+                    rewritten.push(parent);
+                }
+            }
+        }
+        tx.execute(
+            "UPDATE operations SET parent_ops = ?2 WHERE id = ?1",
+            params![child, serde_json::to_string(&rewritten)?],
+        )?;
+    }
+
+    if let Some(op) = &replacement {
+        db::insert_operation(&tx, op)?;
+    }
+    tx.commit()?;
+
+    Ok(run.len() - usize::from(replacement.is_some()))
+}
+
+/// The smallest single replacement turning `before` into `after`, as a char
+/// offset, the text removed there and the text inserted. `None` if they're
+/// equal.
+fn net_change(before: &str, after: &str) -> Option<(usize, String, String)> {
+    if before == after {
+        return None;
+    }
+    let old: Vec<char> = before.chars().collect();
+    let new: Vec<char> = after.chars().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    Some((
+        prefix,
+        old[prefix..old.len() - suffix].iter().collect(),
+        new[prefix..new.len() - suffix].iter().collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::QueryFilter;
+    use crate::sync::GLOBAL_CLOCK;
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    fn typed(offset: usize, content: &str, actor: &str) -> OperationType {
+        OperationType::Insert {
+            position: Position::new(1, 1, offset, actor.into(), GLOBAL_CLOCK.tick()),
+            content: content.into(),
+            length: content.chars().count(),
+        }
+    }
+
+    #[test]
+    fn squashes_an_actors_keystrokes_into_one_replace() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let start = Utc::now() - Duration::seconds(60);
+        let steps = [
+            (
+                0,
+                OperationType::FileCreate {
+                    content: "fn main() {}\n".into(),
+                },
+                "me",
+            ),
+            (10, typed(11, "l", "me"), "me"),
+            (10, typed(12, "e", "me"), "me"),
+            (10, typed(13, "t", "me"), "me"),
+            (
+                10,
+                OperationType::Delete {
+                    position: Position::new(1, 1, 13, "me".into(), GLOBAL_CLOCK.tick()),
+                    length: 1,
+                },
+                "me",
+            ),
+            (10, typed(12, "t", "you"), "you"),
+            (10, typed(0, "// a\n", "me"), "me"),
+            // Too long after the previous edit to join it
+            (5_000, typed(0, "x", "me"), "me"),
+        ];
+        let mut at = start;
+        let mut ops: Vec<Operation> = Vec::new();
+        for (gap, op_type, actor) in steps {
+            at += Duration::milliseconds(gap);
+            let mut op = Operation::new("main.rs".into(), op_type, actor.into());
+            op.timestamp = at;
+            if let Some(prev) = ops.last() {
+                op = op.with_parents(vec![prev.id]);
+            }
+            db.store_operation(&op).unwrap();
+            ops.push(op);
+        }
+        let expected = reconstruct::reconstruct_at(&db, "main.rs", Utc::now()).unwrap();
+
+        let removed = squash(&db, "main.rs", Duration::seconds(1), Some("me")).unwrap();
+
+        assert_eq!(removed, 3);
+        assert_eq!(
+            reconstruct::reconstruct_at(&db, "main.rs", Utc::now()).unwrap(),
+            expected
+        );
+        let remaining = db.query_operations(&QueryFilter::default()).unwrap();
+        assert_eq!(remaining.len(), ops.len() - 3);
+        let replace = remaining
+            .iter()
+            .find(|op| matches!(op.op_type, OperationType::Replace { .. }))
+            .unwrap();
+        match &replace.op_type {
+            OperationType::Replace {
+                old_content,
+                new_content,
+                position,
+            } => {
+                assert_eq!((old_content.as_str(), new_content.as_str()), ("", "le"));
+                assert_eq!(position.offset, 11);
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(replace.parent_ops, vec![ops[0].id]);
+        let theirs = remaining.iter().find(|op| op.actor_id == "you").unwrap();
+        assert_eq!(theirs.parent_ops, vec![replace.id]);
+    }
+
+    #[test]
+    fn squash_starts_from_the_replay_order_prefix_not_the_wall_clock() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        // Their edit is ordered before the run, but their clock ran ahead
+        let start = Utc::now() - Duration::seconds(60);
+        let steps = [
+            (
+                0,
+                OperationType::FileCreate {
+                    content: "abc".into(),
+                },
+                "me",
+            ),
+            (10_000, typed(0, "X", "you"), "you"),
+            (1_000, typed(4, "d", "me"), "me"),
+            (1_010, typed(5, "e", "me"), "me"),
+        ];
+        let mut ops: Vec<Operation> = Vec::new();
+        for (lamport, (gap, op_type, actor)) in (1..).zip(steps) {
+            let mut op = Operation::new("a.txt".into(), op_type, actor.into());
+            op.timestamp = start + Duration::milliseconds(gap);
+            op.lamport = lamport;
+            if let Some(prev) = ops.last() {
+                op = op.with_parents(vec![prev.id]);
+            }
+            db.store_operation(&op).unwrap();
+            ops.push(op);
+        }
+        let expected = reconstruct::reconstruct_at(&db, "a.txt", Utc::now()).unwrap();
+        assert_eq!(expected.as_deref(), Some("Xabcde"));
+
+        let removed = squash(&db, "a.txt", Duration::seconds(1), Some("me")).unwrap();
+
+        assert_eq!(removed, 1);
+        assert_eq!(
+            reconstruct::reconstruct_at(&db, "a.txt", Utc::now()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn net_change_trims_the_common_prefix_and_suffix() {
+        assert_eq!(
+            net_change("hello world", "hello brave world"),
+            Some((6, String::new(), "brave ".into()))
+        );
+        assert_eq!(net_change("same", "same"), None);
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::db::{Checkpoint, Database, QueryFilter};
+use crate::crdt::{Anchor, Operation, OperationType};
+
+/// Persistence behind an [`OperationLog`](super::OperationLog). The SQLite
+/// [`Database`] is the default; other backends implement this to keep the
+/// log somewhere else.
+#[allow(dead_code)]
+pub trait OperationStore: Send + Sync + 'static {
+    /// Persist `op`. Returns `false` if an operation with the same id was
+    /// already stored.
+    fn store_operation(&self, op: &Operation) -> Result<bool>;
+
+    /// Persist a batch of operations, in one write where the store can.
+    /// Returns whether each one was new.
+    fn append_many(&self, ops: &[Operation]) -> Result<Vec<bool>> {
+        ops.iter().map(|op| self.store_operation(op)).collect()
+    }
+
+    fn has_operation(&self, id: &Uuid) -> Result<bool>;
+
+    /// The operation with `id`, if it's stored.
+    fn operation(&self, id: &Uuid) -> Result<Option<Operation>>;
+
+    /// The most recent `limit` operations, newest first, optionally for a
+    /// single file.
+    fn get_operations(&self, file: Option<&Path>, limit: usize) -> Result<Vec<Operation>>;
+
+    /// Operations matching `filter`, oldest first.
+    fn query_operations(&self, filter: &QueryFilter) -> Result<Vec<Operation>>;
+
+    /// Operations matching `filter` in replay order (`Operation::order_key`)
+    /// rather than by local timestamp.
+    fn query_operations_causal(&self, filter: &QueryFilter) -> Result<Vec<Operation>> {
+        let mut operations = self.query_operations(filter)?;
+        operations.sort_by(|a, b| a.order_key().cmp(&b.order_key()));
+        Ok(operations)
+    }
+
+    /// Operations matching `filter` that replay after `after`, in replay
+    /// order. Replay resumes from a checkpoint this way.
+    fn query_operations_causal_after(
+        &self,
+        filter: &QueryFilter,
+        after: &Operation,
+    ) -> Result<Vec<Operation>> {
+        let mut operations = self.query_operations_causal(filter)?;
+        operations.retain(|op| op.order_key() > after.order_key());
+        Ok(operations)
+    }
+
+    /// The newest operation of each file that hasn't been deleted.
+    fn file_heads(&self) -> Result<HashMap<String, Uuid>> {
+        let mut heads = HashMap::new();
+        for op in self.query_operations(&QueryFilter::default())? {
+            if matches!(op.op_type, OperationType::FileDelete) {
+                heads.remove(&op.file_path);
+            } else {
+                heads.insert(op.file_path, op.id);
+            }
+        }
+        Ok(heads)
+    }
+
+    fn store_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()>;
+
+    /// The checkpoint for `file_path` taken at or before `at` whose operation
+    /// comes last in replay order.
+    fn latest_checkpoint(&self, file_path: &str, at: DateTime<Utc>) -> Result<Option<Checkpoint>>;
+
+    fn store_anchor(&self, anchor: &Anchor) -> Result<()>;
+}
+
+/// Rows read per page when SQLite sorts operations into replay order.
+pub(super) const REPLAY_BATCH_SIZE: usize = 1_000;
+
+impl OperationStore for Database {
+    fn store_operation(&self, op: &Operation) -> Result<bool> {
+        Database::store_operation(self, op)
+    }
+
+    fn append_many(&self, ops: &[Operation]) -> Result<Vec<bool>> {
+        Database::append_many(self, ops)
+    }
+
+    fn has_operation(&self, id: &Uuid) -> Result<bool> {
+        Database::has_operation(self, id)
+    }
+
+    fn operation(&self, id: &Uuid) -> Result<Option<Operation>> {
+        Database::operation(self, id)
+    }
+
+    fn get_operations(&self, file: Option<&Path>, limit: usize) -> Result<Vec<Operation>> {
+        Database::get_operations(self, file, limit)
+    }
+
+    fn query_operations(&self, filter: &QueryFilter) -> Result<Vec<Operation>> {
+        Database::query_operations(self, filter)
+    }
+
+    fn query_operations_causal_after(
+        &self,
+        filter: &QueryFilter,
+        after: &Operation,
+    ) -> Result<Vec<Operation>> {
+        self.iter_operations_causal_after(filter, after, REPLAY_BATCH_SIZE)
+            .collect()
+    }
+
+    fn file_heads(&self) -> Result<HashMap<String, Uuid>> {
+        Database::file_heads(self)
+    }
+
+    fn store_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        Database::store_checkpoint(self, checkpoint)
+    }
+
+    fn latest_checkpoint(&self, file_path: &str, at: DateTime<Utc>) -> Result<Option<Checkpoint>> {
+        Database::latest_checkpoint(self, file_path, at)
+    }
+
+    fn store_anchor(&self, anchor: &Anchor) -> Result<()> {
+        Database::store_anchor(self, anchor)
+    }
+}
+
+// Lets callers holding a shared store pass it wherever a store is expected.
+impl<S: OperationStore + ?Sized> OperationStore for Arc<S> {
+    fn store_operation(&self, op: &Operation) -> Result<bool> {
+        (**self).store_operation(op)
+    }
+
+    fn append_many(&self, ops: &[Operation]) -> Result<Vec<bool>> {
+        (**self).append_many(ops)
+    }
+
+    fn has_operation(&self, id: &Uuid) -> Result<bool> {
+        (**self).has_operation(id)
+    }
+
+    fn operation(&self, id: &Uuid) -> Result<Option<Operation>> {
+        (**self).operation(id)
+    }
+
+    fn get_operations(&self, file: Option<&Path>, limit: usize) -> Result<Vec<Operation>> {
+        (**self).get_operations(file, limit)
+    }
+
+    fn query_operations(&self, filter: &QueryFilter) -> Result<Vec<Operation>> {
+        (**self).query_operations(filter)
+    }
+
+    fn query_operations_causal(&self, filter: &QueryFilter) -> Result<Vec<Operation>> {
+        (**self).query_operations_causal(filter)
+    }
+
+    fn query_operations_causal_after(
+        &self,
+        filter: &QueryFilter,
+        after: &Operation,
+    ) -> Result<Vec<Operation>> {
+        (**self).query_operations_causal_after(filter, after)
+    }
+
+    fn file_heads(&self) -> Result<HashMap<String, Uuid>> {
+        (**self).file_heads()
+    }
+
+    fn store_checkpoint(&self, checkpoint: &Checkpoint) -> Result<()> {
+        (**self).store_checkpoint(checkpoint)
+    }
+
+    fn latest_checkpoint(&self, file_path: &str, at: DateTime<Utc>) -> Result<Option<Checkpoint>> {
+        (**self).latest_checkpoint(file_path, at)
+    }
+
+    fn store_anchor(&self, anchor: &Anchor) -> Result<()> {
+        (**self).store_anchor(anchor)
+    }
+}
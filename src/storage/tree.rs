@@ -0,0 +1,447 @@
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::db::Database;
+use super::hash;
+use super::reconstruct;
+use crate::crdt::OperationType;
+
+/// Content-addressed snapshot of the tracked tree: each file's path relative
+/// to the repository root mapped to the hash of its content, sorted by path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeManifest {
+    pub entries: Vec<(String, String)>,
+}
+
+impl TreeManifest {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Hash of the serialized manifest. Identical trees hash identically.
+    pub fn hash(&self) -> Result<String> {
+        hash::digest(self.to_json()?.as_bytes())
+    }
+}
+
+/// Reconstruct every tracked file as of `at` and hash its content. Symlinks
+/// hash their target instead, and binary files keep the hash recorded for
+/// them. Recorded directories are listed with a
+/// trailing `/`, so empty ones are part of the tree too.
+pub fn build(db: &Database, repo_root: &Path, at: DateTime<Utc>) -> Result<TreeManifest> {
+    let mut entries = Vec::new();
+    for dir in dirs_at(db, at)? {
+        entries.push((
+            format!("{}/", relative_path(repo_root, &dir)),
+            hash::digest(b"dir")?,
+        ));
+    }
+    for (file_path, content) in files_at(db, at)? {
+        // As in `export`, a binary file's content is its recorded hash rather
+        // than the text left over from before it turned binary
+        let hashed = match reconstruct::symlink_target_at(db, &file_path, at)? {
+            Some(target) => hash::digest(format!("symlink:{target}").as_bytes())?,
+            None => match reconstruct::binary_hash_at(db, &file_path, at)? {
+                Some(hash) => hash,
+                None => hash::digest(content.as_bytes())?,
+            },
+        };
+        entries.push((relative_path(repo_root, &file_path), hashed));
+    }
+    entries.sort();
+    Ok(TreeManifest { entries })
+}
+
+/// Every file that existed at `at` with its content then, keyed by the path
+/// recorded in the log. Files deleted or renamed away by then are skipped.
+pub fn files_at(db: &Database, at: DateTime<Utc>) -> Result<Vec<(String, String)>> {
+    let mut files = Vec::new();
+    for summary in db.file_summaries_at(at)? {
+        if summary.deleted {
+            continue;
+        }
+        if let Some(content) = reconstruct::reconstruct_at(db, &summary.file_path, at)? {
+            files.push((summary.file_path, content));
+        }
+    }
+    Ok(files)
+}
+
+/// Every recorded directory that existed at `at`, sorted by path.
+/// Directories created before directory operations were recorded only show
+/// up through the files inside them.
+pub fn dirs_at(db: &Database, at: DateTime<Utc>) -> Result<Vec<String>> {
+    let under = |dir: &str, path: &str| path != dir && Path::new(path).starts_with(dir);
+
+    let mut dirs = BTreeSet::new();
+    for op in db.operations_of_types(&["DirCreate", "DirDelete", "DirRename"], None, at)? {
+        match op.op_type {
+            OperationType::DirCreate => {
+                dirs.insert(op.file_path);
+            }
+            OperationType::DirDelete => {
+                dirs.retain(|dir: &String| *dir != op.file_path && !under(&op.file_path, dir));
+            }
+            OperationType::DirRename { old_path, new_path } => {
+                let moved: Vec<String> = dirs
+                    .iter()
+                    .filter(|dir| under(&old_path, dir))
+                    .cloned()
+                    .collect();
+                dirs.remove(&old_path);
+                for dir in moved {
+                    dirs.remove(&dir);
+                    if let Ok(rest) = Path::new(&dir).strip_prefix(&old_path) {
+                        dirs.insert(Path::new(&new_path).join(rest).display().to_string());
+                    }
+                }
+                dirs.insert(new_path);
+            }
+            _ => {}
+        }
+    }
+    Ok(dirs.into_iter().collect())
+}
+
+/// `file_path` relative to `repo_root` with `/` separators, so manifests built
+/// on different machines agree.
+pub fn relative_path(repo_root: &Path, file_path: &str) -> String {
+    let path = Path::new(file_path);
+    let relative = path.strip_prefix(repo_root).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// What [`export`] wrote.
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub files: usize,
+    pub dirs: usize,
+    /// Files whose path was already taken in the export, by relative path,
+    /// with where they were written instead.
+    pub collisions: Vec<(String, PathBuf)>,
+    /// Binary files left out because their content wasn't stored.
+    pub missing_blobs: Vec<String>,
+}
+
+/// Write the tracked tree as of `at` under `out`, which must be empty or not
+/// exist yet: the read side of [`build`]. Files keep their path relative to
+/// `repo_root`, their recorded mode and, for symlinks, their target. A file
+/// whose path is already taken, such as one that later became a directory,
+/// is written beside it with a `~N` suffix instead of replacing anything.
+pub fn export(
+    db: &Database,
+    repo_root: &Path,
+    at: DateTime<Utc>,
+    out: &Path,
+) -> Result<ExportSummary> {
+    if out.exists() && fs::read_dir(out)?.next().is_some() {
+        bail!("{} is not empty", out.display());
+    }
+    fs::create_dir_all(out)?;
+
+    let mut files: Vec<(PathBuf, String, String)> = files_at(db, at)?
+        .into_iter()
+        .map(|(file_path, content)| (export_path(repo_root, &file_path), file_path, content))
+        .collect();
+    files.sort();
+
+    // Directories go first, so no file can take a path one of them needs
+    let mut dirs: BTreeSet<PathBuf> = dirs_at(db, at)?
+        .iter()
+        .map(|dir| export_path(repo_root, dir))
+        .collect();
+    dirs.extend(
+        files
+            .iter()
+            .filter_map(|(relative, ..)| relative.parent().map(Path::to_path_buf)),
+    );
+    dirs.remove(Path::new(""));
+    for dir in &dirs {
+        fs::create_dir_all(out.join(dir))?;
+    }
+
+    let mut summary = ExportSummary {
+        dirs: dirs.len(),
+        ..Default::default()
+    };
+    for (relative, file_path, content) in files {
+        let mut target = out.join(&relative);
+        if target.symlink_metadata().is_ok() {
+            target = free_path(&target);
+            summary
+                .collisions
+                .push((relative_path(repo_root, &file_path), target.clone()));
+        }
+
+        if let Some(link) = reconstruct::symlink_target_at(db, &file_path, at)? {
+            write_symlink(&link, &target)?;
+        } else {
+            match reconstruct::binary_hash_at(db, &file_path, at)? {
+                Some(hash) => match db.blob(&hash)? {
+                    Some(bytes) => fs::write(&target, bytes)?,
+                    None => {
+                        summary
+                            .missing_blobs
+                            .push(relative_path(repo_root, &file_path));
+                        continue;
+                    }
+                },
+                None => fs::write(&target, content)?,
+            }
+            if let Some(mode) = reconstruct::mode_at(db, &file_path, at)? {
+                set_mode(&target, mode)?;
+            }
+        }
+        summary.files += 1;
+    }
+    Ok(summary)
+}
+
+/// Where `file_path` goes in an export: relative to `repo_root`, with only
+/// plain components so nothing lands outside the export directory.
+fn export_path(repo_root: &Path, file_path: &str) -> PathBuf {
+    Path::new(&relative_path(repo_root, file_path))
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+/// `path` with the first `~N` suffix nothing exists at.
+fn free_path(path: &Path) -> PathBuf {
+    (1..)
+        .map(|n| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(format!("~{n}"));
+            PathBuf::from(name)
+        })
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("some suffix is free")
+}
+
+#[cfg(unix)]
+fn write_symlink(target: &str, link: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(target, link)?;
+    Ok(())
+}
+
+// Without symlinks, the target is written where the link would be
+#[cfg(not(unix))]
+fn write_symlink(target: &str, link: &Path) -> Result<()> {
+    fs::write(link, target)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::Operation;
+    use tempfile::TempDir;
+
+    fn create(path: &str, content: &str) -> Operation {
+        Operation::new(
+            path.to_string(),
+            OperationType::FileCreate {
+                content: content.into(),
+            },
+            "actor".into(),
+        )
+    }
+
+    #[test]
+    fn identical_trees_have_identical_hashes() {
+        let root = Path::new("/repo");
+        let build_for = |ops: &[Operation]| {
+            let temp_dir = TempDir::new().unwrap();
+            let db = Database::new(temp_dir.path()).unwrap();
+            db.initialize().unwrap();
+            for op in ops {
+                db.store_operation(op).unwrap();
+            }
+            build(&db, root, Utc::now()).unwrap()
+        };
+
+        let first = build_for(&[create("/repo/b.txt", "b"), create("/repo/src/a.rs", "a")]);
+        let second = build_for(&[create("/repo/src/a.rs", "a"), create("/repo/b.txt", "b")]);
+
+        assert_eq!(first.entries[0].0, "b.txt");
+        assert_eq!(first.entries[1].0, "src/a.rs");
+        assert_eq!(first.hash().unwrap(), second.hash().unwrap());
+    }
+
+    #[test]
+    fn binary_files_hash_by_their_content() {
+        let root = Path::new("/repo");
+        let build_for = |bytes: &[u8]| {
+            let temp_dir = TempDir::new().unwrap();
+            let db = Database::new(temp_dir.path()).unwrap();
+            db.initialize().unwrap();
+            let binary = Operation::new(
+                "/repo/logo.png".to_string(),
+                OperationType::BinaryModify {
+                    old_hash: None,
+                    new_hash: hash::digest(bytes).unwrap(),
+                    size: bytes.len() as u64,
+                },
+                "actor".into(),
+            );
+            db.store_operation(&binary).unwrap();
+            build(&db, root, Utc::now()).unwrap()
+        };
+
+        let first = build_for(&[0x89, 0x00]);
+        let second = build_for(&[0x89, 0x01]);
+
+        assert_eq!(first.entries[0].1, hash::digest(&[0x89, 0x00]).unwrap());
+        assert_ne!(first.hash().unwrap(), second.hash().unwrap());
+    }
+
+    #[test]
+    fn renamed_files_appear_only_under_the_new_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let mut original = create("/repo/old.txt", "moved");
+        original.timestamp -= chrono::Duration::seconds(1);
+        let rename = Operation::new(
+            "/repo/new.txt".to_string(),
+            OperationType::FileRename {
+                old_path: "/repo/old.txt".into(),
+                new_path: "/repo/new.txt".into(),
+            },
+            "actor".into(),
+        );
+        db.store_operation(&original).unwrap();
+        db.store_operation(&rename).unwrap();
+
+        let manifest = build(&db, Path::new("/repo"), Utc::now()).unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(manifest.entries[0].0, "new.txt");
+    }
+
+    #[test]
+    fn directories_follow_their_create_rename_and_delete() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let start = Utc::now() - chrono::Duration::seconds(10);
+        let steps = [
+            ("/repo/a", OperationType::DirCreate),
+            ("/repo/a/b", OperationType::DirCreate),
+            ("/repo/gone", OperationType::DirCreate),
+            (
+                "/repo/c",
+                OperationType::DirRename {
+                    old_path: "/repo/a".into(),
+                    new_path: "/repo/c".into(),
+                },
+            ),
+            ("/repo/gone", OperationType::DirDelete),
+        ];
+        for (idx, (path, op_type)) in steps.into_iter().enumerate() {
+            let mut op = Operation::new(path.to_string(), op_type, "actor".into());
+            op.timestamp = start + chrono::Duration::seconds(idx as i64);
+            db.store_operation(&op).unwrap();
+        }
+
+        assert_eq!(dirs_at(&db, Utc::now()).unwrap(), ["/repo/c", "/repo/c/b"]);
+        assert_eq!(
+            dirs_at(&db, start + chrono::Duration::seconds(2)).unwrap(),
+            ["/repo/a", "/repo/a/b", "/repo/gone"]
+        );
+
+        let manifest = build(&db, Path::new("/repo"), Utc::now()).unwrap();
+        let paths: Vec<_> = manifest
+            .entries
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect();
+        assert_eq!(paths, ["c/", "c/b/"]);
+        assert!(files_at(&db, Utc::now()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn export_writes_each_file_without_overwriting_another() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+
+        let start = Utc::now() - chrono::Duration::seconds(10);
+        let steps = [
+            create("/repo/src/main.rs", "fn main() {}\n"),
+            create("/repo/notes", "a file first"),
+            create("/repo/notes/today.md", "then a directory"),
+            Operation::new(
+                "/repo/empty".into(),
+                OperationType::DirCreate,
+                "actor".into(),
+            ),
+            Operation::new(
+                "/repo/src/main.rs".into(),
+                OperationType::ChmodChange {
+                    old_mode: 0o644,
+                    new_mode: 0o755,
+                },
+                "actor".into(),
+            ),
+        ];
+        for (idx, mut op) in steps.into_iter().enumerate() {
+            op.timestamp = start + chrono::Duration::seconds(idx as i64);
+            db.store_operation(&op).unwrap();
+        }
+
+        let out = temp_dir.path().join("export");
+        let summary = export(&db, Path::new("/repo"), Utc::now(), &out).unwrap();
+        assert_eq!(summary.files, 3);
+        assert_eq!(
+            fs::read_to_string(out.join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("notes/today.md")).unwrap(),
+            "then a directory"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("notes~1")).unwrap(),
+            "a file first"
+        );
+        assert_eq!(
+            summary.collisions,
+            [("notes".to_string(), out.join("notes~1"))]
+        );
+        assert!(out.join("empty").is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(out.join("src/main.rs"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o7777, 0o755);
+        }
+
+        // An export never lands on top of existing files
+        assert!(export(&db, Path::new("/repo"), Utc::now(), &out).is_err());
+    }
+}
@@ -63,6 +63,13 @@ impl HybridLogicalClock {
     }
 }
 
+/// Hybrid timestamp for a wall-clock instant with a zero logical counter, so
+/// events without their own clock reading can still be ordered against ones
+/// that have it.
+pub fn stamp_from_millis(millis: u64) -> u64 {
+    encode(millis, 0)
+}
+
 fn current_millis() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -0,0 +1,86 @@
+// LAN peer discovery over mDNS: servers register a `_forge._tcp` service
+// whose TXT record carries their repo id, and watchers browsing for the
+// service connect to servers that share their repo id.
+use std::collections::HashSet;
+
+use anyhow::Result;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use tokio::sync::mpsc;
+
+/// mDNS service type forge servers register under.
+pub const SERVICE_TYPE: &str = "_forge._tcp.local.";
+
+/// TXT record key holding the advertised repo id.
+const REPO_ID_KEY: &str = "repo_id";
+
+/// Announce a forge server for `repo_id` listening on `port`. The returned
+/// daemon keeps answering queries until it's shut down.
+pub fn advertise(repo_id: String, port: u16) -> Result<ServiceDaemon> {
+    let daemon = ServiceDaemon::new()?;
+    let host = host_label();
+    let service = ServiceInfo::new(
+        SERVICE_TYPE,
+        &format!("{host}-{port}"),
+        &format!("{host}.local."),
+        "",
+        port,
+        [(REPO_ID_KEY, repo_id.as_str())].as_slice(),
+    )?
+    .enable_addr_auto();
+    daemon.register(service)?;
+    Ok(daemon)
+}
+
+/// Browse for forge servers and yield the WebSocket URL of every newly seen
+/// one whose repo id matches `repo_id`. Servers for other repositories are
+/// ignored so peers never link across projects.
+pub fn discover(repo_id: String) -> Result<mpsc::Receiver<String>> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let mut known = HashSet::new();
+        while let Ok(event) = events.recv_async().await {
+            let ServiceEvent::ServiceResolved(service) = event else {
+                continue;
+            };
+            if service.get_property_val_str(REPO_ID_KEY) != Some(repo_id.as_str()) {
+                continue;
+            }
+
+            // A loopback address is only reachable from the server's own
+            // machine, and any other one reaches it from there too
+            let Some(addr) = service
+                .get_addresses_v4()
+                .into_iter()
+                .find(|addr| !addr.is_loopback())
+            else {
+                continue;
+            };
+            if !known.insert(service.get_fullname().to_string()) {
+                continue;
+            }
+            let url = format!("ws://{}:{}/ws", addr, service.get_port());
+            if tx.send(url).await.is_err() {
+                break;
+            }
+        }
+        let _ = daemon.shutdown();
+    });
+
+    Ok(rx)
+}
+
+/// This machine's name, reduced to what's valid in a DNS label.
+fn host_label() -> String {
+    let host: String = whoami::fallible::hostname()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    match host.trim_matches('-') {
+        "" => "forge".to_string(),
+        host => host.to_string(),
+    }
+}
@@ -1,21 +1,182 @@
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
 
 use crate::crdt::Operation;
+use crate::storage::ActorIdentity;
+
+/// Codec named in a handshake's `compression` list: LZ4-compressed JSON.
+pub const COMPRESSION_LZ4: &str = "lz4";
+
+// Starts every compressed binary frame; a CBOR-encoded operation (the other
+// binary frame peers send) can never begin with a NUL byte
+const COMPRESSED_FRAME_PREFIX: &[u8] = b"\0lz4";
+
+/// Largest message either side of a sync connection accepts, compressed or
+/// not. Bigger frames close the connection before anything is parsed.
+pub const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Messages smaller than this are sent as plain JSON even when the peer
+/// accepts compression; they don't shrink enough to be worth it.
+const COMPRESS_MIN_BYTES: usize = 1024;
+
+/// A message ready to put in a WebSocket frame.
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
 
 /// Wire format for sync messages exchanged over WebSockets.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum SyncMessage {
-    Handshake { actor_id: String, repo_id: String },
+    Handshake {
+        actor_id: String,
+        repo_id: String,
+        /// Human-readable identity of the sender, when configured.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor_email: Option<String>,
+        /// Codecs the sender can decode; peers that predate compression
+        /// send none and only ever get plain JSON.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        compression: Vec<String>,
+    },
     Operation { operation: Operation },
+    /// Operations produced together, to be applied as a unit.
+    OperationBatch { operations: Vec<Operation> },
+    /// The receiver refused to store an operation.
+    Rejected { op_id: Uuid, reason: String },
+    /// The receiver has stored everything the sender sent up to `op_id`.
+    Ack { op_id: Uuid },
 }
 
 impl SyncMessage {
-    pub fn handshake(actor_id: String, repo_id: String) -> Self {
-        Self::Handshake { actor_id, repo_id }
+    pub fn handshake(identity: ActorIdentity, repo_id: String) -> Self {
+        Self::Handshake {
+            actor_id: identity.actor_id,
+            repo_id,
+            actor_name: identity.name,
+            actor_email: identity.email,
+            compression: vec![COMPRESSION_LZ4.to_string()],
+        }
+    }
+
+    /// Whether this handshake's sender decodes compressed frames.
+    pub fn accepts_compression(&self) -> bool {
+        matches!(self, Self::Handshake { compression, .. }
+            if compression.iter().any(|codec| codec == COMPRESSION_LZ4))
+    }
+
+    /// Serialize for the wire, compressing large messages when the peer
+    /// accepts it.
+    pub fn encode(&self, compress: bool) -> serde_json::Result<Frame> {
+        let json = serde_json::to_string(self)?;
+        if !compress || json.len() < COMPRESS_MIN_BYTES {
+            return Ok(Frame::Text(json));
+        }
+        match lz4::block::compress(json.as_bytes(), None, true) {
+            Ok(compressed) => {
+                let mut frame = COMPRESSED_FRAME_PREFIX.to_vec();
+                frame.extend_from_slice(&compressed);
+                Ok(Frame::Binary(frame))
+            }
+            Err(_) => Ok(Frame::Text(json)),
+        }
+    }
+
+    /// Decode a binary frame written by `encode`. Returns `None` for binary
+    /// frames that aren't compressed messages.
+    pub fn decode_compressed(frame: &[u8]) -> Option<anyhow::Result<Self>> {
+        let compressed = frame.strip_prefix(COMPRESSED_FRAME_PREFIX)?;
+        Some((|| {
+            // The block starts with its decompressed size; check it before
+            // allocating so a tiny frame can't claim gigabytes
+            let size = compressed
+                .first_chunk::<4>()
+                .map(|size| i32::from_le_bytes(*size))
+                .ok_or_else(|| anyhow::anyhow!("truncated compressed frame"))?;
+            if size < 0 || size as usize > MAX_MESSAGE_BYTES {
+                anyhow::bail!("compressed message too large ({size} bytes)");
+            }
+            let json = lz4::block::decompress(compressed, None)?;
+            Ok(serde_json::from_slice(&json)?)
+        })())
     }
 
     pub fn operation(operation: Operation) -> Self {
         Self::Operation { operation }
     }
+
+    pub fn batch(operations: Vec<Operation>) -> Self {
+        Self::OperationBatch { operations }
+    }
+
+    /// `Operation` for a single op, `OperationBatch` for several.
+    pub fn for_ops(ops: &[Arc<Operation>]) -> Self {
+        match ops {
+            [op] => Self::operation((**op).clone()),
+            _ => Self::batch(ops.iter().map(|op| (**op).clone()).collect()),
+        }
+    }
+
+    pub fn rejected(op_id: Uuid, reason: String) -> Self {
+        Self::Rejected { op_id, reason }
+    }
+
+    pub fn ack(op_id: Uuid) -> Self {
+        Self::Ack { op_id }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::OperationType;
+
+    #[test]
+    fn large_messages_compress_when_the_peer_accepts() {
+        let ops = (0..50)
+            .map(|i| {
+                Operation::new(
+                    format!("src/file_{i}.rs"),
+                    OperationType::FileCreate {
+                        content: "fn main() {}\n".repeat(10),
+                    },
+                    "actor".into(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let msg = SyncMessage::batch(ops);
+
+        let Frame::Text(json) = msg.encode(false).unwrap() else {
+            panic!("uncompressed message sent as binary");
+        };
+        let Frame::Binary(frame) = msg.encode(true).unwrap() else {
+            panic!("large message was not compressed");
+        };
+        assert!(frame.len() < json.len());
+
+        let decoded = SyncMessage::decode_compressed(&frame).unwrap().unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+        assert!(SyncMessage::decode_compressed(b"not compressed").is_none());
+
+        let mut bomb = COMPRESSED_FRAME_PREFIX.to_vec();
+        bomb.extend_from_slice(&i32::MAX.to_le_bytes());
+        assert!(SyncMessage::decode_compressed(&bomb).unwrap().is_err());
+
+        let small = SyncMessage::ack(uuid::Uuid::new_v4());
+        assert!(matches!(small.encode(true).unwrap(), Frame::Text(_)));
+    }
+
+    #[test]
+    fn handshakes_advertise_compression() {
+        let handshake = SyncMessage::handshake(ActorIdentity::anonymous("a"), "repo".into());
+        assert!(handshake.accepts_compression());
+
+        let legacy: SyncMessage =
+            serde_json::from_str(r#"{"type":"handshake","actor_id":"a","repo_id":"repo"}"#).unwrap();
+        assert!(!legacy.accepts_compression());
+    }
 }
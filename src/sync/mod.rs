@@ -1,4 +1,5 @@
 pub mod clock;
+pub mod discovery;
 pub mod messages;
 pub mod protocol;
 pub mod remote;
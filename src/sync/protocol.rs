@@ -1,36 +1,161 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
 use tokio::sync::broadcast;
+use uuid::Uuid;
 
 use crate::crdt::Operation;
+use crate::storage::{OperationLog, QueryFilter};
+
+/// Published batches a [`SyncManager`] holds for its slowest subscriber
+/// unless configured otherwise.
+pub const DEFAULT_SYNC_CHANNEL_CAPACITY: usize = 256;
+
+/// Most operations sent to a peer in one message when catching up.
+pub const CATCH_UP_BATCH: usize = 256;
+
+/// Operations published together, e.g. every op from one debounce tick.
+/// Subscribers that forward to peers send a batch as a single message.
+pub type OperationBatch = Arc<Vec<Arc<Operation>>>;
 
 /// Lightweight in-process sync manager using a tokio broadcast channel.
 /// Components can `publish` operations and other components can `subscribe`
 /// to receive live updates. Messages are wrapped in `Arc` to make cloning cheap.
 #[derive(Clone)]
 pub struct SyncManager {
-    tx: broadcast::Sender<Arc<Operation>>,
+    tx: broadcast::Sender<OperationBatch>,
 }
 
 impl SyncManager {
-    /// Create a new SyncManager with a reasonable buffer size.
+    /// Create a new SyncManager with the default buffer size.
+    #[allow(dead_code)]
     pub fn new() -> Self {
-        let (tx, _) = broadcast::channel(256);
+        Self::with_capacity(DEFAULT_SYNC_CHANNEL_CAPACITY)
+    }
+
+    /// Create a SyncManager that holds up to `capacity` published batches
+    /// for subscribers that haven't read them yet. A subscriber further
+    /// behind than that lags and has to [`catch_up`](Subscription::catch_up).
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
         Self { tx }
     }
 
     /// Subscribe to live operations. The receiver will receive only
     /// messages published after subscription.
-    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Operation>> {
-        self.tx.subscribe()
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            rx: self.tx.subscribe(),
+            pending: VecDeque::new(),
+            last: None,
+            subscribed_at: Utc::now(),
+            caught_up: HashSet::new(),
+        }
     }
 
     /// Publish an operation to all subscribers. Returns Err if there are
     /// no subscribers or the buffer is full.
+    #[allow(dead_code)]
     pub fn publish(
         &self,
         op: Arc<Operation>,
-    ) -> Result<usize, broadcast::error::SendError<Arc<Operation>>> {
-        self.tx.send(op)
+    ) -> Result<usize, broadcast::error::SendError<OperationBatch>> {
+        self.tx.send(Arc::new(vec![op]))
+    }
+
+    /// Publish operations that belong together, so subscribers see them as
+    /// one unit rather than interleaved with other publishers' operations.
+    pub fn publish_batch(
+        &self,
+        ops: Vec<Arc<Operation>>,
+    ) -> Result<usize, broadcast::error::SendError<OperationBatch>> {
+        self.tx.send(Arc::new(ops))
+    }
+}
+
+/// Receiving end of a [`SyncManager`]. Read either one operation at a time
+/// or a whole published batch at a time.
+pub struct Subscription {
+    rx: broadcast::Receiver<OperationBatch>,
+    // Rest of a batch partly handed out by `recv`
+    pending: VecDeque<Arc<Operation>>,
+    // Last operation handed out, where a catch-up resumes
+    last: Option<Uuid>,
+    subscribed_at: DateTime<Utc>,
+    // Operations a catch-up delivered that the live feed may repeat
+    caught_up: HashSet<Uuid>,
+}
+
+impl Subscription {
+    pub async fn recv(&mut self) -> Result<Arc<Operation>, broadcast::error::RecvError> {
+        loop {
+            if let Some(op) = self.pending.pop_front() {
+                if self.caught_up.remove(&op.id) {
+                    continue;
+                }
+                self.last = Some(op.id);
+                return Ok(op);
+            }
+            let batch = self.rx.recv().await?;
+            self.pending.extend(batch.iter().cloned());
+        }
+    }
+
+    pub async fn recv_batch(&mut self) -> Result<OperationBatch, broadcast::error::RecvError> {
+        loop {
+            let batch = if self.pending.is_empty() {
+                self.rx.recv().await?
+            } else {
+                Arc::new(self.pending.drain(..).collect())
+            };
+            let batch = if self.caught_up.is_empty() {
+                batch
+            } else {
+                Arc::new(
+                    batch
+                        .iter()
+                        .filter(|op| !self.caught_up.remove(&op.id))
+                        .cloned()
+                        .collect(),
+                )
+            };
+            if let Some(op) = batch.last() {
+                self.last = Some(op.id);
+                return Ok(batch);
+            }
+        }
+    }
+
+    /// Recover after a receive reported `Lagged`: skip to the live end of
+    /// the feed and return, from `oplog`, every operation stored since the
+    /// last one this subscription handed out (or since it subscribed). The
+    /// live feed then carries on where the returned operations end.
+    pub async fn catch_up(&mut self, oplog: &Arc<OperationLog>) -> Result<Vec<Arc<Operation>>> {
+        // Resubscribe first, so anything published while the log is read
+        // arrives live rather than falling in between
+        self.rx = self.rx.resubscribe();
+        self.pending.clear();
+
+        let oplog = oplog.clone();
+        let (last, since) = (self.last, self.subscribed_at);
+        let missed = tokio::task::spawn_blocking(move || {
+            oplog.flush();
+            match last {
+                Some(id) => oplog.db().operations_stored_after(&id),
+                None => oplog.db().query_operations(&QueryFilter {
+                    since: Some(since),
+                    ..Default::default()
+                }),
+            }
+        })
+        .await??;
+
+        self.caught_up = missed.iter().map(|op| op.id).collect();
+        if let Some(op) = missed.last() {
+            self.last = Some(op.id);
+        }
+        Ok(missed.into_iter().map(Arc::new).collect())
     }
 }
 
@@ -55,5 +180,68 @@ mod tests {
         let got = rx.recv().await.unwrap();
         assert_eq!(got.id, op.id);
     }
+
+    #[tokio::test]
+    async fn batches_arrive_whole_or_one_by_one() {
+        let mgr = SyncManager::new();
+        let mut by_op = mgr.subscribe();
+        let mut by_batch = mgr.subscribe();
+
+        let ops: Vec<Arc<Operation>> = (0..3)
+            .map(|i| {
+                Arc::new(Operation::new(
+                    format!("/tmp/{i}"),
+                    crate::crdt::OperationType::FileDelete,
+                    "actor".into(),
+                ))
+            })
+            .collect();
+        mgr.publish_batch(ops.clone()).unwrap();
+
+        let batch = by_batch.recv_batch().await.unwrap();
+        assert_eq!(batch.len(), 3);
+        for op in &ops {
+            assert_eq!(by_op.recv().await.unwrap().id, op.id);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lagging_subscriber_catches_up_from_the_log() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db = Arc::new(crate::storage::Database::new(temp_dir.path()).unwrap());
+        db.initialize().unwrap();
+        let oplog = Arc::new(OperationLog::new(db));
+        let mgr = SyncManager::with_capacity(2);
+        let mut rx = mgr.subscribe();
+
+        let record = |i: usize| {
+            let op = Operation::new(
+                format!("/tmp/{i}"),
+                crate::crdt::OperationType::FileDelete,
+                "actor".into(),
+            );
+            oplog.append(op.clone()).unwrap();
+            mgr.publish(Arc::new(op.clone())).unwrap();
+            op
+        };
+
+        let first = record(0);
+        assert_eq!(rx.recv().await.unwrap().id, first.id);
+        let missed: Vec<Operation> = (1..6).map(record).collect();
+        assert!(matches!(
+            rx.recv().await,
+            Err(broadcast::error::RecvError::Lagged(_))
+        ));
+
+        let caught_up = rx.catch_up(&oplog).await.unwrap();
+        let ids: Vec<Uuid> = caught_up.iter().map(|op| op.id).collect();
+        let expected: Vec<Uuid> = missed.iter().map(|op| op.id).collect();
+        assert_eq!(ids, expected);
+
+        // The live feed carries on without repeating what the catch-up sent
+        mgr.publish(caught_up[4].clone()).unwrap();
+        let next = record(6);
+        assert_eq!(rx.recv().await.unwrap().id, next.id);
+    }
 }
 // Future: WebSocket-based sync protocol for real-time collaboration
@@ -1,23 +1,43 @@
+// WebSocket-based sync protocol for real-time collaboration
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use anyhow::{Result, anyhow};
 use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 use url::Url;
 
-use super::protocol::SyncManager;
+use super::protocol::{CATCH_UP_BATCH, SyncManager};
 use crate::crdt::Operation;
-use crate::storage::OperationLog;
+use crate::storage::{ActorIdentity, OperationLog};
+use crate::sync::messages::{Frame, MAX_MESSAGE_BYTES};
 use crate::sync::{GLOBAL_CLOCK, SyncMessage};
 use colored::*;
 use dashmap::DashSet;
 use reqwest::Client;
 use uuid::Uuid;
 
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const RECONNECT_MIN: Duration = Duration::from_millis(500);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
 /// Connect to a remote WebSocket peer and bridge operations between the
 /// in-process SyncManager and the remote. Returns a JoinHandle for the
 /// background task managing the connection.
+///
+/// The first connection attempt must succeed. After that the link reconnects
+/// with backoff whenever it drops, and each (re)connect replays the local
+/// operations the peer hasn't received yet so offline edits still propagate.
 pub async fn connect_peer(
     url: &str,
     actor_id: String,
@@ -25,109 +45,161 @@ pub async fn connect_peer(
     sync: SyncManager,
     oplog: Arc<OperationLog>,
 ) -> Result<JoinHandle<()>> {
-    let seen = Arc::new(DashSet::new());
     let url = Url::parse(url).map_err(|e| anyhow!("invalid ws url: {e}"))?;
-    let (ws_stream, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+    let link = Arc::new(PeerLink {
+        peer_id: url.to_string(),
+        token: crate::server::auth::token_from_env(),
+        url,
+        actor_id,
+        repo_id,
+        sync,
+        oplog,
+        seen: DashSet::new(),
+        compress: AtomicBool::new(false),
+    });
+
+    let first = link.open().await?;
+
+    let handle = tokio::spawn(async move {
+        let mut session = Some(first);
+        let mut backoff = RECONNECT_MIN;
+        loop {
+            if let Some(ws) = session.take() {
+                link.clone().run(ws).await;
+                backoff = RECONNECT_MIN;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(RECONNECT_MAX);
+
+            if let Ok(ws) = link.open().await {
+                println!(
+                    "{} Reconnected peer {}",
+                    "↔".bright_blue(),
+                    link.peer_id.bright_yellow()
+                );
+                session = Some(ws);
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+struct PeerLink {
+    peer_id: String,
+    url: Url,
+    token: Option<String>,
+    actor_id: String,
+    repo_id: String,
+    sync: SyncManager,
+    oplog: Arc<OperationLog>,
+    seen: DashSet<Uuid>,
+    /// Whether the peer's handshake accepted compressed frames.
+    compress: AtomicBool,
+}
 
-    let (mut ws_tx, mut ws_rx) = ws_stream.split();
+impl PeerLink {
+    /// Connect, send our handshake and pull the peer's recent history.
+    async fn open(&self) -> Result<WsStream> {
+        let mut request = self.url.as_str().into_client_request()?;
+        if let Some(token) = &self.token {
+            request.headers_mut().insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {token}"))?,
+            );
+        }
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(MAX_MESSAGE_BYTES))
+            .max_frame_size(Some(MAX_MESSAGE_BYTES));
+        let (mut ws_stream, _) =
+            tokio_tungstenite::connect_async_with_config(request, Some(config), false).await?;
+        // Plain JSON until this connection's handshake says otherwise
+        self.compress.store(false, Ordering::Relaxed);
 
-    // Send handshake so the peer can deduplicate correctly
-    let handshake = SyncMessage::handshake(actor_id.clone(), repo_id.clone());
-    let handshake_json = serde_json::to_string(&handshake)?;
-    ws_tx.send(Message::Text(handshake_json.into())).await?;
+        // Send handshake so the peer can deduplicate correctly
+        let identity = self
+            .oplog
+            .db()
+            .actor(&self.actor_id)?
+            .unwrap_or_else(|| ActorIdentity::anonymous(self.actor_id.clone()));
+        let handshake = SyncMessage::handshake(identity, self.repo_id.clone());
+        let handshake_json = serde_json::to_string(&handshake)?;
+        ws_stream.send(Message::Text(handshake_json.into())).await?;
 
-    // Initial cold start sync via HTTP
-    if let Some(ops_url) = derive_ops_url(&url) {
-        if let Ok(ops) = fetch_initial_ops(ops_url).await {
+        // Initial cold start sync via HTTP
+        if let Some(ops_url) = derive_ops_url(&self.url)
+            && let Ok(ops) = fetch_initial_ops(ops_url, self.token.as_deref()).await
+        {
             for op in ops.into_iter().rev() {
-                if insert_seen(&seen, op.id) {
-                    if let Some(lamport) = op.lamport() {
-                        GLOBAL_CLOCK.observe(lamport);
-                    }
-                    if let Ok(true) = oplog.append(op.clone()) {
-                        let _ = sync.publish(Arc::new(op));
-                    }
-                }
+                self.ingest(op);
             }
         }
+
+        Ok(ws_stream)
     }
 
-    // Subscribe to local ops to forward to remote
-    let mut rx = sync.subscribe();
+    /// Bridge one connection until either direction fails.
+    async fn run(self: Arc<Self>, ws_stream: WsStream) {
+        let (mut ws_tx, mut ws_rx) = ws_stream.split();
 
-    // Spawn forwarder for local -> remote
-    let actor_id_clone = actor_id.clone();
-    let seen_forward = seen.clone();
-    let forward = tokio::spawn(async move {
-        loop {
-            match rx.recv().await {
-                Ok(op_arc) => {
-                    // Only forward our own actor's ops to reduce echo, server will broadcast
-                    if op_arc.actor_id == actor_id_clone && insert_seen(&seen_forward, op_arc.id) {
-                        if let Ok(json) =
-                            serde_json::to_string(&SyncMessage::operation((*op_arc).clone()))
-                        {
-                            if ws_tx.send(Message::Text(json.into())).await.is_err() {
-                                break;
+        // Subscribe before replaying so nothing published in between is lost;
+        // the seen set keeps replayed ops from being forwarded twice.
+        let mut rx = self.sync.subscribe();
+
+        let link = self.clone();
+        let forward = tokio::spawn(async move {
+            if link.replay_pending(&mut ws_tx).await.is_err() {
+                return;
+            }
+            loop {
+                let batch = match rx.recv_batch().await {
+                    Ok(batch) => batch,
+                    // Fell behind: forward what was missed from the log
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let missed = rx.catch_up(&link.oplog).await.unwrap_or_default();
+                        let own: Vec<Arc<Operation>> = missed
+                            .into_iter()
+                            .filter(|op| {
+                                op.actor_id == link.actor_id && insert_seen(&link.seen, op.id)
+                            })
+                            .collect();
+                        for chunk in own.chunks(CATCH_UP_BATCH) {
+                            if link.send_batch(&mut ws_tx, chunk).await.is_err() {
+                                return;
                             }
                         }
+                        continue;
                     }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                // Only forward our own actor's ops to reduce echo, server will broadcast
+                let own: Vec<Arc<Operation>> = batch
+                    .iter()
+                    .filter(|op| op.actor_id == link.actor_id && insert_seen(&link.seen, op.id))
+                    .cloned()
+                    .collect();
+                if !own.is_empty() && link.send_batch(&mut ws_tx, &own).await.is_err() {
+                    break;
                 }
-                Err(_) => break,
             }
-        }
-    });
+        });
 
-    // Spawn receiver for remote -> local
-    let sync_clone = sync.clone();
-    let actor_id_clone2 = actor_id.clone();
-    let oplog_clone = oplog.clone();
-    let seen_recv = seen.clone();
-    let recv = tokio::spawn(async move {
         while let Some(msg) = ws_rx.next().await {
             match msg {
                 Ok(Message::Text(text)) => {
                     let text: String = text.to_string();
                     if let Ok(msg) = serde_json::from_str::<SyncMessage>(&text) {
-                        match msg {
-                            SyncMessage::Handshake { actor_id, repo_id } => {
-                                println!(
-                                    "{} Connected peer handshake (actor={} repo={})",
-                                    "↔".bright_blue(),
-                                    actor_id.bright_yellow(),
-                                    repo_id.bright_white()
-                                );
-                            }
-                            SyncMessage::Operation { operation: op } => {
-                                if op.actor_id != actor_id_clone2 && insert_seen(&seen_recv, op.id)
-                                {
-                                    if let Some(lamport) = op.lamport() {
-                                        GLOBAL_CLOCK.observe(lamport);
-                                    }
-                                    let _ = oplog_clone.append(op.clone());
-                                    let _ = sync_clone.publish(Arc::new(op));
-                                }
-                            }
-                        }
+                        self.handle(msg);
                     } else if let Ok(op) = serde_json::from_str::<Operation>(&text) {
-                        if op.actor_id != actor_id_clone2 && insert_seen(&seen_recv, op.id) {
-                            if let Some(lamport) = op.lamport() {
-                                GLOBAL_CLOCK.observe(lamport);
-                            }
-                            let _ = oplog_clone.append(op.clone());
-                            let _ = sync_clone.publish(Arc::new(op));
-                        }
+                        self.ingest(op);
                     }
                 }
                 Ok(Message::Binary(bin)) => {
-                    if let Ok(op) = serde_cbor::from_slice::<Operation>(&bin) {
-                        if op.actor_id != actor_id_clone2 && insert_seen(&seen_recv, op.id) {
-                            if let Some(lamport) = op.lamport() {
-                                GLOBAL_CLOCK.observe(lamport);
-                            }
-                            let _ = oplog_clone.append(op.clone());
-                            let _ = sync_clone.publish(Arc::new(op));
-                        }
+                    if let Some(Ok(msg)) = SyncMessage::decode_compressed(&bin) {
+                        self.handle(msg);
+                    } else if let Ok(op) = serde_cbor::from_slice::<Operation>(&bin) {
+                        self.ingest(op);
                     }
                 }
                 Ok(Message::Frame(_)) => { /* ignore */ }
@@ -136,15 +208,165 @@ pub async fn connect_peer(
                 }
                 Err(_) => break,
             }
+            if forward.is_finished() {
+                break;
+            }
         }
-    });
 
-    // Join both tasks under a single handle
-    let handle = tokio::spawn(async move {
-        let _ = tokio::join!(forward, recv);
-    });
+        forward.abort();
+    }
 
-    Ok(handle)
+    /// Act on one message from the peer.
+    fn handle(&self, msg: SyncMessage) {
+        if let SyncMessage::Handshake { .. } = &msg {
+            self.compress.store(msg.accepts_compression(), Ordering::Relaxed);
+        }
+        match msg {
+            SyncMessage::Handshake {
+                actor_id,
+                repo_id,
+                actor_name,
+                actor_email,
+                ..
+            } => {
+                let identity = ActorIdentity {
+                    actor_id,
+                    name: actor_name,
+                    email: actor_email,
+                };
+                if !identity.is_anonymous() {
+                    let _ = self.oplog.db().record_actor(&identity);
+                }
+                println!(
+                    "{} Connected peer handshake (actor={} repo={})",
+                    "↔".bright_blue(),
+                    identity.display_name().bright_yellow(),
+                    repo_id.bright_white()
+                );
+            }
+            SyncMessage::Operation { operation: op } => {
+                self.ingest(op);
+            }
+            SyncMessage::OperationBatch { operations } => {
+                self.ingest_batch(operations);
+            }
+            SyncMessage::Ack { op_id } => {
+                if let Err(err) = self.acked(op_id) {
+                    println!(
+                        "{} Failed to record sync position for {}: {}",
+                        "⚠️".bright_red(),
+                        self.peer_id.bright_yellow(),
+                        err
+                    );
+                }
+            }
+            SyncMessage::Rejected { op_id, reason } => {
+                println!(
+                    "{} Peer {} rejected operation {}: {}",
+                    "⚠️".bright_red(),
+                    self.peer_id.bright_yellow(),
+                    op_id,
+                    reason
+                );
+            }
+        }
+    }
+
+    /// Send every local operation recorded after the last one the peer
+    /// acknowledged, in batches of up to `CATCH_UP_BATCH`.
+    async fn replay_pending<S>(&self, ws_tx: &mut S) -> Result<()>
+    where
+        S: SinkExt<Message> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        // Operations still buffered by the writer are pending too
+        let oplog = self.oplog.clone();
+        tokio::task::spawn_blocking(move || oplog.flush()).await?;
+
+        let db = self.oplog.db();
+        let marker = db.sync_marker(&self.peer_id)?;
+        let pending: Vec<Arc<Operation>> = db
+            .operations_after_marker(&self.actor_id, marker.as_ref())?
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+        for chunk in pending.chunks(CATCH_UP_BATCH) {
+            for op in chunk {
+                self.seen.insert(op.id);
+            }
+            self.send_batch(ws_tx, chunk).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send operations published together as one message.
+    async fn send_batch<S>(&self, ws_tx: &mut S, ops: &[Arc<Operation>]) -> Result<()>
+    where
+        S: SinkExt<Message> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        self.send_message(ws_tx, &SyncMessage::for_ops(ops)).await
+    }
+
+    async fn send_message<S>(&self, ws_tx: &mut S, msg: &SyncMessage) -> Result<()>
+    where
+        S: SinkExt<Message> + Unpin,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let frame = match msg.encode(self.compress.load(Ordering::Relaxed))? {
+            Frame::Text(json) => Message::Text(json.into()),
+            Frame::Binary(bytes) => Message::Binary(bytes.into()),
+        };
+        ws_tx.send(frame).await?;
+        Ok(())
+    }
+
+    /// Advance the peer's sync marker to an operation it acknowledged, so
+    /// the next connection replays only what came after it. Only the peer's
+    /// ack moves the marker: an op sent just before a disconnect is resent.
+    fn acked(&self, op_id: Uuid) -> Result<()> {
+        let db = self.oplog.db();
+        // An op still buffered by the writer is only in the log's cache
+        let op = match self.oplog.get(&op_id) {
+            Some(op) => Some(op),
+            None => db.operation(&op_id)?,
+        };
+        if let Some(op) = op
+            && op.actor_id == self.actor_id
+        {
+            db.set_sync_marker(&self.peer_id, &op)?;
+        }
+        Ok(())
+    }
+
+    /// Record an operation that arrived from the peer and publish it locally.
+    fn ingest(&self, op: Operation) {
+        self.ingest_batch(vec![op]);
+    }
+
+    /// Record operations the peer sent together and publish the new ones
+    /// locally as a single batch.
+    fn ingest_batch(&self, ops: Vec<Operation>) {
+        let mut accepted = Vec::with_capacity(ops.len());
+        for op in ops {
+            if op.actor_id == self.actor_id || !insert_seen(&self.seen, op.id) {
+                continue;
+            }
+            if let Some(lamport) = op.lamport() {
+                GLOBAL_CLOCK.observe(lamport);
+            }
+            if let Ok(true) = self.oplog.append(op.clone()) {
+                accepted.push(Arc::new(op));
+            }
+        }
+        if !accepted.is_empty() {
+            let _ = self.sync.publish_batch(accepted);
+        }
+    }
 }
 
 const SEEN_LIMIT: usize = 10_000;
@@ -186,9 +408,13 @@ fn derive_ops_url(ws_url: &Url) -> Option<Url> {
     Some(http)
 }
 
-async fn fetch_initial_ops(url: Url) -> Result<Vec<Operation>> {
+async fn fetch_initial_ops(url: Url, token: Option<&str>) -> Result<Vec<Operation>> {
     let client = Client::new();
-    let resp = client.get(url).send().await?;
+    let mut request = client.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let resp = request.send().await?;
     let status = resp.status();
     if !status.is_success() {
         return Err(anyhow!("failed to fetch ops: {status}"));
@@ -92,14 +92,18 @@ fn collect_trackable_files(root: &Path) -> Result<Vec<PathBuf>> {
     
     let mut files = Vec::new();
     
-    let walker = WalkBuilder::new(root)
+    let mut builder = WalkBuilder::new(root);
+    builder
         .hidden(false)
         .git_ignore(true)
         .git_global(true)
         .git_exclude(true)
         .max_depth(None)
-        .follow_links(false)
-        .build();
+        .follow_links(false);
+    if let Some(filter) = super::filter::active() {
+        builder.overrides(filter.overrides().clone());
+    }
+    let walker = builder.build();
     
     for entry in walker {
         if let Ok(entry) = entry {
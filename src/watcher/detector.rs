@@ -1,31 +1,34 @@
 use anyhow::Result;
 use colored::*;
-use notify::event::{ModifyKind, RenameMode};
+use notify::event::{ModifyKind, RemoveKind, RenameMode};
 use notify::{EventKind, RecursiveMode};
 use notify_debouncer_full::{new_debouncer, DebounceEventResult};
 use once_cell::sync::Lazy;
 use std::fs::File;
 use std::path::{Component, Path, PathBuf};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex as StdMutex};
-use std::sync::mpsc::{channel, Receiver};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
 use std::time::{Duration, Instant};
 use memmap2::Mmap;
 
 use crate::crdt::{Operation, OperationType, Position};
-use crate::storage::OperationLog;
+use crate::server::rate_limit::TokenBucket;
+use crate::storage::{OperationLog, reconstruct};
+use crate::storage::config::{
+    DEFAULT_ATOMIC_SAVE_WINDOW_MS, DEFAULT_MAX_FILE_OPS_PER_SEC, DEFAULT_MAX_TRACKED_BYTES,
+};
 use crate::sync::{GLOBAL_CLOCK, SyncManager};
-use crate::watcher::cache_warmer;
-use dashmap::DashMap;
+use crate::watcher::{cache_warmer, filter};
+use dashmap::{DashMap, DashSet};
 use std::sync::Arc as StdArc;
-use uuid::Uuid;
 
 // 🚀 PERFORMANCE OPTIMIZATION: Cache path->string conversions (Windows paths are slow to convert)
 // Inspired by dx-style's sub-100µs performance techniques
 static PATH_STRING_CACHE: Lazy<DashMap<PathBuf, String>> = Lazy::new(|| DashMap::new());
 
 // � ULTRA-FAST FILE HASH CACHE: ahash-based instant change detection (dx-style)
-// Maps path -> (file_hash, mtime, size) for O(1) "has file changed?" checks
+// Maps path -> (file_hash, mtime_ns, size) for O(1) "has file changed?" checks
 static FILE_HASH_CACHE: Lazy<DashMap<PathBuf, (u64, u64, u64)>> = Lazy::new(|| DashMap::new());
 
 // �🚀 Get cached path string or convert and cache (avoids expensive Windows path conversions)
@@ -43,25 +46,26 @@ fn path_to_string(path: &Path) -> String {
 /// 🚀 ULTRA-FAST: Check if file changed using ONLY metadata (dx-style, <1µs)
 /// Returns false if file definitely hasn't changed (mtime+size match)
 #[inline(always)]
-#[allow(dead_code)]
 fn file_definitely_changed(path: &Path) -> bool {
     // Quick metadata check only (< 1µs) - NO content hashing!
     let Ok(metadata) = std::fs::metadata(path) else { return true };
     let size = metadata.len();
     let Ok(mtime) = metadata.modified() else { return true };
-    let Ok(mtime_secs) = mtime.duration_since(std::time::UNIX_EPOCH) else { return true };
+    let Ok(since_epoch) = mtime.duration_since(std::time::UNIX_EPOCH) else { return true };
+    // Nanoseconds, so two same-size writes within a second still differ
+    let mtime_ns = since_epoch.as_nanos() as u64;
     
     // Check cache: if mtime+size match, file definitely hasn't changed
     if let Some(cached) = FILE_HASH_CACHE.get(path) {
         let (_hash, cached_mtime, cached_size) = *cached.value();
-        if cached_mtime == mtime_secs.as_secs() && cached_size == size {
+        if cached_mtime == mtime_ns && cached_size == size {
             return false; // File hasn't changed, skip processing!
         }
     }
     
     // File changed or not cached - update cache with new metadata
     // We'll compute hash lazily only if we actually need to diff
-    FILE_HASH_CACHE.insert(path.to_path_buf(), (0, mtime_secs.as_secs(), size));
+    FILE_HASH_CACHE.insert(path.to_path_buf(), (0, mtime_ns, size));
     true
 }
 
@@ -92,7 +96,7 @@ static DISABLE_RAPID_MODE: Lazy<bool> = Lazy::new(|| {
 /// ⚡ ULTRA-FAST MODE: Change detection with ZERO syscalls (<20µs)
 /// Returns simple event indicating file changed
 #[inline(always)]
-fn detect_rapid_change(path: &Path) -> Option<u64> {
+fn detect_rapid_change(_path: &Path) -> Option<u64> {
     // Skip if disabled via env var
     if *DISABLE_RAPID_MODE {
         return Some(0);
@@ -102,11 +106,8 @@ fn detect_rapid_change(path: &Path) -> Option<u64> {
     
     // Ultra-fast: NO syscalls! Just use atomic sequence counter
     // This achieves sub-10µs performance by avoiding ALL system calls
-    let sequence = RAPID_SEQUENCE.fetch_add(1, Ordering::Relaxed);
-    
-    // Update cache (notify debouncer already handles duplicates, no need for extra check)
-    // We trust that if we got the event, it's a real change
-    FILE_HASH_CACHE.insert(path.to_path_buf(), (0, sequence, 0));
+    // (unchanged files were already filtered by file_definitely_changed)
+    RAPID_SEQUENCE.fetch_add(1, Ordering::Relaxed);
     
     let elapsed = start.elapsed().as_micros() as u64;
     
@@ -192,26 +193,89 @@ static PROFILE_DETECT: Lazy<bool> = Lazy::new(|| {
         .unwrap_or(false)
 });
 
+// 🧪 Dry run: detect and report operations without recording or syncing them
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+// 📈 Print recording throughput every 100 operations (`forge watch --stats`)
+static SHOW_STATS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_show_stats(enabled: bool) {
+    SHOW_STATS.store(enabled, Ordering::Relaxed);
+}
+
 // 🎯 Performance target: Sub-20µs operation processing (dx-style level)
 const TARGET_PERFORMANCE_US: u128 = 20;
 
-// 🚀 Watcher mode (ultra-fast 1ms debounce only)
+// 🚀 Watcher mode
 enum WatchMode {
-    Debounced(Duration), // Ultra-fast debounced events
+    Debounced(Duration), // Debounced events
 }
 
-// 🚀 Watcher mode configuration (ultra-fast 1ms debounce only)
-const DEBOUNCE_MS: u64 = 1; // Ultra-fast 1ms debounce for sub-20µs target
+// 🚀 Debounce from the watch profile or `debounce_ms` (1ms for sub-20µs latency)
+static DEBOUNCE_MS: AtomicU64 = AtomicU64::new(1);
+
+pub fn set_debounce(debounce: Duration) {
+    DEBOUNCE_MS.store(debounce.as_millis() as u64, Ordering::Relaxed);
+}
 
 impl WatchMode {
     fn from_env() -> Self {
+        // DX_WATCH_DEBOUNCE_MS overrides the configured debounce
+        let debounce_ms = std::env::var("DX_WATCH_DEBOUNCE_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| DEBOUNCE_MS.load(Ordering::Relaxed));
         // println!(
         //     "{} Using ultra-fast mode: {}ms debounce (sub-20µs target)",
         //     "⚡".bright_yellow(),
-        //     DEBOUNCE_MS
+        //     debounce_ms
         // );
-        WatchMode::Debounced(Duration::from_millis(DEBOUNCE_MS))
+        WatchMode::Debounced(Duration::from_millis(debounce_ms))
+    }
+}
+
+// 🎯 Accuracy profile: wait for a file's writes to settle before diffing it
+static SETTLE_WRITES: AtomicBool = AtomicBool::new(false);
+
+// How often a file still being written is re-checked, and for how long at most
+const SETTLE_INTERVAL: Duration = Duration::from_millis(10);
+const SETTLE_ATTEMPTS: usize = 20;
+
+pub fn set_settle_writes(enabled: bool) {
+    SETTLE_WRITES.store(enabled, Ordering::Relaxed);
+}
+
+// 🧱 Keep binary files' content as blobs, not just their hashes
+static TRACK_BINARY: AtomicBool = AtomicBool::new(false);
+
+pub fn set_track_binary(enabled: bool) {
+    TRACK_BINARY.store(enabled, Ordering::Relaxed);
+}
+
+/// Block until `path`'s size and mtime are the same on two checks in a row.
+/// A file that keeps changing is diffed anyway after `SETTLE_ATTEMPTS`, so a
+/// log being appended to isn't starved; its next event records the rest.
+/// Returns whether the file settled.
+fn wait_for_settle(path: &Path) -> bool {
+    let stamp = |path: &Path| {
+        std::fs::metadata(path)
+            .ok()
+            .map(|meta| (meta.len(), meta.modified().ok()))
+    };
+    let mut last = stamp(path);
+    for _ in 0..SETTLE_ATTEMPTS {
+        std::thread::sleep(SETTLE_INTERVAL);
+        let current = stamp(path);
+        if current == last {
+            return true;
+        }
+        last = current;
     }
+    false
 }
 
 pub async fn start_watching(
@@ -275,17 +339,41 @@ async fn start_debounced_watcher(
     let mut debouncer = new_debouncer(debounce, None, tx)?;
     debouncer.watch(&path, RecursiveMode::Recursive)?;
 
-    process_events_loop(rx, actor_id, oplog, sync_mgr).await
+    // The loop blocks on the event channel, so it gets a thread of its own
+    // rather than a runtime worker; the debouncer stays alive until it ends
+    let result =
+        tokio::task::spawn_blocking(move || process_events_loop(rx, actor_id, oplog, sync_mgr))
+            .await?;
+    drop(debouncer);
+    result
 }
 
 // 🎯 Core event processing loop (shared by all modes)
-async fn process_events_loop(
+fn process_events_loop(
     rx: Receiver<DebounceEventResult>,
     actor_id: String,
     oplog: Arc<OperationLog>,
     sync_mgr: Option<StdArc<SyncManager>>,
 ) -> Result<()> {
-    while let Ok(result) = rx.recv() {
+    loop {
+        // Wake up in time to record deletes that never turned into a rename
+        // and the snapshots of throttled files that went quiet
+        let result = match next_flush_wait() {
+            None => match rx.recv() {
+                Ok(result) => result,
+                Err(_) => break,
+            },
+            Some(wait) => match rx.recv_timeout(wait) {
+                Ok(result) => result,
+                Err(RecvTimeoutError::Timeout) => {
+                    flush_pending_deletes(false, &actor_id, oplog.as_ref(), &sync_mgr)?;
+                    flush_throttled(false, &actor_id, oplog.as_ref(), &sync_mgr)?;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+        };
+
         match result {
             Ok(events) => {
                 for event in events {
@@ -334,6 +422,12 @@ async fn process_events_loop(
                             }
                             _ => {}
                         },
+                        EventKind::Modify(ModifyKind::Metadata(_)) => {
+                            for path in &event.paths {
+                                process_mode_change(path, &actor_id, start, oplog.as_ref(), &sync_mgr)?;
+                                process_path(path, &actor_id, start, oplog.as_ref(), &sync_mgr)?;
+                            }
+                        }
                         EventKind::Modify(_) => {
                             for path in &event.paths {
                                 process_path(path, &actor_id, start, oplog.as_ref(), &sync_mgr)?;
@@ -341,26 +435,40 @@ async fn process_events_loop(
                         }
                         EventKind::Create(_) => {
                             for path in &event.paths {
+                                if is_real_dir(path) {
+                                    process_dir_create(path, &actor_id, start, oplog.as_ref(), &sync_mgr)?;
+                                    continue;
+                                }
                                 // Warm cache for newly created files
                                 let _ = cache_warmer::warm_file(path);
                                 process_path(path, &actor_id, start, oplog.as_ref(), &sync_mgr)?;
                             }
                         }
-                        EventKind::Remove(_) => {
+                        EventKind::Remove(kind) => {
                             for path in &event.paths {
+                                // The path is gone, so only the event kind or
+                                // an earlier create says it was a directory
+                                if *kind == RemoveKind::Folder || DIRECTORIES.contains(path) {
+                                    process_dir_delete(path, &actor_id, start, oplog.as_ref(), &sync_mgr)?;
+                                    continue;
+                                }
                                 if is_temp_path(path) {
                                     continue;
                                 }
                                 TEMP_CONTENT_CACHE.remove(path);
                                 if should_track(path) {
+                                    if defer_delete(path) {
+                                        continue;
+                                    }
                                     let detect_start = Instant::now();
                                     clear_prev_state(path);
-                                    clear_last_operation_entry(path);
-                                    let op = register_operation(Operation::new(
+                                    SYMLINK_TARGETS.remove(path);
+                                    oplog.clear_head(&path_to_string(path));
+                                    let op = Operation::new(
                                         path_to_string(path),
                                         OperationType::FileDelete,
                                         actor_id.clone(),
-                                    ));
+                                    );
 
                                     let detect_us = detect_start.elapsed().as_micros();
                                     emit_operations(vec![op], detect_us, start, oplog.as_ref(), &sync_mgr)?;
@@ -377,8 +485,13 @@ async fn process_events_loop(
                 }
             }
         }
+
+        flush_pending_deletes(false, &actor_id, oplog.as_ref(), &sync_mgr)?;
+        flush_throttled(false, &actor_id, oplog.as_ref(), &sync_mgr)?;
     }
 
+    flush_pending_deletes(true, &actor_id, oplog.as_ref(), &sync_mgr)?;
+    flush_throttled(true, &actor_id, oplog.as_ref(), &sync_mgr)?;
     Ok(())
 }
 
@@ -389,6 +502,8 @@ struct FileSnapshot {
     char_len: usize,
     char_to_byte: Vec<usize>,
     line_starts: Vec<usize>,
+    // Unix permission bits, when known
+    mode: Option<u32>,
 }
 
 #[derive(Default, Clone, Copy)]
@@ -408,19 +523,68 @@ struct DetectionReport {
 }
 
 static PREV_STATE: Lazy<DashMap<PathBuf, FileSnapshot>> = Lazy::new(|| DashMap::new());
-static LAST_OPERATION: Lazy<DashMap<String, Uuid>> = Lazy::new(|| DashMap::new());
-static OPS_PROCESSED: AtomicU64 = AtomicU64::new(0);
 static LAST_THROUGHPUT_SNAPSHOT: Lazy<StdMutex<Instant>> =
     Lazy::new(|| StdMutex::new(Instant::now()));
 static TEMP_CONTENT_CACHE: Lazy<DashMap<PathBuf, (Arc<String>, Instant)>> =
     Lazy::new(|| DashMap::new());
 static LAST_RENAME_SOURCE: Lazy<StdMutex<Option<PathBuf>>> = Lazy::new(|| StdMutex::new(None));
+// Deleted files held back briefly with their last snapshot, in case a create
+// that follows is the same file under a new name
+static PENDING_DELETES: Lazy<DashMap<PathBuf, (FileSnapshot, Instant)>> =
+    Lazy::new(DashMap::new);
+// Directories seen created, so their removal is recorded as one
+static DIRECTORIES: Lazy<DashSet<PathBuf>> = Lazy::new(DashSet::new);
+// Last known target of every tracked symlink
+static SYMLINK_TARGETS: Lazy<DashMap<PathBuf, String>> = Lazy::new(DashMap::new);
+// Files larger than this are skipped (`max_tracked_bytes` in config.json)
+static MAX_TRACKED_FILE_BYTES: AtomicU64 = AtomicU64::new(DEFAULT_MAX_TRACKED_BYTES);
+// How long a delete waits for a create of the same file (`atomic_save_window_ms`)
+static ATOMIC_SAVE_WINDOW_MS: AtomicU64 = AtomicU64::new(DEFAULT_ATOMIC_SAVE_WINDOW_MS);
+// Changes per second one file records individually (`max_file_ops_per_sec`)
+static MAX_FILE_OPS_PER_SEC: AtomicU64 = AtomicU64::new(DEFAULT_MAX_FILE_OPS_PER_SEC as u64);
+// Each file's budget of individually recorded changes, for files that have
+// spent some of it
+static RATE_LIMITS: Lazy<DashMap<PathBuf, TokenBucket>> = Lazy::new(DashMap::new);
+// Files over their budget: when their changes were last recorded, and
+// whether they've changed since
+static THROTTLED: Lazy<DashMap<PathBuf, (Instant, bool)>> = Lazy::new(DashMap::new);
+
+/// Skip files larger than `bytes`.
+pub fn set_max_tracked_bytes(bytes: u64) {
+    MAX_TRACKED_FILE_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+fn max_tracked_bytes() -> u64 {
+    MAX_TRACKED_FILE_BYTES.load(Ordering::Relaxed)
+}
+
+/// Hold deletes for `window` so a save that deletes and re-creates a file
+/// is recorded as an edit, and a re-create under a new name as a rename.
+/// A zero window records every delete as it happens.
+pub fn set_atomic_save_window(window: Duration) {
+    ATOMIC_SAVE_WINDOW_MS.store(window.as_millis() as u64, Ordering::Relaxed);
+}
+
+fn atomic_save_window() -> Duration {
+    Duration::from_millis(ATOMIC_SAVE_WINDOW_MS.load(Ordering::Relaxed))
+}
+
+/// Record at most `limit` changes per second for any one file; a file
+/// changing faster is recorded once per [`THROTTLE_SNAPSHOT_INTERVAL`]
+/// instead. Zero removes the limit.
+pub fn set_max_file_ops_per_sec(limit: u32) {
+    MAX_FILE_OPS_PER_SEC.store(limit as u64, Ordering::Relaxed);
+}
+
+// How often a throttled file's accumulated changes are recorded
+const THROTTLE_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
 
 // � Ultra-fast deduplication now handled by FILE_HASH_CACHE (ahash-based, <1µs)
 
 const PREV_CONTENT_LIMIT: usize = 2_048;
-const MAX_TRACKED_FILE_BYTES: u64 = 1_000_000; // ~1MB per file
 const TEMP_CACHE_LIMIT: usize = 256;
+// Minimum content similarity (0.0–1.0) for a delete + create to be a rename
+const RENAME_SIMILARITY: f32 = 0.8;
 
 fn enforce_prev_state_limit() {
     while PREV_STATE.len() > PREV_CONTENT_LIMIT {
@@ -434,24 +598,25 @@ fn enforce_prev_state_limit() {
     }
 }
 
-fn record_throughput(micros: u128) {
-    let total = OPS_PROCESSED.fetch_add(1, Ordering::Relaxed) + 1;
-    if total % 100 == 0 {
-        if let Ok(mut guard) = LAST_THROUGHPUT_SNAPSHOT.lock() {
-            let elapsed = guard.elapsed();
-            if elapsed >= Duration::from_secs(1) {
-                let ops_per_sec = 100.0 / elapsed.as_secs_f64().max(f64::EPSILON);
-                println!(
-                    "{} Processed {} ops in {:.2}s (~{:.1} ops/s, last op {}µs)",
-                    "📈".bright_blue(),
-                    total,
-                    elapsed.as_secs_f64(),
-                    ops_per_sec,
-                    micros
-                );
-                *guard = Instant::now();
-            }
-        }
+fn report_throughput(oplog: &OperationLog) {
+    if !SHOW_STATS.load(Ordering::Relaxed) {
+        return;
+    }
+    let stats = oplog.throughput_snapshot();
+    if stats.total_ops % 100 != 0 {
+        return;
+    }
+    if let Ok(mut guard) = LAST_THROUGHPUT_SNAPSHOT.lock()
+        && guard.elapsed() >= Duration::from_secs(1)
+    {
+        println!(
+            "{} Processed {} ops (~{:.1} ops/s, last op {}µs)",
+            "📈".bright_blue(),
+            stats.total_ops,
+            stats.ops_per_sec,
+            stats.last_op_us
+        );
+        *guard = Instant::now();
     }
 }
 
@@ -470,14 +635,31 @@ fn emit_operations(
     // Store operations for diff display AFTER timing
     let ops_for_diff = ops.clone();
     
+    let dry_run = DRY_RUN.load(Ordering::Relaxed);
+    // Everything recorded this tick goes to peers as one batch
+    let mut publish = Vec::new();
+
     for op in ops {
+        // A binary file's content is stored before the operation pointing at it
+        if let OperationType::BinaryModify { new_hash, .. } = &op.op_type
+            && let Some((hash, content)) = PENDING_BLOBS.remove(new_hash)
+            && !dry_run
+        {
+            oplog.db().store_blob(&hash, &content)?;
+        }
+
         // 🔥 FAST PATH: Skip timing for appends - just do it
-        let append_result = oplog.append(op.clone())?;
-        
-        if append_result {
-            // 🔥 FAST PATH: Non-blocking publish
-            if let Some(mgr) = sync_mgr {
-                let _ = mgr.publish(StdArc::new(op.clone()));
+        // Parents are assigned here, under the file's lock, so a peer op
+        // landing for the same file can't fork the causality chain.
+        let recorded = if dry_run {
+            Some(op)
+        } else {
+            oplog.append_local(op)?
+        };
+
+        if let Some(op) = recorded {
+            if sync_mgr.is_some() && !dry_run {
+                publish.push(StdArc::new(op.clone()));
             }
             
             let total_us = start.elapsed().as_micros();
@@ -487,9 +669,15 @@ fn emit_operations(
                 print_operation(&op, total_us, detect_us, 0);
             }
             
-            record_throughput(total_us);
+            oplog.record_throughput(total_us as u64);
+            report_throughput(oplog);
         }
     }
+
+    // 🔥 FAST PATH: Non-blocking publish
+    if let (Some(mgr), false) = (sync_mgr, publish.is_empty()) {
+        let _ = mgr.publish_batch(publish);
+    }
     
     // 🎨 Display operation details AFTER timing (doesn't count in performance metrics)
     print_operation_diff(&ops_for_diff);
@@ -509,10 +697,34 @@ fn process_path(
         return Ok(());
     }
 
-    if !should_track(path) || path.is_dir() {
+    if !should_track(path) {
+        return Ok(());
+    }
+
+    // Symlinks are recorded by target, never by following them
+    if process_symlink(path, actor_id, start, oplog, sync_mgr)? || path.is_dir() {
         return Ok(());
     }
 
+    if process_similar_rename(path, actor_id, start, oplog, sync_mgr)? {
+        return Ok(());
+    }
+
+    // Formatters and save hooks rewrite files without changing them; a
+    // known file whose mtime and size are untouched isn't read at all
+    if PREV_STATE.contains_key(path) && !file_definitely_changed(path) {
+        return Ok(());
+    }
+
+    // A file rewritten in a tight loop is recorded in periodic snapshots
+    if throttle(path) {
+        return Ok(());
+    }
+
+    if SETTLE_WRITES.load(Ordering::Relaxed) {
+        wait_for_settle(path);
+    }
+
     // ⚡⚡ DUAL-WATCHER SYSTEM ⚡⚡
     
     // Step 1: ULTRA-FAST MODE (<20µs) - Zero-syscall rapid change detection
@@ -540,6 +752,399 @@ fn process_path(
     Ok(())
 }
 
+/// Record changes made while the watcher wasn't running: every live file
+/// under `repo_root` is compared with its recorded state and anything that
+/// drifted is recorded as if it had just been edited. The recorded state
+/// also seeds each file's snapshot, so the first edit after startup diffs
+/// against it. Returns how many files had drifted.
+pub fn reconcile(
+    repo_root: &Path,
+    oplog: &OperationLog,
+    actor_id: &str,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<usize> {
+    let db = oplog.db().as_ref();
+    let now = chrono::Utc::now();
+    let mut drifted = 0;
+    for (file_path, content) in crate::storage::tree::files_at(db, now)? {
+        let path = PathBuf::from(&file_path);
+        if !path.starts_with(repo_root) || !should_track(&path) {
+            continue;
+        }
+        let start = Instant::now();
+        let recorded = RecordedState {
+            content,
+            mode: reconstruct::mode_at(db, &file_path, now)?,
+            symlink_target: reconstruct::symlink_target_at(db, &file_path, now)?,
+            binary_hash: reconstruct::binary_hash_at(db, &file_path, now)?,
+        };
+        let ops = catch_up_operations(&path, recorded, actor_id)?;
+        if !ops.is_empty() {
+            drifted += 1;
+            if ops.iter().any(|op| matches!(op.op_type, OperationType::FileDelete)) {
+                oplog.clear_head(&file_path);
+            }
+            emit_operations(ops, start.elapsed().as_micros(), start, oplog, sync_mgr)?;
+        }
+    }
+    Ok(drifted)
+}
+
+/// A file as the log last recorded it.
+struct RecordedState {
+    content: String,
+    mode: Option<u32>,
+    symlink_target: Option<String>,
+    binary_hash: Option<String>,
+}
+
+/// Operations taking `path` from its recorded state to what's on disk.
+fn catch_up_operations(path: &Path, recorded: RecordedState, actor_id: &str) -> Result<Vec<Operation>> {
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return Ok(vec![Operation::new(
+            path_to_string(path),
+            OperationType::FileDelete,
+            actor_id.to_string(),
+        )]);
+    };
+    if meta.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    if meta.file_type().is_symlink() {
+        let target = std::fs::read_link(path)?.to_string_lossy().into_owned();
+        SYMLINK_TARGETS.insert(path.to_path_buf(), target.clone());
+        let op_type = match recorded.symlink_target {
+            Some(old_target) if old_target == target => return Ok(Vec::new()),
+            Some(old_target) => OperationType::SymlinkChange {
+                old_target,
+                new_target: target,
+            },
+            None => OperationType::SymlinkCreate { target },
+        };
+        return Ok(vec![Operation::new(path_to_string(path), op_type, actor_id.to_string())]);
+    }
+
+    let mut snapshot = build_snapshot_fast(&recorded.content);
+    let current_mode = file_mode(path);
+    snapshot.mode = recorded.mode.or(current_mode);
+    update_prev_state(path, Some(snapshot));
+    if let Some(hash) = recorded.binary_hash {
+        BINARY_HASHES.insert(path.to_path_buf(), hash);
+    }
+
+    let mut ops = detect_operations_with_content(path, actor_id, None, true)?.ops;
+    if let (Some(old_mode), Some(new_mode)) = (recorded.mode, current_mode)
+        && old_mode != new_mode
+    {
+        if let Some(mut snapshot) = PREV_STATE.get_mut(path) {
+            snapshot.mode = Some(new_mode);
+        }
+        ops.push(Operation::new(
+            path_to_string(path),
+            OperationType::ChmodChange { old_mode, new_mode },
+            actor_id.to_string(),
+        ));
+    }
+    Ok(ops)
+}
+
+/// Record a permission change for a tracked file whose mode differs from the
+/// one in its snapshot. Files without a snapshot yet pick up their mode when
+/// their content is first detected.
+fn process_mode_change(
+    path: &Path,
+    actor_id: &str,
+    start: Instant,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<()> {
+    if is_temp_path(path) || !should_track(path) {
+        return Ok(());
+    }
+    let Some(new_mode) = file_mode(path) else {
+        return Ok(());
+    };
+
+    let detect_start = Instant::now();
+    let old_mode = match PREV_STATE.get_mut(path) {
+        Some(mut snapshot) => snapshot.mode.replace(new_mode),
+        None => return Ok(()),
+    };
+
+    if let Some(old_mode) = old_mode.filter(|&old_mode| old_mode != new_mode) {
+        let op = Operation::new(
+            path_to_string(path),
+            OperationType::ChmodChange { old_mode, new_mode },
+            actor_id.to_string(),
+        );
+        let detect_us = detect_start.elapsed().as_micros();
+        emit_operations(vec![op], detect_us, start, oplog, sync_mgr)?;
+    }
+
+    Ok(())
+}
+
+/// Record a symlink's target instead of the content it points at. Returns
+/// `false` when `path` is not a symlink so regular detection can run.
+fn process_symlink(
+    path: &Path,
+    actor_id: &str,
+    start: Instant,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<bool> {
+    let is_symlink = std::fs::symlink_metadata(path)
+        .map(|meta| meta.file_type().is_symlink())
+        .unwrap_or(false);
+    if !is_symlink {
+        SYMLINK_TARGETS.remove(path);
+        return Ok(false);
+    }
+    let Ok(target) = std::fs::read_link(path) else {
+        return Ok(true);
+    };
+
+    let detect_start = Instant::now();
+    let target = target.to_string_lossy().into_owned();
+    let op_type = match SYMLINK_TARGETS.insert(path.to_path_buf(), target.clone()) {
+        Some(old_target) if old_target == target => return Ok(true),
+        Some(old_target) => OperationType::SymlinkChange {
+            old_target,
+            new_target: target,
+        },
+        None => OperationType::SymlinkCreate { target },
+    };
+
+    // A regular file replaced by a link no longer has content to diff against
+    clear_prev_state(path);
+    let op = Operation::new(path_to_string(path), op_type, actor_id.to_string());
+    let detect_us = detect_start.elapsed().as_micros();
+    emit_operations(vec![op], detect_us, start, oplog, sync_mgr)?;
+    Ok(true)
+}
+
+/// Mode a new file gets under the usual umask. New files with any other mode
+/// get a `ChmodChange` from it.
+const DEFAULT_FILE_MODE: u32 = 0o644;
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .ok()
+        .map(|meta| meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// Hold back the delete of a tracked file so a create arriving within the
+/// atomic-save window can claim it as an edit or a rename. Returns `false`
+/// when there is no snapshot to compare against and the delete should be
+/// recorded now.
+fn defer_delete(path: &Path) -> bool {
+    if atomic_save_window().is_zero() || SYMLINK_TARGETS.contains_key(path) {
+        return false;
+    }
+    let Some((_, snapshot)) = PREV_STATE.remove(path) else {
+        return false;
+    };
+    cache_warmer::FILE_POOL.write().remove(path);
+    FILE_HASH_CACHE.remove(path);
+    PENDING_DELETES.insert(path.to_path_buf(), (snapshot, Instant::now()));
+    true
+}
+
+/// Record held-back deletes whose window has passed, or all of them when
+/// `all` is set.
+fn flush_pending_deletes(
+    all: bool,
+    actor_id: &str,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<()> {
+    let expired: Vec<PathBuf> = PENDING_DELETES
+        .iter()
+        .filter(|entry| all || entry.value().1.elapsed() >= atomic_save_window())
+        .map(|entry| entry.key().clone())
+        .collect();
+    for path in expired {
+        flush_pending_delete(&path, actor_id, oplog, sync_mgr)?;
+    }
+    Ok(())
+}
+
+/// Cancel the held-back delete of `path`, which was re-created: its old
+/// snapshot comes back so the new content is recorded as an edit.
+fn restore_pending_delete(path: &Path) -> bool {
+    match PENDING_DELETES.remove(path) {
+        Some((_, (snapshot, _))) => {
+            PREV_STATE.insert(path.to_path_buf(), snapshot);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether a change to `path` should wait for the file's next snapshot
+/// rather than be recorded now, because the file is over its rate limit.
+fn throttle(path: &Path) -> bool {
+    if let Some(mut entry) = THROTTLED.get_mut(path) {
+        entry.1 = true;
+        return true;
+    }
+    let limit = MAX_FILE_OPS_PER_SEC.load(Ordering::Relaxed) as u32;
+    if limit == 0 {
+        return false;
+    }
+    let allowed = RATE_LIMITS
+        .entry(path.to_path_buf())
+        .or_insert_with(|| TokenBucket::new(limit, limit))
+        .try_acquire();
+    if allowed {
+        return false;
+    }
+    THROTTLED.insert(path.to_path_buf(), (Instant::now(), true));
+    println!(
+        "{} {} changes more than {} times a second; recording it every {}s until it settles",
+        "🚦".bright_yellow(),
+        path_to_string(path).bright_cyan(),
+        limit,
+        THROTTLE_SNAPSHOT_INTERVAL.as_secs()
+    );
+    true
+}
+
+/// Record the changes throttled files accumulated since their last
+/// snapshot, for those whose interval has passed or for all of them when
+/// `all` is set. A file that didn't change for a whole interval is no longer
+/// throttled, and one whose budget has refilled no longer needs a bucket.
+fn flush_throttled(
+    all: bool,
+    actor_id: &str,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<()> {
+    RATE_LIMITS.retain(|_, bucket| !bucket.is_full());
+    let due: Vec<(PathBuf, bool)> = THROTTLED
+        .iter()
+        .filter(|entry| all || entry.value().0.elapsed() >= THROTTLE_SNAPSHOT_INTERVAL)
+        .map(|entry| (entry.key().clone(), entry.value().1))
+        .collect();
+    for (path, changed) in due {
+        if all || !changed {
+            THROTTLED.remove(&path);
+        } else {
+            THROTTLED.insert(path.clone(), (Instant::now(), false));
+        }
+        // A file deleted meanwhile had its delete recorded already
+        if !changed || !path.is_file() {
+            continue;
+        }
+        // Diffing against the last recorded state covers every change since
+        let start = Instant::now();
+        let report = detect_operations(&path, actor_id, true)?;
+        emit_operations(report.ops, report.timings.total_us, start, oplog, sync_mgr)?;
+    }
+    Ok(())
+}
+
+/// How long the event loop may wait for events before held-back deletes or
+/// throttled files are due, if it has any.
+fn next_flush_wait() -> Option<Duration> {
+    let deletes = (!PENDING_DELETES.is_empty()).then(atomic_save_window);
+    let snapshots = (!THROTTLED.is_empty()).then_some(THROTTLE_SNAPSHOT_INTERVAL);
+    deletes.into_iter().chain(snapshots).min()
+}
+
+fn flush_pending_delete(
+    path: &Path,
+    actor_id: &str,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<()> {
+    if PENDING_DELETES.remove(path).is_none() {
+        return Ok(());
+    }
+    let start = Instant::now();
+    oplog.clear_head(&path_to_string(path));
+    let op = Operation::new(path_to_string(path), OperationType::FileDelete, actor_id.to_string());
+    emit_operations(vec![op], start.elapsed().as_micros(), start, oplog, sync_mgr)
+}
+
+/// Take the held-back delete whose last snapshot is most similar to
+/// `content`, if any is similar enough to call `path` a rename of it.
+fn take_rename_source_by_content(path: &Path, content: &str) -> Option<(PathBuf, FileSnapshot)> {
+    if content.is_empty() {
+        return None;
+    }
+    let best = PENDING_DELETES
+        .iter()
+        .filter(|entry| entry.key() != path)
+        .filter_map(|entry| {
+            let score = content_similarity(&entry.value().0.content, content);
+            (score >= RENAME_SIMILARITY).then(|| (entry.key().clone(), score))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    PENDING_DELETES
+        .remove(&best.0)
+        .map(|(old_path, (snapshot, _))| (old_path, snapshot))
+}
+
+fn content_similarity(old: &str, new: &str) -> f32 {
+    if old == new {
+        return 1.0;
+    }
+    // Sizes this far apart can't reach the threshold; skip the diff
+    let (shorter, longer) = (old.len().min(new.len()), old.len().max(new.len()));
+    if (shorter as f32) < longer as f32 * RENAME_SIMILARITY {
+        return 0.0;
+    }
+    similar::TextDiff::from_lines(old, new).ratio()
+}
+
+/// Record a newly seen file as a rename when its content matches a file
+/// deleted moments ago, for editors that delete and re-create instead of
+/// renaming. Returns `false` when `path` should be detected as usual.
+fn process_similar_rename(
+    path: &Path,
+    actor_id: &str,
+    start: Instant,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<bool> {
+    // Delete + create of the same file is an atomic save, not a new file
+    if restore_pending_delete(path) || PENDING_DELETES.is_empty() || PREV_STATE.contains_key(path) {
+        return Ok(false);
+    }
+    let Ok(content) = read_file_fast(path) else {
+        return Ok(false);
+    };
+    let Some((old_path, snapshot)) = take_rename_source_by_content(path, &content) else {
+        return Ok(false);
+    };
+
+    let detect_start = Instant::now();
+    PREV_STATE.insert(path.to_path_buf(), snapshot);
+    oplog.move_head(&path_to_string(&old_path), path_to_string(path));
+    let mut ops = vec![Operation::new(
+        path_to_string(path),
+        OperationType::FileRename {
+            old_path: path_to_string(&old_path),
+            new_path: path_to_string(path),
+        },
+        actor_id.to_string(),
+    )];
+    // Edits made along with the rename diff against the old snapshot
+    ops.extend(detect_operations_with_content(path, actor_id, Some(content), false)?.ops);
+    let detect_us = detect_start.elapsed().as_micros();
+    emit_operations(ops, detect_us, start, oplog, sync_mgr)?;
+    Ok(true)
+}
+
 // 🔥 Deduplication helper: Skip if we just processed this file
 // 🚀 Deduplication now handled by file_definitely_changed() using metadata-only (<1µs)
 // No need for separate should_skip_duplicate function
@@ -553,11 +1158,22 @@ fn handle_rename_transition(
     sync_mgr: &Option<StdArc<SyncManager>>,
 ) -> Result<()> {
     remember_rename_source(None);
+    if is_real_dir(&new_path) {
+        return handle_dir_rename(old_path, new_path, actor_id, start, oplog, sync_mgr);
+    }
     move_cached_content(&old_path, &new_path);
 
     let old_is_temp = is_temp_path(&old_path);
     let new_is_temp = is_temp_path(&new_path);
 
+    // A temp file moved over a target deleted moments ago saves the target;
+    // any other file renamed over it replaces it
+    if old_is_temp && !new_is_temp {
+        restore_pending_delete(&new_path);
+    } else {
+        flush_pending_delete(&new_path, actor_id, oplog, sync_mgr)?;
+    }
+
     if old_is_temp && !new_is_temp {
         if !should_track(&new_path) {
             TEMP_CONTENT_CACHE.remove(&new_path);
@@ -581,17 +1197,17 @@ fn handle_rename_transition(
 
     if old_trackable && new_trackable {
         move_prev_state_entry(&old_path, &new_path);
-        move_last_operation_entry(&old_path, &new_path);
+        oplog.move_head(&path_to_string(&old_path), path_to_string(&new_path));
 
         let detect_start = Instant::now();
-        let op = register_operation(Operation::new(
+        let op = Operation::new(
             path_to_string(&new_path),
             OperationType::FileRename {
                 old_path: path_to_string(&old_path),
                 new_path: path_to_string(&new_path),
             },
             actor_id.to_string(),
-        ));
+        );
         let detect_us = detect_start.elapsed().as_micros();
         emit_operations(vec![op], detect_us, start, oplog, sync_mgr)?;
     } else if !old_trackable && new_trackable {
@@ -599,13 +1215,14 @@ fn handle_rename_transition(
     } else if old_trackable && !new_trackable {
         TEMP_CONTENT_CACHE.remove(&old_path);
         clear_prev_state(&old_path);
-        clear_last_operation_entry(&old_path);
+        SYMLINK_TARGETS.remove(&old_path);
+        oplog.clear_head(&path_to_string(&old_path));
         let detect_start = Instant::now();
-        let op = register_operation(Operation::new(
+        let op = Operation::new(
             path_to_string(&old_path),
             OperationType::FileDelete,
             actor_id.to_string(),
-        ));
+        );
         let detect_us = detect_start.elapsed().as_micros();
         emit_operations(vec![op], detect_us, start, oplog, sync_mgr)?;
     }
@@ -613,6 +1230,113 @@ fn handle_rename_transition(
     Ok(())
 }
 
+/// A directory, not a symlink to one.
+fn is_real_dir(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.is_dir())
+}
+
+fn process_dir_create(
+    path: &Path,
+    actor_id: &str,
+    start: Instant,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<()> {
+    if is_temp_path(path) || !should_track(path) || !DIRECTORIES.insert(path.to_path_buf()) {
+        return Ok(());
+    }
+    let op = Operation::new(path_to_string(path), OperationType::DirCreate, actor_id.to_string());
+    emit_operations(vec![op], 0, start, oplog, sync_mgr)
+}
+
+fn process_dir_delete(
+    path: &Path,
+    actor_id: &str,
+    start: Instant,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<()> {
+    DIRECTORIES.retain(|dir| !dir.starts_with(path));
+    if is_temp_path(path) || !should_track(path) {
+        return Ok(());
+    }
+    oplog.clear_head(&path_to_string(path));
+    let op = Operation::new(path_to_string(path), OperationType::DirDelete, actor_id.to_string());
+    emit_operations(vec![op], 0, start, oplog, sync_mgr)
+}
+
+/// Record a moved directory, and a rename for every tracked file inside it
+/// so their histories follow. A directory moved into or out of the tracked
+/// tree is recorded as created or deleted instead.
+fn handle_dir_rename(
+    old_path: PathBuf,
+    new_path: PathBuf,
+    actor_id: &str,
+    start: Instant,
+    oplog: &OperationLog,
+    sync_mgr: &Option<StdArc<SyncManager>>,
+) -> Result<()> {
+    let (old_trackable, new_trackable) = (should_track(&old_path), should_track(&new_path));
+    if !old_trackable {
+        return process_dir_create(&new_path, actor_id, start, oplog, sync_mgr);
+    }
+    if !new_trackable {
+        return process_dir_delete(&old_path, actor_id, start, oplog, sync_mgr);
+    }
+
+    let moved_dirs: Vec<PathBuf> = DIRECTORIES
+        .iter()
+        .filter(|dir| dir.starts_with(&old_path))
+        .map(|dir| dir.clone())
+        .collect();
+    for dir in moved_dirs {
+        DIRECTORIES.remove(&dir);
+        if let Ok(rest) = dir.strip_prefix(&old_path) {
+            DIRECTORIES.insert(new_path.join(rest));
+        }
+    }
+    DIRECTORIES.insert(new_path.clone());
+
+    let detect_start = Instant::now();
+    oplog.move_head(&path_to_string(&old_path), path_to_string(&new_path));
+    let mut ops = vec![Operation::new(
+        path_to_string(&new_path),
+        OperationType::DirRename {
+            old_path: path_to_string(&old_path),
+            new_path: path_to_string(&new_path),
+        },
+        actor_id.to_string(),
+    )];
+
+    let mut files: Vec<PathBuf> = PREV_STATE
+        .iter()
+        .map(|entry| entry.key().clone())
+        .chain(SYMLINK_TARGETS.iter().map(|entry| entry.key().clone()))
+        .filter(|file| file.starts_with(&old_path))
+        .collect();
+    files.sort();
+    files.dedup();
+    for old_file in files {
+        let Ok(rest) = old_file.strip_prefix(&old_path) else {
+            continue;
+        };
+        let new_file = new_path.join(rest);
+        move_prev_state_entry(&old_file, &new_file);
+        oplog.move_head(&path_to_string(&old_file), path_to_string(&new_file));
+        ops.push(Operation::new(
+            path_to_string(&new_file),
+            OperationType::FileRename {
+                old_path: path_to_string(&old_file),
+                new_path: path_to_string(&new_file),
+            },
+            actor_id.to_string(),
+        ));
+    }
+
+    let detect_us = detect_start.elapsed().as_micros();
+    emit_operations(ops, detect_us, start, oplog, sync_mgr)
+}
+
 #[inline(always)]
 fn detect_operations(path: &Path, actor_id: &str, suppress_logging: bool) -> Result<DetectionReport> {
     detect_operations_with_content(path, actor_id, None, suppress_logging)
@@ -644,25 +1368,42 @@ fn detect_operations_with_content(
             Some(text) => text,
             None => match read_file_fast(path) {
                 Ok(text) => text,
-                Err(_) => return Ok(finalize_detection(path, detect_start, timings, Vec::new(), suppress_logging)),
+                Err(err) => {
+                    let ops = binary_change(path, actor_id, &err).into_iter().collect();
+                    return Ok(finalize_detection(path, detect_start, timings, ops, suppress_logging));
+                }
             }
         };
 
-        if new_content.len() as u64 > MAX_TRACKED_FILE_BYTES {
+        if new_content.len() as u64 > max_tracked_bytes() {
             return Ok(finalize_detection(path, detect_start, timings, Vec::new(), suppress_logging));
         }
 
         // 🚀 Zero-copy snapshot building
-        let snapshot = build_snapshot_fast(&new_content);
+        let mut snapshot = build_snapshot_fast(&new_content);
+        let mode = file_mode(path);
+        snapshot.mode = mode;
         update_prev_state(path, Some(snapshot));
-        let op = register_operation(Operation::new(
+        let mut ops = vec![Operation::new(
             path_to_string(path),
             OperationType::FileCreate {
                 content: new_content,
             },
             actor_id.to_string(),
-        ));
-        return Ok(finalize_detection(path, detect_start, timings, vec![op], suppress_logging));
+        )];
+        // A mode other than the usual one is recorded right away, so a new
+        // script keeps its executable bit through replay and export
+        if let Some(new_mode) = mode.filter(|&mode| mode != DEFAULT_FILE_MODE) {
+            ops.push(Operation::new(
+                path_to_string(path),
+                OperationType::ChmodChange {
+                    old_mode: DEFAULT_FILE_MODE,
+                    new_mode,
+                },
+                actor_id.to_string(),
+            ));
+        }
+        return Ok(finalize_detection(path, detect_start, timings, ops, suppress_logging));
     }
 
     let mut prev = previous_snapshot.unwrap();
@@ -672,7 +1413,10 @@ fn detect_operations_with_content(
         Some(text) => text,
         None => match read_file_fast(path) {
             Ok(text) => text,
-            Err(_) => return Ok(finalize_detection(path, detect_start, timings, Vec::new(), suppress_logging)),
+            Err(err) => {
+                let ops = binary_change(path, actor_id, &err).into_iter().collect();
+                return Ok(finalize_detection(path, detect_start, timings, ops, suppress_logging));
+            }
         }
     };
     
@@ -693,7 +1437,7 @@ fn detect_operations_with_content(
             let (line, col) = line_col_from_snapshot(&prev, char_offset);
             let lamport = GLOBAL_CLOCK.tick();
             let appended_len = appended.chars().count();
-            let op = register_operation(Operation::new(
+            let op = Operation::new(
                 path_to_string(path),
                 OperationType::Insert {
                     position: Position::new(
@@ -707,7 +1451,7 @@ fn detect_operations_with_content(
                     length: appended_len,
                 },
                 actor_id.to_string(),
-            ));
+            );
             extend_snapshot(&mut prev, &appended);
             update_prev_state(path, Some(prev));
             return Ok(finalize_detection(path, detect_start, timings, vec![op], suppress_logging));
@@ -715,11 +1459,13 @@ fn detect_operations_with_content(
     }
     
     // 🚀 Full diff path - build new snapshot with optimizations
-    let new_snapshot = build_snapshot_fast(&new_content);
-    if new_snapshot.byte_len > MAX_TRACKED_FILE_BYTES {
+    let mut new_snapshot = build_snapshot_fast(&new_content);
+    if new_snapshot.byte_len > max_tracked_bytes() {
         update_prev_state(path, None);
         return Ok(finalize_detection(path, detect_start, timings, Vec::new(), suppress_logging));
     }
+    // Mode changes are tracked separately from content
+    new_snapshot.mode = prev.mode;
 
     let ops = fast_diff_ops(path, actor_id, &prev, &new_snapshot);
     update_prev_state(path, Some(new_snapshot));
@@ -778,6 +1524,7 @@ fn build_snapshot_fast(content: &str) -> FileSnapshot {
         char_len,
         char_to_byte,
         line_starts,
+        mode: None,
     }
 }
 
@@ -961,7 +1708,7 @@ fn fast_diff_ops(
     };
 
     let op = Operation::new(path_to_string(path), op_type, actor_id.to_string());
-    vec![register_operation(op)]
+    vec![op]
 }
 
 // Ensure char_to_byte mapping exists (build it if empty for ASCII)
@@ -971,9 +1718,19 @@ fn ensure_char_mapping(snapshot: &FileSnapshot) -> std::borrow::Cow<'_, FileSnap
         return std::borrow::Cow::Borrowed(snapshot);
     }
     
-    // Build mapping for ASCII content
+    // Build mapping for ASCII content. A snapshot that started out ASCII and
+    // was extended with multi-byte text also lands here, so check again.
     let mut new_snap = snapshot.clone();
-    new_snap.char_to_byte = (0..=snapshot.content.len()).collect();
+    new_snap.char_to_byte = if snapshot.content.is_ascii() {
+        (0..=snapshot.content.len()).collect()
+    } else {
+        snapshot
+            .content
+            .char_indices()
+            .map(|(byte_idx, _)| byte_idx)
+            .chain(std::iter::once(snapshot.content.len()))
+            .collect()
+    };
     std::borrow::Cow::Owned(new_snap)
 }
 
@@ -1044,6 +1801,20 @@ fn compute_change_range_fast(
         0
     };
     
+    // The byte-level prefix/suffix can stop inside a multi-byte character
+    // (e.g. "é" → "è" share their first byte). Widen the changed range to
+    // whole characters; the shared bytes are identical in both texts, so a
+    // boundary in one is a boundary in the other.
+    let old_text = old_snapshot.content.as_str();
+    let mut common_prefix_bytes = common_prefix_bytes;
+    while !old_text.is_char_boundary(common_prefix_bytes) {
+        common_prefix_bytes -= 1;
+    }
+    let mut common_suffix_bytes = common_suffix_bytes;
+    while !old_text.is_char_boundary(old_bytes.len() - common_suffix_bytes) {
+        common_suffix_bytes -= 1;
+    }
+
     // 🔥 FIX: Handle ASCII fast path (char_to_byte is empty for ASCII)
     let old_is_ascii = old_snapshot.char_to_byte.is_empty();
     let new_is_ascii = new_snapshot.char_to_byte.is_empty();
@@ -1086,7 +1857,7 @@ fn compute_change_range_fast(
 }
 
 fn should_track(path: &Path) -> bool {
-    is_trackable(path)
+    is_trackable(path) && filter::allows(path)
 }
 
 fn print_operation(op: &Operation, total_us: u128, detect_us: u128, _queue_us: u128) {
@@ -1179,7 +1950,10 @@ fn print_operation(op: &Operation, total_us: u128, detect_us: u128, _queue_us: u
         OperationType::FileDelete => {
             ("DELETE".bright_red(), "file".to_string())
         }
-        OperationType::FileRename { old_path, new_path } => {
+        OperationType::DirCreate => ("MKDIR".bright_green(), "directory".to_string()),
+        OperationType::DirDelete => ("RMDIR".bright_red(), "directory".to_string()),
+        OperationType::FileRename { old_path, new_path }
+        | OperationType::DirRename { old_path, new_path } => {
             let old_name = std::path::Path::new(old_path)
                 .file_name()
                 .and_then(|n| n.to_str())
@@ -1193,6 +1967,23 @@ fn print_operation(op: &Operation, total_us: u128, detect_us: u128, _queue_us: u
                 format!("{} → {}", old_name.red(), new_name.green()),
             )
         }
+        OperationType::ChmodChange { old_mode, new_mode } => (
+            "CHMOD".bright_magenta(),
+            format!("{:o} → {:o}", old_mode, new_mode),
+        ),
+        OperationType::SymlinkCreate { target } => {
+            ("SYMLINK".bright_cyan(), format!("→ {}", target))
+        }
+        OperationType::SymlinkChange {
+            old_target,
+            new_target,
+        } => (
+            "SYMLINK".bright_cyan(),
+            format!("{} → {}", old_target.red(), new_target.green()),
+        ),
+        OperationType::BinaryModify { size, .. } => {
+            ("BINARY".bright_magenta(), format!("{} bytes", size))
+        }
     };
 
     println!(
@@ -1217,6 +2008,17 @@ fn truncate_with_preview(s: &str, max_len: usize) -> String {
 }
 
 // 🎨 Display operation details showing what changed (displayed AFTER timing)
+/// Print an operation a peer made, marked with who made it.
+pub fn print_remote_operation(op: &Operation, peer: &str) {
+    println!(
+        "{} {} {}",
+        "⇣".bright_magenta(),
+        peer.bright_magenta(),
+        op.file_path.bright_black()
+    );
+    print_operation_diff(std::slice::from_ref(op));
+}
+
 fn print_operation_diff(ops: &[Operation]) {
     use colored::*;
     
@@ -1290,7 +2092,14 @@ fn print_operation_diff(ops: &[Operation]) {
             OperationType::FileDelete => {
                 println!("  {} {}", "🗑️ ".bright_red(), filename.bright_cyan());
             }
-            OperationType::FileRename { old_path, new_path } => {
+            OperationType::DirCreate => {
+                println!("  {} {}/", "📁".bright_green(), filename.bright_cyan());
+            }
+            OperationType::DirDelete => {
+                println!("  {} {}/", "🗑️ ".bright_red(), filename.bright_cyan());
+            }
+            OperationType::FileRename { old_path, new_path }
+            | OperationType::DirRename { old_path, new_path } => {
                 let old_name = std::path::Path::new(old_path)
                     .file_name()
                     .and_then(|n| n.to_str())
@@ -1305,22 +2114,43 @@ fn print_operation_diff(ops: &[Operation]) {
                     new_name.bright_cyan()
                 );
             }
+            OperationType::ChmodChange { old_mode, new_mode } => {
+                println!("  {} {} {:o} → {:o}",
+                    "🔐".bright_magenta(),
+                    filename.bright_cyan(),
+                    old_mode,
+                    new_mode
+                );
+            }
+            OperationType::SymlinkCreate { target } => {
+                println!("  {} {} → {}",
+                    "🔗".bright_cyan(),
+                    filename.bright_cyan(),
+                    target.green()
+                );
+            }
+            OperationType::SymlinkChange { old_target, new_target } => {
+                println!("  {} {} {} → {}",
+                    "🔗".bright_cyan(),
+                    filename.bright_cyan(),
+                    old_target.red(),
+                    new_target.green()
+                );
+            }
+            OperationType::BinaryModify { new_hash: hash, size, .. } => {
+                println!("  {} {} ({} bytes, {})",
+                    "▦".bright_magenta(),
+                    filename.bright_cyan(),
+                    size,
+                    hash.bright_black()
+                );
+            }
         }
     }
 }
 
-fn register_operation(op: Operation) -> Operation {
-    let file_path = op.file_path.clone();
-    let op = if let Some(prev) = LAST_OPERATION.get(&file_path) {
-        op.with_parents(vec![*prev])
-    } else {
-        op
-    };
-    LAST_OPERATION.insert(file_path, op.id);
-    op
-}
-
 fn update_prev_state(path: &Path, snapshot: Option<FileSnapshot>) {
+    BINARY_HASHES.remove(path);
     // 🚀 OPTIMIZATION: Lazy cleanup to reduce overhead (dx-style inspired)
     if let Some(state) = snapshot {
         PREV_STATE.insert(path.to_path_buf(), state);
@@ -1336,11 +2166,21 @@ fn update_prev_state(path: &Path, snapshot: Option<FileSnapshot>) {
 
 fn clear_prev_state(path: &Path) {
     update_prev_state(path, None);
+    FILE_HASH_CACHE.remove(path);
+    RATE_LIMITS.remove(path);
     // Also remove from file pool
     cache_warmer::FILE_POOL.write().remove(path);
 }
 
 fn move_prev_state_entry(old: &Path, new: &Path) {
+    if let Some((_, target)) = SYMLINK_TARGETS.remove(old) {
+        SYMLINK_TARGETS.insert(new.to_path_buf(), target);
+    }
+
+    if let Some((_, metadata)) = FILE_HASH_CACHE.remove(old) {
+        FILE_HASH_CACHE.insert(new.to_path_buf(), metadata);
+    }
+
     let old_key = old.to_path_buf();
     if let Some((_, snapshot)) = PREV_STATE.remove(&old_key) {
         PREV_STATE.insert(new.to_path_buf(), snapshot);
@@ -1354,21 +2194,6 @@ fn move_prev_state_entry(old: &Path, new: &Path) {
     }
 }
 
-fn move_last_operation_entry(old: &Path, new: &Path) {
-    let old_key = path_key(old);
-    if let Some((_, op_id)) = LAST_OPERATION.remove(&old_key) {
-        LAST_OPERATION.insert(path_key(new), op_id);
-    }
-}
-
-fn clear_last_operation_entry(path: &Path) {
-    LAST_OPERATION.remove(&path_key(path));
-}
-
-fn path_key(path: &Path) -> String {
-    path_to_string(path)
-}
-
 fn is_temp_path(path: &Path) -> bool {
     if let Some(name) = path.file_name().and_then(|s| s.to_str()) {
         let lower = name.to_ascii_lowercase();
@@ -1444,26 +2269,101 @@ fn take_rename_source() -> Option<PathBuf> {
     }
 }
 
+// A file caught mid-write can be briefly invalid UTF-8: re-read it this many
+// times, this far apart, before treating it as binary
+const UTF8_RETRIES: usize = 3;
+const UTF8_RETRY_DELAY: Duration = Duration::from_millis(3);
+
+/// Error from `read_file_fast` for content that stayed invalid UTF-8.
+#[derive(Debug)]
+struct BinaryContent {
+    size: u64,
+    hash: String,
+    // Only read when binary tracking is on
+    content: Option<Vec<u8>>,
+}
+
+impl std::fmt::Display for BinaryContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "binary content ({} bytes)", self.size)
+    }
+}
+
+impl std::error::Error for BinaryContent {}
+
 fn read_file_fast(path: &Path) -> Result<String> {
+    let mut retries = 0;
+    loop {
+        let mmap = map_file_fast(path)?;
+        match std::str::from_utf8(&mmap) {
+            Ok(content) => return Ok(content.to_string()),
+            Err(_) if retries < UTF8_RETRIES => {
+                retries += 1;
+                std::thread::sleep(UTF8_RETRY_DELAY);
+            }
+            Err(_) => {
+                return Err(BinaryContent {
+                    size: mmap.len() as u64,
+                    hash: crate::storage::hash::digest(&mmap)?,
+                    content: TRACK_BINARY.load(Ordering::Relaxed).then(|| mmap.to_vec()),
+                }
+                .into());
+            }
+        }
+    }
+}
+
+fn map_file_fast(path: &Path) -> Result<Mmap> {
     // FAST PATH: Try pooled file handle with read lock (no allocation)
     {
         let pool = cache_warmer::FILE_POOL.read();
         if let Some(file_arc) = pool.get(path) {
             // Reuse existing file handle with mmap
-            let mmap = unsafe { Mmap::map(file_arc.as_ref())? };
-            return Ok(std::str::from_utf8(&mmap)?.to_string());
+            return Ok(unsafe { Mmap::map(file_arc.as_ref())? });
         }
     } // Drop read lock before acquiring write lock
     
     // SLOW PATH: Not in pool - open it, add to pool, and read
     let file = File::open(path)?;
     let mmap = unsafe { Mmap::map(&file)? };
-    let content = std::str::from_utf8(&mmap)?.to_string();
     
     // Add to pool for next time (write lock held briefly)
     cache_warmer::FILE_POOL.write().insert(path.to_path_buf(), Arc::new(file));
     
-    Ok(content)
+    Ok(mmap)
+}
+
+// 🧱 Hash of the binary content last recorded per file, so an unchanged
+// binary file isn't recorded again; cleared once the file is text again
+static BINARY_HASHES: Lazy<DashMap<PathBuf, String>> = Lazy::new(DashMap::new);
+
+// Binary content by hash, waiting for its operation to be recorded
+static PENDING_BLOBS: Lazy<DashMap<String, Vec<u8>>> = Lazy::new(DashMap::new);
+
+/// A `BinaryModify` for a file `read_file_fast` found to hold binary
+/// content, or `None` if `err` is any other read error or that content was
+/// already recorded. With binary tracking on, the content is held until the
+/// operation is recorded and then stored as a blob.
+fn binary_change(path: &Path, actor_id: &str, err: &anyhow::Error) -> Option<Operation> {
+    let binary = err.downcast_ref::<BinaryContent>()?;
+    if binary.size > max_tracked_bytes()
+        || BINARY_HASHES.get(path).is_some_and(|hash| *hash == binary.hash)
+    {
+        return None;
+    }
+    let old_hash = BINARY_HASHES.insert(path.to_path_buf(), binary.hash.clone());
+    if let Some(content) = &binary.content {
+        PENDING_BLOBS.insert(binary.hash.clone(), content.clone());
+    }
+    Some(Operation::new(
+        path_to_string(path),
+        OperationType::BinaryModify {
+            old_hash,
+            new_hash: binary.hash.clone(),
+            size: binary.size,
+        },
+        actor_id.to_string(),
+    ))
 }
 
 fn is_trackable(path: &Path) -> bool {
@@ -1483,11 +2383,341 @@ fn is_trackable(path: &Path) -> bool {
     true
 }
 
+/// Entry points for the `detection` benchmark. Not part of the public API,
+/// and unused by the binary, which compiles this module too.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+#[allow(dead_code)]
+pub mod bench {
+    use super::*;
+
+    /// Make `content` the last known state of `path`, as if it had just been
+    /// detected.
+    pub fn prime(path: &Path, content: &str) {
+        update_prev_state(path, Some(build_snapshot_fast(content)));
+    }
+
+    /// Run detection for `path` against `new_content` and return the number of
+    /// operations produced.
+    pub fn detect(path: &Path, actor_id: &str, new_content: String) -> usize {
+        detect_operations_with_content(path, actor_id, Some(new_content), true)
+            .map(|report| report.ops.len())
+            .unwrap_or(0)
+    }
+
+    /// Build a snapshot and return its character length.
+    pub fn snapshot(content: &str) -> usize {
+        build_snapshot_fast(content).char_len
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::is_trackable;
+    use super::*;
+    use crate::crdt::document::apply_to_rope;
+    use proptest::prelude::*;
+    use ropey::Rope;
     use std::path::Path;
 
+    #[test]
+    fn similar_content_claims_a_pending_delete_as_rename() {
+        let old_path = PathBuf::from("/rename-detect/src/old_name.rs");
+        let original = "fn main() {\n    println!(\"rename detection\");\n}\n\nfn helper() {}\n";
+        PENDING_DELETES.insert(
+            old_path.clone(),
+            (build_snapshot_fast(original), Instant::now()),
+        );
+
+        let unrelated = Path::new("/rename-detect/src/other.rs");
+        assert!(take_rename_source_by_content(unrelated, "something else entirely\n").is_none());
+
+        let edited = original.replace("fn helper() {}", "fn helper() { todo!() }");
+        let new_path = Path::new("/rename-detect/src/new_name.rs");
+        let (source, snapshot) = take_rename_source_by_content(new_path, &edited).unwrap();
+        assert_eq!(source, old_path);
+        assert_eq!(snapshot.content, original);
+        assert!(!PENDING_DELETES.contains_key(&old_path));
+    }
+
+    #[test]
+    fn recreating_a_pending_delete_restores_its_snapshot() {
+        let path = PathBuf::from("/atomic-save/notes.md");
+        PENDING_DELETES.insert(path.clone(), (build_snapshot_fast("draft\n"), Instant::now()));
+
+        assert!(restore_pending_delete(&path));
+        assert!(!PENDING_DELETES.contains_key(&path));
+        assert_eq!(PREV_STATE.get(&path).unwrap().content, "draft\n");
+        assert!(!restore_pending_delete(&path));
+        PREV_STATE.remove(&path);
+    }
+
+    #[test]
+    fn unchanged_metadata_skips_rewrites() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("formatted.rs");
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+
+        assert!(file_definitely_changed(&path));
+        assert!(!file_definitely_changed(&path));
+
+        std::fs::write(&path, "fn main() { }\n").unwrap();
+        assert!(file_definitely_changed(&path));
+        assert!(!file_definitely_changed(&path));
+    }
+
+    #[test]
+    fn settling_gives_up_on_a_file_that_never_stops_changing() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("saving.txt");
+        std::fs::write(&path, "part").unwrap();
+        assert!(wait_for_settle(&path));
+
+        let done = StdArc::new(AtomicBool::new(false));
+        let writer = {
+            let (path, done) = (path.clone(), done.clone());
+            std::thread::spawn(move || {
+                let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+                while !done.load(Ordering::Relaxed) {
+                    std::io::Write::write_all(&mut file, b".").unwrap();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+        assert!(!wait_for_settle(&path));
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn binary_content_records_each_new_hash_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("image.bin");
+        std::fs::write(&path, [0xff, 0xfe, 0x00, 0x01]).unwrap();
+
+        let ops = detect_operations_with_content(&path, "actor", None, true).unwrap().ops;
+        assert_eq!(ops.len(), 1);
+        match &ops[0].op_type {
+            OperationType::BinaryModify {
+                old_hash,
+                new_hash,
+                size,
+            } => {
+                assert_eq!(*size, 4);
+                assert_eq!(*new_hash, crate::storage::hash::digest(&[0xff, 0xfe, 0x00, 0x01]).unwrap());
+                assert!(old_hash.is_none());
+            }
+            other => panic!("expected a binary change, got {other:?}"),
+        }
+        assert!(detect_operations_with_content(&path, "actor", None, true).unwrap().ops.is_empty());
+
+        std::fs::write(&path, [0xff, 0xfe, 0x00, 0x02]).unwrap();
+        let ops = detect_operations_with_content(&path, "actor", None, true).unwrap().ops;
+        match &ops[0].op_type {
+            OperationType::BinaryModify { old_hash, .. } => assert_eq!(
+                old_hash.as_deref(),
+                Some(crate::storage::hash::digest(&[0xff, 0xfe, 0x00, 0x01]).unwrap().as_str())
+            ),
+            other => panic!("expected a binary change, got {other:?}"),
+        }
+
+        // Back to text, the file is tracked by content again
+        std::fs::write(&path, "text\n").unwrap();
+        let ops = detect_operations_with_content(&path, "actor", None, true).unwrap().ops;
+        assert!(matches!(ops[0].op_type, OperationType::FileCreate { .. }));
+        clear_prev_state(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn new_files_record_a_mode_other_than_the_default() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let script = temp_dir.path().join("run.sh");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let plain = temp_dir.path().join("notes.txt");
+        std::fs::write(&plain, "notes\n").unwrap();
+        std::fs::set_permissions(&plain, std::fs::Permissions::from_mode(DEFAULT_FILE_MODE))
+            .unwrap();
+
+        let ops = detect_operations_with_content(&script, "actor", None, true).unwrap().ops;
+        assert_eq!(ops.len(), 2);
+        assert!(matches!(ops[0].op_type, OperationType::FileCreate { .. }));
+        assert!(matches!(
+            ops[1].op_type,
+            OperationType::ChmodChange {
+                old_mode: DEFAULT_FILE_MODE,
+                new_mode: 0o755
+            }
+        ));
+
+        let ops = detect_operations_with_content(&plain, "actor", None, true).unwrap().ops;
+        assert_eq!(ops.len(), 1);
+        clear_prev_state(&script);
+        clear_prev_state(&plain);
+    }
+
+    #[test]
+    fn moving_a_directory_renames_the_files_inside_it() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let forge_dir = temp_dir.path().join("forge");
+        std::fs::create_dir(&forge_dir).unwrap();
+        let db = crate::storage::Database::new(&forge_dir).unwrap();
+        db.initialize().unwrap();
+        let oplog = OperationLog::new(Arc::new(db));
+
+        let old_dir = temp_dir.path().join("scaffold");
+        let new_dir = temp_dir.path().join("templates");
+        std::fs::create_dir(&old_dir).unwrap();
+        process_dir_create(&old_dir, "actor", Instant::now(), &oplog, &None).unwrap();
+        PREV_STATE.insert(old_dir.join("a.txt"), build_snapshot_fast("a"));
+        std::fs::rename(&old_dir, &new_dir).unwrap();
+
+        handle_rename_transition(
+            old_dir.clone(),
+            new_dir.clone(),
+            "actor",
+            Instant::now(),
+            &oplog,
+            &None,
+        )
+        .unwrap();
+
+        assert!(DIRECTORIES.contains(&new_dir) && !DIRECTORIES.contains(&old_dir));
+        assert!(PREV_STATE.contains_key(&new_dir.join("a.txt")));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let ops = loop {
+            let ops = oplog
+                .db()
+                .query_operations(&crate::storage::QueryFilter::default())
+                .unwrap();
+            if ops.len() == 3 || Instant::now() > deadline {
+                break ops;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let names: Vec<_> = ops.iter().map(|op| op.op_type.name()).collect();
+        assert!(names.contains(&"DirCreate"));
+        assert!(names.contains(&"DirRename"));
+        let file_rename = ops
+            .iter()
+            .find(|op| matches!(op.op_type, OperationType::FileRename { .. }))
+            .unwrap();
+        assert_eq!(file_rename.file_path, path_to_string(&new_dir.join("a.txt")));
+
+        process_dir_delete(&new_dir, "actor", Instant::now(), &oplog, &None).unwrap();
+        assert!(!DIRECTORIES.contains(&new_dir));
+        clear_prev_state(&new_dir.join("a.txt"));
+    }
+
+    #[test]
+    fn reconcile_records_edits_made_while_stopped() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        let forge_dir = temp_dir.path().join("forge");
+        std::fs::create_dir(&repo).unwrap();
+        std::fs::create_dir(&forge_dir).unwrap();
+        let db = crate::storage::Database::new(&forge_dir).unwrap();
+        db.initialize().unwrap();
+        let edited = repo.join("edited.txt");
+        let kept = repo.join("kept.txt");
+        let removed = repo.join("removed.txt");
+        for (path, content) in [(&edited, "one\n"), (&kept, "keep\n"), (&removed, "gone\n")] {
+            let mut op = Operation::new(
+                path_to_string(path),
+                OperationType::FileCreate {
+                    content: content.into(),
+                },
+                "actor".into(),
+            );
+            op.timestamp -= chrono::Duration::seconds(1);
+            db.store_operation(&op).unwrap();
+        }
+        std::fs::write(&edited, "one\ntwo\n").unwrap();
+        std::fs::write(&kept, "keep\n").unwrap();
+        let oplog = OperationLog::new(Arc::new(db));
+
+        assert_eq!(reconcile(&repo, &oplog, "actor", &None).unwrap(), 2);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let ops = loop {
+            let ops = oplog
+                .db()
+                .query_operations(&crate::storage::QueryFilter::default())
+                .unwrap();
+            if ops.len() == 5 || Instant::now() > deadline {
+                break ops;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let caught_up: Vec<_> = ops[3..]
+            .iter()
+            .map(|op| (op.file_path.clone(), op.op_type.name()))
+            .collect();
+        assert!(caught_up.contains(&(path_to_string(&edited), "Insert")));
+        assert!(caught_up.contains(&(path_to_string(&removed), "FileDelete")));
+        assert_eq!(PREV_STATE.get(&kept).unwrap().content, "keep\n");
+        for path in [&edited, &kept] {
+            clear_prev_state(path);
+        }
+    }
+
+    #[test]
+    fn throttled_changes_are_recorded_as_one_snapshot() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db = crate::storage::Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+        let oplog = OperationLog::new(Arc::new(db));
+        let path = temp_dir.path().join("fought-over.rs");
+        update_prev_state(&path, Some(build_snapshot_fast("fn main() {}\n")));
+
+        // Changes made while throttled are only marked, then recorded together
+        let overdue = Instant::now() - THROTTLE_SNAPSHOT_INTERVAL;
+        THROTTLED.insert(path.clone(), (overdue, false));
+        for content in ["fn main(){}\n", "fn main() { }\n", "fn main() { run() }\n"] {
+            std::fs::write(&path, content).unwrap();
+            assert!(throttle(&path));
+        }
+        flush_throttled(false, "actor", &oplog, &None).unwrap();
+        oplog.flush();
+
+        let ops = oplog.db().query_operations(&crate::storage::QueryFilter::default()).unwrap();
+        assert_eq!(ops.len(), 1);
+        let mut rope = Rope::from_str("fn main() {}\n");
+        apply_to_rope(&mut rope, &ops[0]);
+        assert_eq!(rope.to_string(), "fn main() { run() }\n");
+
+        // A whole quiet interval ends the throttling
+        assert!(!THROTTLED.get(&path).unwrap().1);
+        THROTTLED.insert(path.clone(), (overdue, false));
+        flush_throttled(false, "actor", &oplog, &None).unwrap();
+        assert!(!THROTTLED.contains_key(&path));
+        clear_prev_state(&path);
+    }
+
+    #[test]
+    fn rate_limits_are_dropped_once_refilled_or_deleted() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db = crate::storage::Database::new(temp_dir.path()).unwrap();
+        db.initialize().unwrap();
+        let oplog = OperationLog::new(Arc::new(db));
+        let quiet = temp_dir.path().join("quiet.rs");
+        let deleted = temp_dir.path().join("deleted.rs");
+
+        for path in [&quiet, &deleted] {
+            assert!(!throttle(path));
+            assert!(RATE_LIMITS.contains_key(path));
+        }
+        clear_prev_state(&deleted);
+        assert!(!RATE_LIMITS.contains_key(&deleted));
+
+        // A bucket refills a token every 1/limit seconds
+        std::thread::sleep(Duration::from_millis(100));
+        flush_throttled(false, "actor", &oplog, &None).unwrap();
+        assert!(!RATE_LIMITS.contains_key(&quiet));
+    }
+
     #[test]
     fn ignores_git_directory_unix_style() {
         assert!(!is_trackable(Path::new("/repo/.git/config")));
@@ -1522,4 +2752,110 @@ mod tests {
     fn tracks_nested_source_file() {
         assert!(is_trackable(Path::new("C:\\repo\\src\\lib.rs")));
     }
+
+    fn apply_diff(old: &str, new: &str) -> String {
+        let ops = fast_diff_ops(
+            Path::new("/prop/diff.txt"),
+            "prop",
+            &build_snapshot_fast(old),
+            &build_snapshot_fast(new),
+        );
+        assert!(ops.len() <= 1);
+        let mut rope = Rope::from_str(old);
+        for op in &ops {
+            apply_to_rope(&mut rope, op);
+        }
+        rope.to_string()
+    }
+
+    const TEXT: &str = "[ab \t\né漢🚀]{0,40}";
+
+    proptest! {
+        #[test]
+        fn fast_diff_round_trips(old in TEXT, new in TEXT) {
+            prop_assert_eq!(apply_diff(&old, &new), new);
+        }
+
+        #[test]
+        fn fast_diff_round_trips_local_edits(
+            old in TEXT,
+            at in any::<prop::sample::Index>(),
+            removed in 0usize..4,
+            inserted in TEXT,
+        ) {
+            let chars: Vec<char> = old.chars().collect();
+            let start = at.index(chars.len() + 1);
+            let end = (start + removed).min(chars.len());
+            let new: String = chars[..start]
+                .iter()
+                .copied()
+                .chain(inserted.chars())
+                .chain(chars[end..].iter().copied())
+                .collect();
+            prop_assert_eq!(apply_diff(&old, &new), new);
+        }
+
+        #[test]
+        fn fast_diff_round_trips_extended_snapshots(old in TEXT, appended in TEXT, new in TEXT) {
+            // Snapshots grown by the append fast path must diff like fresh ones.
+            let mut snapshot = build_snapshot_fast(&old);
+            extend_snapshot(&mut snapshot, &appended);
+            let ops = fast_diff_ops(
+                Path::new("/prop/diff.txt"),
+                "prop",
+                &snapshot,
+                &build_snapshot_fast(&new),
+            );
+            let mut rope = Rope::from_str(&snapshot.content);
+            for op in &ops {
+                apply_to_rope(&mut rope, op);
+            }
+            prop_assert_eq!(rope.to_string(), new);
+        }
+    }
+
+    #[test]
+    fn fast_diff_round_trips_large_files() {
+        // Files over 8KB take the parallel prefix scan.
+        let old = "let café = \"🚀\";\n".repeat(800);
+        for at in [0, 1, 4_095, 4_096, 7_000, old.chars().count()] {
+            let mut new: String = old.chars().take(at).collect();
+            new.push('è');
+            new.extend(old.chars().skip(at + 1));
+            assert_eq!(apply_diff(&old, &new), new, "edit at char {at}");
+        }
+    }
+
+    /// Regression guard for the append fast path. Timing depends on the build,
+    /// so CI runs it explicitly: `cargo test --release -- --ignored`.
+    /// `FORGE_APPEND_BUDGET_US` overrides the per-detection budget.
+    #[test]
+    #[ignore = "timing-sensitive; run in release mode"]
+    fn append_detection_stays_within_budget() {
+        let budget_us: u128 = std::env::var("FORGE_APPEND_BUDGET_US")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(TARGET_PERFORMANCE_US * 5);
+
+        let path = Path::new("/bench/append_budget.rs");
+        let mut content = "fn main() {}\n".repeat(2_000);
+        update_prev_state(path, Some(build_snapshot_fast(&content)));
+
+        let mut samples: Vec<u128> = (0..201)
+            .map(|i| {
+                content.push_str(&format!("// {i}\n"));
+                let report =
+                    detect_operations_with_content(path, "bench", Some(content.clone()), true)
+                        .unwrap();
+                assert_eq!(report.ops.len(), 1);
+                report.timings.total_us
+            })
+            .collect();
+        samples.sort_unstable();
+        let median = samples[samples.len() / 2];
+        assert!(
+            median <= budget_us,
+            "append detection median {median}µs exceeds {budget_us}µs budget"
+        );
+    }
 }
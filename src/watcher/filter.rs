@@ -0,0 +1,82 @@
+use anyhow::Result;
+use ignore::overrides::{Override, OverrideBuilder};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use std::path::Path;
+
+/// Include/exclude globs scoping what the watcher tracks. Globs use gitignore
+/// syntax relative to the repository root; when any include glob is given,
+/// files matching none of them are skipped.
+#[derive(Clone)]
+pub struct PathFilter {
+    overrides: Override,
+}
+
+impl PathFilter {
+    pub fn new(root: &Path, include: &[String], exclude: &[String]) -> Result<Self> {
+        let mut builder = OverrideBuilder::new(root);
+        for glob in include {
+            builder.add(glob)?;
+        }
+        for glob in exclude {
+            builder.add(&format!("!{glob}"))?;
+        }
+        Ok(Self {
+            overrides: builder.build()?,
+        })
+    }
+
+    /// Whether the file at `path` should be tracked.
+    pub fn allows(&self, path: &Path) -> bool {
+        !self.overrides.matched(path, false).is_ignore()
+    }
+
+    pub fn overrides(&self) -> &Override {
+        &self.overrides
+    }
+}
+
+static ACTIVE_FILTER: Lazy<RwLock<Option<PathFilter>>> = Lazy::new(|| RwLock::new(None));
+
+/// Scope the watcher in this process to `filter`.
+pub fn set_active(filter: PathFilter) {
+    *ACTIVE_FILTER.write() = Some(filter);
+}
+
+pub fn active() -> Option<PathFilter> {
+    ACTIVE_FILTER.read().clone()
+}
+
+/// Whether the active filter (if any) lets `path` through.
+pub fn allows(path: &Path) -> bool {
+    ACTIVE_FILTER
+        .read()
+        .as_ref()
+        .is_none_or(|filter| filter.allows(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        let owned = |globs: &[&str]| globs.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+        PathFilter::new(Path::new("/repo"), &owned(include), &owned(exclude)).unwrap()
+    }
+
+    #[test]
+    fn includes_scope_and_excludes_carve_out() {
+        let filter = filter(&["src/**", "docs/**"], &["docs/generated/**"]);
+        assert!(filter.allows(Path::new("/repo/src/main.rs")));
+        assert!(filter.allows(Path::new("/repo/docs/guide.md")));
+        assert!(!filter.allows(Path::new("/repo/docs/generated/api.md")));
+        assert!(!filter.allows(Path::new("/repo/vendor/lib.rs")));
+    }
+
+    #[test]
+    fn exclude_only_keeps_everything_else() {
+        let filter = filter(&[], &["*.log"]);
+        assert!(filter.allows(Path::new("/repo/src/main.rs")));
+        assert!(!filter.allows(Path::new("/repo/build/output.log")));
+    }
+}
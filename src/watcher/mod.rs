@@ -1,43 +1,120 @@
 pub mod detector;
 pub mod cache_warmer;
+pub mod filter;
+pub mod run_state;
 
 use anyhow::Result;
 use colored::*;
-use sha2::{Digest, Sha256};
 use std::path::PathBuf;
+use std::time::Duration;
 
-use crate::storage::{Database, OperationLog};
-use crate::sync::{SyncManager, remote::connect_peer};
+use crate::storage::config::WatchProfile;
+use crate::storage::oplog::WriteBuffer;
+use crate::storage::{Database, ForgeConfig, OperationLog, hash};
+use crate::sync::{SyncManager, discovery, remote::connect_peer};
 use std::sync::Arc as StdArc;
+use tokio::sync::broadcast::error::RecvError;
 
+/// Optional watcher behaviour toggled from the CLI.
+#[derive(Debug, Clone, Default)]
+pub struct WatchOptions {
+    pub sync: bool,
+    pub peers: Vec<String>,
+    /// Connect to LAN servers advertising the same repo id.
+    pub discover: bool,
+    /// Only track paths matching these globs (relative to the repo root).
+    pub include: Vec<String>,
+    /// Never track paths matching these globs.
+    pub exclude: Vec<String>,
+    /// Detect and print operations without recording or syncing them.
+    pub dry_run: bool,
+    /// Also print operations received from peers, not just local ones.
+    pub show_remote: bool,
+    /// Periodically print how fast operations are being recorded.
+    pub stats: bool,
+}
+
+#[allow(dead_code)]
 pub async fn watch(path: PathBuf, enable_sync: bool, peers: Vec<String>) -> Result<()> {
-    // println!("{}", "Initializing operation tracker...".bright_cyan());
+    watch_with_options(
+        path,
+        WatchOptions {
+            sync: enable_sync,
+            peers,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+pub async fn watch_with_options(path: PathBuf, options: WatchOptions) -> Result<()> {
+    let WatchOptions {
+        sync: enable_sync,
+        peers,
+        discover,
+        include,
+        mut exclude,
+        dry_run,
+        show_remote,
+        stats,
+    } = options;
 
     let repo_root = path.canonicalize().unwrap_or_else(|_| path.clone());
     let forge_dir = repo_root.join(".dx/forge");
+    let config = ForgeConfig::load(&forge_dir)?;
+    hash::set_active(config.hash_algorithm);
+
+    // A dry run never talks to peers
+    let enable_sync = (enable_sync || discover || config.real_time_sync) && !dry_run;
+    let discover = discover && !dry_run;
+
+    // println!("{}", "Initializing operation tracker...".bright_cyan());
+
+    exclude.extend(config.ignore_globs.iter().cloned());
+    if !include.is_empty() || !exclude.is_empty() {
+        filter::set_active(filter::PathFilter::new(&repo_root, &include, &exclude)?);
+    }
+
+    detector::set_max_tracked_bytes(config.max_tracked_bytes);
+    detector::set_atomic_save_window(Duration::from_millis(config.atomic_save_window_ms));
+    let debounce_ms = match config.debounce_ms {
+        0 => config.watch_profile.default_debounce_ms(),
+        ms => ms,
+    };
+    detector::set_debounce(Duration::from_millis(debounce_ms));
+    detector::set_settle_writes(config.watch_profile == WatchProfile::Accuracy);
+    detector::set_track_binary(config.track_binary);
+    detector::set_max_file_ops_per_sec(config.max_file_ops_per_sec);
 
     let db = Database::new(&forge_dir)?;
     db.initialize()?;
-    let oplog = std::sync::Arc::new(OperationLog::new(std::sync::Arc::new(db)));
-
-    // Load config
-    let config_raw = tokio::fs::read_to_string(forge_dir.join("config.json")).await?;
-    let config: serde_json::Value = serde_json::from_str(&config_raw)?;
-    let actor_id = config["actor_id"].as_str().unwrap().to_string();
-    let repo_id = config["repo_id"]
-        .as_str()
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| {
-            let mut hasher = Sha256::new();
+    let oplog = std::sync::Arc::new(OperationLog::with_write_buffer(
+        std::sync::Arc::new(db),
+        config.checkpoint_interval,
+        WriteBuffer {
+            flush_interval: Duration::from_millis(config.flush_interval_ms),
+            max_buffered: config.max_buffered_ops,
+        },
+    ));
+    let identity = config.identity();
+    if identity.actor_id.is_empty() {
+        anyhow::bail!("config.json has no actor_id; run `forge init` to create one");
+    }
+    oplog.db().record_actor(&identity)?;
+    let actor_id = identity.actor_id.clone();
+    let repo_id = match config.repo_id.clone() {
+        Some(repo_id) => repo_id,
+        None => {
             let path_string = repo_root.to_string_lossy().into_owned();
-            hasher.update(path_string.as_bytes());
-            format!("local-{:x}", hasher.finalize())
-        });
+            format!("local-{}", hash::digest(path_string.as_bytes())?)
+        }
+    };
 
     println!(
-        "{} Actor ID: {}",
+        "{} Actor: {} ({})",
         "→".bright_blue(),
-        actor_id.bright_yellow()
+        identity.display_name().bright_yellow(),
+        actor_id.bright_black()
     );
     println!(
         "{} Sync: {}",
@@ -49,8 +126,34 @@ pub async fn watch(path: PathBuf, enable_sync: bool, peers: Vec<String>) -> Resu
         }
     );
 
+    detector::set_dry_run(dry_run);
+    detector::set_show_stats(stats);
+    if dry_run {
+        println!(
+            "{} Dry run: operations are detected but not recorded or synced",
+            "→".bright_blue()
+        );
+    }
+
+    let heartbeat = run_state::Heartbeat::start(&forge_dir, enable_sync);
+
+    // Write out buffered operations before Ctrl-C ends the process
+    tokio::spawn({
+        let oplog = oplog.clone();
+        let heartbeat = heartbeat.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                oplog.flush();
+                heartbeat.stop();
+                std::process::exit(130);
+            }
+        }
+    });
+
     let sync_mgr = if enable_sync {
-        Some(StdArc::new(SyncManager::new()))
+        Some(StdArc::new(SyncManager::with_capacity(
+            config.sync_channel_capacity,
+        )))
     } else {
         None
     };
@@ -58,7 +161,7 @@ pub async fn watch(path: PathBuf, enable_sync: bool, peers: Vec<String>) -> Resu
     // If remote peers provided, connect and bridge
     if let (Some(mgr), true) = (&sync_mgr, !peers.is_empty()) {
         for url in peers {
-            let _ = connect_peer(
+            let connected = connect_peer(
                 &url,
                 actor_id.clone(),
                 repo_id.clone(),
@@ -66,6 +169,9 @@ pub async fn watch(path: PathBuf, enable_sync: bool, peers: Vec<String>) -> Resu
                 oplog.clone(),
             )
             .await;
+            if connected.is_ok() {
+                heartbeat.add_peer(&url);
+            }
             println!(
                 "{} Connected peer {}",
                 "↔".bright_blue(),
@@ -74,6 +180,60 @@ pub async fn watch(path: PathBuf, enable_sync: bool, peers: Vec<String>) -> Resu
         }
     }
 
+    // Watching goes on without discovery if it can't start
+    let found = match (&sync_mgr, discover) {
+        (Some(_), true) => match discovery::discover(repo_id.clone()) {
+            Ok(found) => Some(found),
+            Err(err) => {
+                println!("{} LAN discovery unavailable: {}", "⚠️".bright_yellow(), err);
+                None
+            }
+        },
+        _ => None,
+    };
+    if let (Some(mgr), Some(mut found)) = (&sync_mgr, found) {
+        println!(
+            "{} Discovering LAN peers for repo {}",
+            "→".bright_blue(),
+            repo_id.bright_yellow()
+        );
+        let actor_id = actor_id.clone();
+        let repo_id = repo_id.clone();
+        let mgr = mgr.as_ref().clone();
+        let oplog = oplog.clone();
+        let heartbeat = heartbeat.clone();
+        tokio::spawn(async move {
+            while let Some(url) = found.recv().await {
+                let connected = connect_peer(
+                    &url,
+                    actor_id.clone(),
+                    repo_id.clone(),
+                    mgr.clone(),
+                    oplog.clone(),
+                )
+                .await;
+                if connected.is_ok() {
+                    heartbeat.add_peer(&url);
+                    println!(
+                        "{} Discovered peer {}",
+                        "↔".bright_blue(),
+                        url.bright_yellow()
+                    );
+                }
+            }
+        });
+    }
+
+    if show_remote {
+        match &sync_mgr {
+            Some(mgr) => print_remote_operations(mgr, actor_id.clone(), oplog.clone()),
+            None => println!(
+                "{} --show-remote has no effect without sync",
+                "⚠️".bright_yellow()
+            ),
+        }
+    }
+
     // Warm OS page cache with all trackable files
     // Wait for cache warming to complete before starting watcher
     // This ensures all subsequent reads are <100µs
@@ -83,7 +243,53 @@ pub async fn watch(path: PathBuf, enable_sync: bool, peers: Vec<String>) -> Resu
     })
     .await??;
 
-    detector::start_watching(repo_root, oplog, actor_id, repo_id, sync_mgr).await?;
+    if config.reconcile_on_start {
+        let drifted = tokio::task::spawn_blocking({
+            let repo_root = repo_root.clone();
+            let oplog = oplog.clone();
+            let actor_id = actor_id.clone();
+            let sync_mgr = sync_mgr.clone();
+            move || detector::reconcile(&repo_root, &oplog, &actor_id, &sync_mgr)
+        })
+        .await??;
+        if drifted > 0 {
+            println!(
+                "{} Caught up on {} file(s) changed while the watcher was off",
+                "→".bright_blue(),
+                drifted.to_string().bright_yellow()
+            );
+        }
+    }
+
+    let writer = oplog.clone();
+    let result = detector::start_watching(repo_root, oplog, actor_id, repo_id, sync_mgr).await;
+    writer.flush();
+    heartbeat.stop();
+    result
+}
 
-    Ok(())
+/// Print operations arriving from peers as they are applied. Local ones are
+/// printed by the detector, so they are skipped here.
+fn print_remote_operations(sync_mgr: &SyncManager, actor_id: String, oplog: StdArc<OperationLog>) {
+    let mut ops = sync_mgr.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let received = match ops.recv().await {
+                Ok(op) => vec![op],
+                // Print what was missed from the log rather than skip it
+                Err(RecvError::Lagged(_)) => ops.catch_up(&oplog).await.unwrap_or_default(),
+                Err(RecvError::Closed) => break,
+            };
+            for op in received {
+                if op.actor_id == actor_id {
+                    continue;
+                }
+                let peer = match oplog.db().actor(&op.actor_id) {
+                    Ok(Some(identity)) => identity.display_name(),
+                    _ => op.actor_id.clone(),
+                };
+                detector::print_remote_operation(&op, &peer);
+            }
+        }
+    });
 }
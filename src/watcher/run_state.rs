@@ -0,0 +1,105 @@
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// File inside the forge directory describing the running watcher.
+pub const RUN_STATE_FILE: &str = "watcher.json";
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a running watcher reports about itself. The watcher rewrites the file
+/// on every heartbeat, so a stale heartbeat means it is no longer running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunState {
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub heartbeat_at: DateTime<Utc>,
+    pub sync: bool,
+    pub peers: Vec<String>,
+}
+
+impl RunState {
+    pub fn is_alive(&self) -> bool {
+        let stale_after = chrono::Duration::from_std(HEARTBEAT_INTERVAL * 3).unwrap_or_default();
+        Utc::now() - self.heartbeat_at < stale_after
+    }
+}
+
+/// Read the run state left by a watcher, if any.
+pub fn read(forge_dir: &Path) -> Option<RunState> {
+    let raw = std::fs::read_to_string(forge_dir.join(RUN_STATE_FILE)).ok()?;
+    serde_json::from_str(&raw).ok()
+}
+
+/// Periodically publishes the watcher's run state until dropped.
+pub struct Heartbeat {
+    path: PathBuf,
+    state: Mutex<RunState>,
+    task: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl Heartbeat {
+    pub fn start(forge_dir: &Path, sync: bool) -> Arc<Self> {
+        let now = Utc::now();
+        let heartbeat = Arc::new(Self {
+            path: forge_dir.join(RUN_STATE_FILE),
+            state: Mutex::new(RunState {
+                pid: std::process::id(),
+                started_at: now,
+                heartbeat_at: now,
+                sync,
+                peers: Vec::new(),
+            }),
+            task: Mutex::new(None),
+        });
+
+        let weak = Arc::downgrade(&heartbeat);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let Some(heartbeat) = weak.upgrade() else {
+                    break;
+                };
+                heartbeat.write();
+            }
+        });
+        *heartbeat.task.lock() = Some(task);
+        heartbeat
+    }
+
+    /// Record a peer the watcher is linked to.
+    pub fn add_peer(&self, url: &str) {
+        let mut state = self.state.lock();
+        if !state.peers.iter().any(|peer| peer == url) {
+            state.peers.push(url.to_string());
+        }
+        drop(state);
+        self.write();
+    }
+
+    fn write(&self) {
+        let mut state = self.state.lock();
+        state.heartbeat_at = Utc::now();
+        if let Ok(json) = serde_json::to_string_pretty(&*state) {
+            let _ = std::fs::write(&self.path, json);
+        }
+    }
+
+    /// Stop publishing and remove the run state file.
+    pub fn stop(&self) {
+        if let Some(task) = self.task.lock().take() {
+            task.abort();
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
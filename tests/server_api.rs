@@ -0,0 +1,287 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use forge::crdt::{Operation, OperationType, Position};
+use forge::storage::{self, Database, ForgeConfig};
+use forge::sync::SyncMessage;
+use futures::{SinkExt, StreamExt};
+use tempfile::TempDir;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+use uuid::Uuid;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn reserve_port() -> std::io::Result<u16> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+    let port = listener.local_addr()?.port();
+    drop(listener);
+    Ok(port)
+}
+
+/// Serve a freshly initialized repository and return its port.
+async fn serve(repo: &Path, options: forge::server::ServeOptions) -> Result<u16> {
+    serve_with(repo, options, |_| {}).await
+}
+
+/// Like [`serve`], with the repository's config adjusted first.
+async fn serve_with(
+    repo: &Path,
+    options: forge::server::ServeOptions,
+    configure: impl FnOnce(&mut ForgeConfig),
+) -> Result<u16> {
+    storage::init(repo).await?;
+    let forge_dir = repo.join(".dx/forge");
+    let mut config = ForgeConfig::load(&forge_dir)?;
+    configure(&mut config);
+    config.save(&forge_dir)?;
+    let port = reserve_port()?;
+    let repo = repo.to_path_buf();
+    tokio::spawn(async move {
+        let _ = forge::server::start_with_options(port, repo, options).await;
+    });
+    sleep(Duration::from_millis(150)).await;
+    Ok(port)
+}
+
+async fn connect(port: u16) -> Result<Socket> {
+    let (ws, _) = connect_async(format!("ws://127.0.0.1:{port}/ws")).await?;
+    Ok(ws)
+}
+
+async fn send(ws: &mut Socket, msg: &SyncMessage) -> Result<()> {
+    ws.send(Message::Text(serde_json::to_string(msg)?.into()))
+        .await?;
+    Ok(())
+}
+
+/// Read messages until the server acks `op_id`, returning every rejection
+/// seen on the way.
+async fn rejections_until_ack(ws: &mut Socket, op_id: Uuid) -> Result<Vec<String>> {
+    let mut rejected = Vec::new();
+    timeout(Duration::from_secs(5), async {
+        while let Some(msg) = ws.next().await {
+            let Message::Text(text) = msg? else { continue };
+            match serde_json::from_str::<SyncMessage>(&text) {
+                Ok(SyncMessage::Ack { op_id: acked }) if acked == op_id => return Ok(()),
+                Ok(SyncMessage::Rejected { reason, .. }) => rejected.push(reason),
+                _ => {}
+            }
+        }
+        Err(anyhow!("connection closed before the ack"))
+    })
+    .await??;
+    Ok(rejected)
+}
+
+fn create(path: &str, content: &str) -> Operation {
+    Operation::new(
+        path.into(),
+        OperationType::FileCreate {
+            content: content.into(),
+        },
+        "client".into(),
+    )
+}
+
+fn insert(path: &str, offset: usize, content: &str) -> Operation {
+    Operation::new(
+        path.into(),
+        OperationType::Insert {
+            position: Position::new(1, offset + 1, offset, "client".into(), 1),
+            content: content.into(),
+            length: content.chars().count(),
+        },
+        "client".into(),
+    )
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn only_malformed_operations_are_rejected() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let port = serve(temp_dir.path(), Default::default()).await?;
+    let mut ws = connect(port).await?;
+
+    // The second insert was made against a longer copy than the server has,
+    // as one concurrent with another peer's edits would be
+    let create = create("notes.txt", "hello");
+    let append = insert("notes.txt", 5, " world");
+    let concurrent = insert("notes.txt", 20, "!");
+    let last = concurrent.id;
+    send(&mut ws, &SyncMessage::batch(vec![create, append, concurrent])).await?;
+    let rejected = rejections_until_ack(&mut ws, last).await?;
+    assert!(rejected.is_empty(), "batch was rejected: {rejected:?}");
+
+    let empty = insert("notes.txt", 0, "");
+    let fine = insert("notes.txt", 0, ">");
+    let last = fine.id;
+    send(&mut ws, &SyncMessage::batch(vec![empty, fine])).await?;
+    let rejected = rejections_until_ack(&mut ws, last).await?;
+    assert_eq!(rejected.len(), 1, "{rejected:?}");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn batches_are_rate_limited_per_operation() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let port = serve_with(temp_dir.path(), Default::default(), |config| {
+        config.ws_ops_per_sec = 5;
+    })
+    .await?;
+    let mut ws = connect(port).await?;
+
+    // The first batch spends the whole burst allowance of 10 ops
+    let batch = |n: usize| -> Vec<Operation> {
+        (0..10)
+            .map(|i| create(&format!("batch{n}/{i}.txt"), "x"))
+            .collect()
+    };
+    let first = batch(1);
+    let last = first.last().unwrap().id;
+    send(&mut ws, &SyncMessage::batch(first)).await?;
+    rejections_until_ack(&mut ws, last).await?;
+
+    // so a second one straight after is dropped, however few frames it takes
+    let second = batch(2);
+    let last = second.last().unwrap().id;
+    send(&mut ws, &SyncMessage::batch(second)).await?;
+    let acked = timeout(Duration::from_secs(1), rejections_until_ack(&mut ws, last)).await;
+    assert!(acked.is_err(), "a second full batch got past the rate limit");
+    Ok(())
+}
+
+/// GET a file from the server's files API.
+async fn get_file(port: u16, path: &str, timestamp: Option<String>) -> Result<(u16, String)> {
+    let url = format!("http://127.0.0.1:{port}/api/v1/files/{path}");
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(timestamp) = timestamp {
+        request = request.query(&[("timestamp", timestamp)]);
+    }
+    let response = request.send().await?;
+    Ok((response.status().as_u16(), response.text().await?))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn serves_current_and_historical_file_content() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let port = serve(temp_dir.path(), Default::default()).await?;
+    let mut ws = connect(port).await?;
+
+    let create = create("notes.txt", "hello");
+    let created_at = create.timestamp;
+    let last = create.id;
+    send(&mut ws, &SyncMessage::operation(create)).await?;
+    rejections_until_ack(&mut ws, last).await?;
+    sleep(Duration::from_millis(20)).await;
+    let between = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    sleep(Duration::from_millis(20)).await;
+
+    let insert = insert("notes.txt", 5, " world");
+    let last = insert.id;
+    send(&mut ws, &SyncMessage::operation(insert)).await?;
+    rejections_until_ack(&mut ws, last).await?;
+
+    // Acked operations are written to the database in the background
+    let mut current = (0, String::new());
+    for _ in 0..50 {
+        current = get_file(port, "notes.txt", None).await?;
+        if current.1 == "hello world" {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(current, (200, "hello world".to_string()));
+
+    for path in ["notes.txt", "notes.txt/at"] {
+        let historical = get_file(port, path, Some(between.clone())).await?;
+        assert_eq!(historical, (200, "hello".to_string()), "GET {path}");
+    }
+    let before = (created_at - chrono::Duration::seconds(1))
+        .to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    assert_eq!(get_file(port, "notes.txt/at", Some(before)).await?.0, 404);
+
+    assert_eq!(get_file(port, "missing.txt", None).await?.0, 404);
+    assert_eq!(get_file(port, "missing.txt/at", None).await?.0, 404);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn oversized_messages_close_only_their_connection() -> Result<()> {
+    use forge::sync::messages::MAX_MESSAGE_BYTES;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+    let temp_dir = TempDir::new()?;
+    let port = serve(temp_dir.path(), Default::default()).await?;
+    let mut bystander = connect(port).await?;
+    let (mut write, mut read) = connect(port).await?.split();
+
+    // The server hangs up partway through, so the send itself may fail
+    let oversized = "x".repeat(MAX_MESSAGE_BYTES + 1);
+    tokio::spawn(async move { write.send(Message::Text(oversized.into())).await });
+    let close = timeout(Duration::from_secs(5), async {
+        while let Some(msg) = read.next().await {
+            if let Ok(Message::Close(frame)) = msg {
+                return frame;
+            }
+        }
+        None
+    })
+    .await?;
+    assert_eq!(close.map(|frame| frame.code), Some(CloseCode::Size));
+
+    // Other clients are still served
+    let create = create("still-here.txt", "ok");
+    let last = create.id;
+    send(&mut bystander, &SyncMessage::operation(create)).await?;
+    assert!(rejections_until_ack(&mut bystander, last).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn read_only_servers_reject_and_store_nothing() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let options = forge::server::ServeOptions {
+        read_only: true,
+        ..Default::default()
+    };
+    let port = serve(temp_dir.path(), options).await?;
+    let mut ws = connect(port).await?;
+
+    let single = create("one.txt", "x");
+    let batch = vec![create("two.txt", "x"), create("three.txt", "x")];
+    let mut pending: Vec<Uuid> = std::iter::once(&single)
+        .chain(&batch)
+        .map(|op| op.id)
+        .collect();
+    send(&mut ws, &SyncMessage::operation(single)).await?;
+    send(&mut ws, &SyncMessage::batch(batch)).await?;
+
+    timeout(Duration::from_secs(5), async {
+        while !pending.is_empty() {
+            let Some(msg) = ws.next().await else {
+                return Err(anyhow!("connection closed"));
+            };
+            let Message::Text(text) = msg? else { continue };
+            match serde_json::from_str::<SyncMessage>(&text) {
+                Ok(SyncMessage::Rejected { op_id, .. }) => pending.retain(|id| *id != op_id),
+                Ok(SyncMessage::Ack { op_id }) => return Err(anyhow!("{op_id} was acked")),
+                _ => {}
+            }
+        }
+        Ok(())
+    })
+    .await??;
+
+    let db = Database::new(&temp_dir.path().join(".dx/forge"))?;
+    assert_eq!(db.count_operations(&Default::default())?, 0);
+
+    let health: serde_json::Value = reqwest::get(format!("http://127.0.0.1:{port}/health"))
+        .await?
+        .json()
+        .await?;
+    assert_eq!(health["read_only"], true);
+    Ok(())
+}
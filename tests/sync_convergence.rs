@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use forge::storage::{self, Database, OperationLog, reconstruct};
+use forge::sync::protocol::Subscription;
+use forge::sync::{SyncManager, remote::connect_peer};
+use tempfile::TempDir;
+use tokio::sync::broadcast;
+use tokio::time::{sleep, timeout};
+
+fn reserve_port() -> std::io::Result<u16> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+    let port = listener.local_addr()?.port();
+    drop(listener);
+    Ok(port)
+}
+
+/// Wait for the peer to receive an operation for a file ending in `name` and
+/// return the path it was recorded under.
+async fn wait_for_path(
+    rx: &mut Subscription,
+    name: &str,
+) -> Result<String> {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            match rx.recv().await {
+                Ok(op) if op.file_path.ends_with(name) => break Ok(op.file_path.clone()),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(err) => break Err(anyhow!("peer sync channel closed: {err}")),
+            }
+        }
+    })
+    .await?
+}
+
+/// Poll the peer's database until `file_path` reconstructs to `expected`.
+async fn assert_converges(db: &Database, file_path: &str, expected: Option<&str>) -> Result<()> {
+    let mut last = None;
+    for _ in 0..100 {
+        last = reconstruct::reconstruct_at(db, file_path, Utc::now())?;
+        if last.as_deref() == expected {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    Err(anyhow!("peer copy of {file_path} is {last:?}, expected {expected:?}"))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn peer_reconstructs_identical_content() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let repo_path = temp_dir.path().to_path_buf();
+    storage::init(repo_path.as_path()).await?;
+
+    let port = reserve_port()?;
+    let url = format!("ws://127.0.0.1:{}/ws", port);
+
+    let server_handle = tokio::spawn({
+        let repo = repo_path.clone();
+        async move {
+            let _ = forge::server::start(port, repo).await;
+        }
+    });
+    sleep(Duration::from_millis(150)).await;
+
+    let watch_handle = tokio::spawn({
+        let repo = repo_path.clone();
+        let url = url.clone();
+        async move {
+            let _ = forge::watcher::watch(repo, true, vec![url]).await;
+        }
+    });
+    sleep(Duration::from_millis(250)).await;
+
+    // The peer keeps its own store outside the watched tree.
+    let peer_dir = TempDir::new()?;
+    let peer_db = Arc::new(Database::new(peer_dir.path())?);
+    peer_db.initialize()?;
+    let peer_oplog = Arc::new(OperationLog::new(peer_db.clone()));
+    let peer_sync = SyncManager::new();
+    let mut peer_rx = peer_sync.subscribe();
+    let peer_handle = connect_peer(
+        &url,
+        "test-peer".into(),
+        "test-repo".into(),
+        peer_sync.clone(),
+        peer_oplog.clone(),
+    )
+    .await?;
+
+    let source = repo_path.join("hello.txt");
+
+    // Create, then insert, delete and replace
+    tokio::fs::write(&source, "hello world\n").await?;
+    let hello = wait_for_path(&mut peer_rx, "hello.txt").await?;
+    assert_converges(&peer_db, &hello, Some("hello world\n")).await?;
+
+    for content in ["hello brave world\n", "hello world\n", "hello there\n"] {
+        tokio::fs::write(&source, content).await?;
+        assert_converges(&peer_db, &hello, Some(content)).await?;
+    }
+
+    // Rename
+    let renamed = repo_path.join("greeting.txt");
+    tokio::fs::rename(&source, &renamed).await?;
+    let greeting = wait_for_path(&mut peer_rx, "greeting.txt").await?;
+    assert_converges(&peer_db, &greeting, Some("hello there\n")).await?;
+
+    // Delete, then recreate
+    tokio::fs::remove_file(&renamed).await?;
+    assert_converges(&peer_db, &greeting, None).await?;
+
+    tokio::fs::write(&renamed, "fresh start\n").await?;
+    assert_converges(&peer_db, &greeting, Some("fresh start\n")).await?;
+
+    let on_disk = tokio::fs::read(&renamed).await?;
+    let on_peer =
+        reconstruct::reconstruct_at(&peer_db, &greeting, Utc::now())?.unwrap_or_default();
+    assert_eq!(on_peer.as_bytes(), on_disk.as_slice());
+
+    peer_handle.abort();
+    watch_handle.abort();
+    server_handle.abort();
+
+    Ok(())
+}
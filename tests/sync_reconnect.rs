@@ -0,0 +1,120 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use forge::crdt::{Operation, OperationType};
+use forge::storage::oplog::WriteBuffer;
+use forge::storage::{Database, OperationLog};
+use forge::sync::{SyncManager, SyncMessage, remote::connect_peer};
+use futures::{SinkExt, StreamExt};
+use tempfile::TempDir;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{WebSocketStream, accept_async};
+use uuid::Uuid;
+
+type Socket = WebSocketStream<TcpStream>;
+
+/// Accept WebSocket connections in the background. The peer's HTTP fetch of
+/// `/ops` lands on the same listener and is turned away.
+fn accept_all(listener: TcpListener) -> mpsc::UnboundedReceiver<Socket> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            if let Ok(ws) = accept_async(stream).await
+                && tx.send(ws).is_err()
+            {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+async fn next_connection(connections: &mut mpsc::UnboundedReceiver<Socket>) -> Result<Socket> {
+    timeout(Duration::from_secs(10), connections.recv())
+        .await?
+        .ok_or_else(|| anyhow!("listener stopped"))
+}
+
+/// Ids of the operations in the next message carrying any.
+async fn next_ops(ws: &mut Socket) -> Result<Vec<Uuid>> {
+    timeout(Duration::from_secs(5), async {
+        while let Some(msg) = ws.next().await {
+            let Message::Text(text) = msg? else { continue };
+            match serde_json::from_str::<SyncMessage>(&text)? {
+                SyncMessage::Operation { operation } => return Ok(vec![operation.id]),
+                SyncMessage::OperationBatch { operations } => {
+                    return Ok(operations.iter().map(|op| op.id).collect());
+                }
+                _ => {}
+            }
+        }
+        Err(anyhow!("peer disconnected"))
+    })
+    .await?
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn offline_edits_replay_and_only_acks_move_the_marker() -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let url = format!("ws://127.0.0.1:{}/ws", listener.local_addr()?.port());
+    let peer_id = url::Url::parse(&url)?.to_string();
+    let mut connections = accept_all(listener);
+
+    // Writes wait in memory, as with `flush_interval_ms` configured
+    let temp_dir = TempDir::new()?;
+    let db = Arc::new(Database::new(temp_dir.path())?);
+    db.initialize()?;
+    let buffer = WriteBuffer {
+        flush_interval: Duration::from_secs(60),
+        max_buffered: 1_000,
+    };
+    let oplog = Arc::new(OperationLog::with_write_buffer(db.clone(), 0, buffer));
+
+    let handle = connect_peer(
+        &url,
+        "me".into(),
+        "repo".into(),
+        SyncManager::new(),
+        oplog.clone(),
+    )
+    .await?;
+    let first = next_connection(&mut connections).await?;
+
+    // Edit while the link is down
+    drop(first);
+    let edit = oplog
+        .append_local(Operation::new(
+            "a.txt".into(),
+            OperationType::FileCreate {
+                content: "offline".into(),
+            },
+            "me".into(),
+        ))?
+        .unwrap();
+
+    // The reconnect replays it, but sending alone doesn't move the marker
+    let mut second = next_connection(&mut connections).await?;
+    assert_eq!(next_ops(&mut second).await?, [edit.id]);
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(db.sync_marker(&peer_id)?, None);
+
+    second
+        .send(Message::Text(serde_json::to_string(&SyncMessage::ack(edit.id))?.into()))
+        .await?;
+    let mut marker = None;
+    for _ in 0..50 {
+        marker = db.sync_marker(&peer_id)?;
+        if marker.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(marker.map(|marker| marker.op_id), Some(edit.id));
+
+    handle.abort();
+    Ok(())
+}
@@ -85,7 +85,7 @@ async fn init_watch_sync_workflow() -> Result<()> {
     }
 
     std::env::set_current_dir(&repo_path)?;
-    storage::time_travel(&tracked_file, None).await?;
+    storage::time_travel(&tracked_file, None, false).await?;
     std::env::set_current_dir(&original_dir)?;
 
     client_handle.abort();